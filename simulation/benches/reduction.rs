@@ -0,0 +1,126 @@
+// Throughput of the Monte Carlo reduction at 100k samples, before and after replacing the
+// per-sample mutex locking by the pairwise parallel reduction.
+// Run with `cargo bench --bench reduction`.
+use std::{
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use rayon::prelude::*;
+use simulation::{
+    analysis::{get_monte_carlo_partial_performance, get_performance},
+    formatting::{get_monte_carlo_progresses, scale_monte_carlo_progresses},
+    sampling::{
+        sample_monte_carlo_execution_timestamps, sample_monte_carlo_poem_executions, Samples,
+    },
+    simulations::{ADVERSARY_COUNT, HONEST_COUNT},
+    types::Chain,
+};
+
+const MONTE_CARLO: usize = 100_000;
+// The progresses are generated this many samples at a time, to bound the memory
+const CHUNK_SIZE: usize = 10_000;
+const REPETITIONS: usize = 5;
+
+// The reduction as it was: six sums each behind a mutex locked by every sample
+fn get_mutex_reduction(
+    honest_monte_carlo_progress: &[Vec<Chain>],
+    adversary_monte_carlo_progress: &[Vec<Chain>],
+) -> (Vec<f64>, [f64; 6]) {
+    let sums: [Arc<Mutex<f64>>; 6] = Default::default();
+    let k = (0..honest_monte_carlo_progress.len())
+        .into_par_iter()
+        .map(|i| {
+            let (k, f_work, f_height, max_work, max_height, adversary_work, adversary_height) =
+                get_performance(
+                    &honest_monte_carlo_progress[i],
+                    &adversary_monte_carlo_progress[i],
+                );
+            let mut locks = sums.each_ref().map(|sum| sum.lock().unwrap());
+            for (lock, value) in locks.iter_mut().zip([
+                f_work,
+                f_height,
+                max_work,
+                max_height,
+                adversary_work,
+                adversary_height,
+            ]) {
+                **lock += value;
+            }
+            k
+        })
+        .collect();
+    (k, sums.map(|sum| *sum.lock().unwrap()))
+}
+
+fn main() {
+    println!(
+        "{} samples, {} repetitions, {} threads",
+        MONTE_CARLO,
+        REPETITIONS,
+        rayon::current_num_threads()
+    );
+    let (beta, g, gamma) = (0.3, 2.0, 0.5);
+    let mut mutex_seconds = 0.0;
+    let mut pairwise_seconds = 0.0;
+    let mut mutex_sums = [0.0; 6];
+    let mut pairwise_sums = [0.0; 6];
+
+    for chunk in Samples::new(7, MONTE_CARLO).chunks(CHUNK_SIZE) {
+        let timestamps =
+            sample_monte_carlo_execution_timestamps::<HONEST_COUNT, ADVERSARY_COUNT>(chunk);
+        let (honest_blocks, adversary_blocks) =
+            sample_monte_carlo_poem_executions(&timestamps, chunk);
+        drop(timestamps);
+        let mut honest_progress = vec![Vec::with_capacity(HONEST_COUNT + 1); chunk.len()];
+        get_monte_carlo_progresses(&mut honest_progress, &honest_blocks, 1.);
+        let mut adversary_progress = vec![Vec::with_capacity(ADVERSARY_COUNT + 1); chunk.len()];
+        get_monte_carlo_progresses(&mut adversary_progress, &adversary_blocks, 0.);
+        let original_adversary_progress = adversary_progress.clone();
+        scale_monte_carlo_progresses(
+            &mut adversary_progress,
+            &original_adversary_progress,
+            g * beta / (1. - beta),
+            gamma,
+        );
+
+        for _ in 0..REPETITIONS {
+            let start = Instant::now();
+            let (_, sums) = get_mutex_reduction(&honest_progress, &adversary_progress);
+            mutex_seconds += start.elapsed().as_secs_f64();
+            mutex_sums = sums;
+
+            let start = Instant::now();
+            let partial = get_monte_carlo_partial_performance(
+                &honest_progress,
+                &adversary_progress,
+                None,
+                None,
+            );
+            pairwise_seconds += start.elapsed().as_secs_f64();
+            pairwise_sums = partial
+                .statistics
+                .iter()
+                .fold([0.0; 6], |sums, statistics| {
+                    std::array::from_fn(|i| sums[i] + statistics.sums[i])
+                });
+        }
+        // Both reductions sum the same values, up to the order of the additions
+        for (mutex_sum, pairwise_sum) in mutex_sums.iter().zip(pairwise_sums) {
+            assert!((mutex_sum - pairwise_sum).abs() <= 1e-9 * mutex_sum.abs());
+        }
+    }
+
+    let samples = (MONTE_CARLO * REPETITIONS) as f64;
+    println!(
+        "mutex:    {:.3} s, {:.0} samples/s",
+        mutex_seconds,
+        samples / mutex_seconds
+    );
+    println!(
+        "pairwise: {:.3} s, {:.0} samples/s",
+        pairwise_seconds,
+        samples / pairwise_seconds
+    );
+    println!("speedup:  {:.2}x", mutex_seconds / pairwise_seconds);
+}
@@ -0,0 +1,694 @@
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analysis::{get_monte_carlo_partial_performance, PartialPerformance, SUMMATION_BLOCK},
+    events::{
+        BlockId, Engine, Event, EventKind, HonestParties, Miner, PartyId, Subscriber, TreeBlock,
+        GENESIS,
+    },
+    formatting::scale_monte_carlo_blocks,
+    mdp::{MdpPolicy, PolicyMining},
+    protocol::Protocol,
+    sampling::{sample_monte_carlo_parties, Samples},
+    simulations::{start_protocol, ChunkExecutions, HONEST_COUNT},
+    types::{Block, Chain, INF},
+};
+
+// A strategy of the adversary, run inside the event engine. It sees every event, picks the
+// block each of its blocks extends, and decides when the honest parties see its blocks and,
+// within the network delay, each other's.
+pub trait Adversary {
+    // Sets up the network before the first event
+    fn start(&mut self, _engine: &mut Engine) {}
+
+    fn observe(&mut self, _event: &Event, _engine: &mut Engine) {}
+
+    fn get_parent(&self, engine: &Engine) -> BlockId;
+
+    // Called once the adversary has mined `block`
+    fn on_mined(&mut self, block: BlockId, engine: &mut Engine);
+
+    // Called when a block heavier than any other reaches an honest party
+    fn on_public_block(&mut self, block: BlockId, engine: &mut Engine);
+
+    // How long the honest block `block` sent by `from` takes to reach `to`, by default the
+    // network delay between them
+    fn delay(&mut self, from: PartyId, to: PartyId, _block: BlockId, engine: &Engine) -> f64 {
+        engine.get_delay(from, to)
+    }
+}
+
+// The strategies given by `--strategies`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Strategy {
+    /// Mines a private chain from genesis and never releases it
+    PrivateMining,
+    /// Withholds its blocks and releases them to override or match the honest chain
+    SelfishMining,
+    /// Selfish mining that only ever matches the honest chain and never settles a tie
+    StubbornMining,
+    /// Keeps two groups of honest parties on branches of equal work
+    BalanceAttack,
+}
+
+impl Strategy {
+    pub fn get_adversary(&self) -> Box<dyn Adversary> {
+        match self {
+            Strategy::PrivateMining => Box::new(PrivateMining { tip: GENESIS }),
+            Strategy::SelfishMining => Box::new(SelfishMining::new(false)),
+            Strategy::StubbornMining => Box::new(SelfishMining::new(true)),
+            Strategy::BalanceAttack => Box::new(BalanceAttack {
+                tips: vec![GENESIS; 2],
+            }),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Strategy::PrivateMining => "private-mining",
+            Strategy::SelfishMining => "selfish-mining",
+            Strategy::StubbornMining => "stubborn-mining",
+            Strategy::BalanceAttack => "balance-attack",
+        }
+    }
+
+    // The number of honest parties the strategy needs
+    pub fn parties(&self) -> usize {
+        match self {
+            Strategy::BalanceAttack => 2,
+            _ => 1,
+        }
+    }
+}
+
+// What an experiment pits against the honest parties: one of the strategies, or the policy
+// of a solved MDP replayed block by block
+#[derive(Debug, Clone, Copy)]
+pub enum Attacker<'a> {
+    Strategy(Strategy),
+    Policy(&'a MdpPolicy),
+}
+
+impl Attacker<'_> {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Attacker::Strategy(strategy) => strategy.name(),
+            Attacker::Policy(_) => "mdp-policy",
+        }
+    }
+
+    pub fn parties(&self) -> usize {
+        match self {
+            Attacker::Strategy(strategy) => strategy.parties(),
+            Attacker::Policy(_) => 1,
+        }
+    }
+
+    // Same as run_attack
+    pub fn run(
+        &self,
+        honest_blocks: &[Block],
+        honest_parties: &[PartyId],
+        adversary_blocks: &[Block],
+        network_delay: f64,
+    ) -> Attack {
+        match self {
+            Attacker::Strategy(strategy) => run_attack(
+                *strategy,
+                honest_blocks,
+                honest_parties,
+                adversary_blocks,
+                network_delay,
+            ),
+            Attacker::Policy(policy) => run_adversary(
+                &mut PolicyMining::new(policy),
+                1,
+                honest_blocks,
+                honest_parties,
+                adversary_blocks,
+                network_delay,
+                &mut [],
+            ),
+        }
+    }
+}
+
+// Whether `ancestor` is `block` or one of its ancestors
+pub(crate) fn is_ancestor(engine: &Engine, ancestor: BlockId, mut block: BlockId) -> bool {
+    while engine.block(block).height > engine.block(ancestor).height {
+        block = engine.block(block).parent;
+    }
+    block == ancestor
+}
+
+pub(crate) fn get_common_ancestor(blocks: &[TreeBlock], mut a: BlockId, mut b: BlockId) -> BlockId {
+    while a != b {
+        if blocks[a].height >= blocks[b].height {
+            a = blocks[a].parent;
+        } else {
+            b = blocks[b].parent;
+        }
+    }
+    a
+}
+
+pub struct PrivateMining {
+    tip: BlockId,
+}
+
+impl Adversary for PrivateMining {
+    fn get_parent(&self, _engine: &Engine) -> BlockId {
+        self.tip
+    }
+
+    fn on_mined(&mut self, block: BlockId, _engine: &mut Engine) {
+        self.tip = block;
+    }
+
+    fn on_public_block(&mut self, _block: BlockId, _engine: &mut Engine) {}
+}
+
+// Selfish mining of Eyal and Sirer, with the lead measured in work: when the honest chain
+// catches up, the adversary publishes its whole chain if withholding its last block would
+// no longer win, and otherwise only the blocks that match the honest chain. Stubborn mining
+// of Nayak et al. always only matches (lead-stubborn) and keeps mining privately after a
+// tie (equal-fork stubborn). Ties need equal work, so under PoEM the two coincide.
+pub struct SelfishMining {
+    private: BlockId,
+    released: BlockId,
+    // Every block is released and ties the honest chain
+    racing: bool,
+    stubborn: bool,
+}
+
+impl SelfishMining {
+    pub fn new(stubborn: bool) -> Self {
+        SelfishMining {
+            private: GENESIS,
+            released: GENESIS,
+            racing: false,
+            stubborn,
+        }
+    }
+
+    fn release(&mut self, block: BlockId, engine: &mut Engine) {
+        engine.schedule(engine.time(), EventKind::Release { block });
+        self.released = block;
+    }
+}
+
+impl Adversary for SelfishMining {
+    fn get_parent(&self, _engine: &Engine) -> BlockId {
+        self.private
+    }
+
+    fn on_mined(&mut self, block: BlockId, engine: &mut Engine) {
+        self.private = block;
+        if self.racing && !self.stubborn {
+            self.racing = false;
+            self.release(block, engine);
+        }
+    }
+
+    fn on_public_block(&mut self, block: BlockId, engine: &mut Engine) {
+        // The adversary's own releases
+        if is_ancestor(engine, block, self.private) {
+            return;
+        }
+        let public_work = engine.block(block).chain_work;
+        let private_work = engine.block(self.private).chain_work;
+        if private_work < public_work
+            || (private_work == public_work && self.private == self.released)
+        {
+            self.private = block;
+            self.released = block;
+            self.racing = false;
+            return;
+        }
+
+        let parent_work = engine.block(engine.block(self.private).parent).chain_work;
+        let release = if !self.stubborn && parent_work <= public_work {
+            self.private
+        } else {
+            // The first withheld block that matches the honest chain
+            let mut matching = self.private;
+            while engine.block(matching).parent != self.released
+                && engine.block(engine.block(matching).parent).chain_work >= public_work
+            {
+                matching = engine.block(matching).parent;
+            }
+            matching
+        };
+        self.racing = release == self.private && private_work == public_work;
+        self.release(release, engine);
+    }
+}
+
+// A balance attack bounded by the network delay: blocks reach the miner's own group at once
+// and the other group after the delay, and the adversary extends the lighter group's branch
+// and shows its block to that group only, so that the honest parties keep splitting their work
+pub struct BalanceAttack {
+    // The heaviest block delivered to each party
+    tips: Vec<BlockId>,
+}
+
+impl BalanceAttack {
+    fn get_lighter_party(&self, engine: &Engine) -> usize {
+        (0..self.tips.len())
+            .min_by(|&a, &b| {
+                engine
+                    .block(self.tips[a])
+                    .chain_work
+                    .total_cmp(&engine.block(self.tips[b]).chain_work)
+            })
+            .unwrap()
+    }
+}
+
+impl Adversary for BalanceAttack {
+    fn observe(&mut self, event: &Event, engine: &mut Engine) {
+        if let EventKind::Deliver { party, block } = event.kind {
+            if engine.block(block).chain_work > engine.block(self.tips[party]).chain_work {
+                self.tips[party] = block;
+            }
+        }
+    }
+
+    fn get_parent(&self, engine: &Engine) -> BlockId {
+        self.tips[self.get_lighter_party(engine)]
+    }
+
+    fn on_mined(&mut self, block: BlockId, engine: &mut Engine) {
+        let party = self.get_lighter_party(engine);
+        engine.schedule(engine.time(), EventKind::Deliver { party, block });
+    }
+
+    fn on_public_block(&mut self, _block: BlockId, _engine: &mut Engine) {}
+
+    fn delay(&mut self, from: PartyId, to: PartyId, _block: BlockId, engine: &Engine) -> f64 {
+        if from == to {
+            0.
+        } else {
+            engine.get_delay(from, to)
+        }
+    }
+}
+
+// Runs the adversary as a subscriber, recording the progress of the chains it mines that
+// would revert the first honest block, which holds the transaction it double spends
+struct AdversaryMiner<'a> {
+    adversary: &'a mut dyn Adversary,
+    // The heaviest block delivered to an honest party
+    public: BlockId,
+    progresses: Vec<Chain>,
+    // The first honest block, and whether each block of the tree so far extends it
+    target: Option<BlockId>,
+    extends_target: Vec<bool>,
+}
+
+impl AdversaryMiner<'_> {
+    fn update_targets(&mut self, engine: &Engine) {
+        for block in self.extends_target.len()..engine.blocks().len() {
+            let tree_block = engine.block(block);
+            if self.target.is_none() && matches!(tree_block.miner, Miner::Honest(_)) {
+                self.target = Some(block);
+            }
+            let extends_target =
+                self.target == Some(block) || self.extends_target[tree_block.parent];
+            self.extends_target.push(extends_target);
+        }
+    }
+}
+
+impl Subscriber for AdversaryMiner<'_> {
+    fn handle(&mut self, event: &Event, engine: &mut Engine) {
+        self.adversary.observe(event, engine);
+        match event.kind {
+            EventKind::Mine {
+                miner: Miner::Adversary,
+                work,
+            } => {
+                let parent = self.adversary.get_parent(engine);
+                let block = engine.mine(parent, Miner::Adversary, work);
+                let tree_block = *engine.block(block);
+                self.update_targets(engine);
+                if !self.extends_target[block]
+                    && tree_block.chain_work > self.progresses.last().unwrap().work
+                {
+                    self.progresses.push(Chain {
+                        timestamp: tree_block.timestamp,
+                        work: tree_block.chain_work,
+                        height: tree_block.height,
+                        arrival_time: tree_block.timestamp,
+                    });
+                }
+                self.adversary.on_mined(block, engine);
+            }
+            EventKind::Deliver { block, .. }
+                if engine.block(block).chain_work > engine.block(self.public).chain_work =>
+            {
+                self.public = block;
+                self.adversary.on_public_block(block, engine);
+            }
+            _ => {}
+        }
+        // The honest blocks sent on this event take the delays the adversary picks
+        for (from, to, block) in engine.take_outbox() {
+            let delay = self.adversary.delay(from, to, block, engine);
+            engine.schedule(
+                engine.time() + delay,
+                EventKind::Deliver { party: to, block },
+            );
+        }
+    }
+}
+
+// One execution under an adversary strategy
+pub struct Attack {
+    pub honest_progresses: Vec<Chain>,
+    pub adversary_progresses: Vec<Chain>,
+    // The fraction of the blocks of the final chain mined by the adversary
+    pub relative_revenue: f64,
+    // The fraction of honest blocks left out of the final chain
+    pub orphan_rate: f64,
+    // The block tree, and the tip of the final chain at the end of the execution
+    pub blocks: Vec<TreeBlock>,
+    pub final_tip: BlockId,
+}
+
+// Runs the scaled blocks of one sample, `honest_parties` holding the party of the strategy
+// that mines each honest block
+pub fn run_attack(
+    strategy: Strategy,
+    honest_blocks: &[Block],
+    honest_parties: &[PartyId],
+    adversary_blocks: &[Block],
+    network_delay: f64,
+) -> Attack {
+    run_adversary(
+        strategy.get_adversary().as_mut(),
+        strategy.parties(),
+        honest_blocks,
+        honest_parties,
+        adversary_blocks,
+        network_delay,
+        &mut [],
+    )
+}
+
+// Same as run_attack, for any adversary, with observers that see every event after the
+// parties
+pub fn run_adversary(
+    adversary: &mut dyn Adversary,
+    parties: usize,
+    honest_blocks: &[Block],
+    honest_parties: &[PartyId],
+    adversary_blocks: &[Block],
+    network_delay: f64,
+    observers: &mut [&mut dyn Subscriber],
+) -> Attack {
+    let mut engine = Engine::uniform(parties, network_delay);
+    for (block, &party) in honest_blocks.iter().zip(honest_parties) {
+        engine.schedule_mining(Miner::Honest(party), std::slice::from_ref(block));
+    }
+    engine.schedule_mining(Miner::Adversary, adversary_blocks);
+
+    adversary.start(&mut engine);
+    let mut honest = HonestParties::new(parties);
+    let mut adversary_miner = AdversaryMiner {
+        adversary,
+        public: GENESIS,
+        progresses: honest.progresses.clone(),
+        target: None,
+        extends_target: vec![false],
+    };
+    // The execution ends with the last honest block
+    let horizon = honest_blocks.last().map_or(0., |block| block.timestamp);
+    let mut subscribers: Vec<&mut dyn Subscriber> = vec![&mut honest, &mut adversary_miner];
+    subscribers.extend(
+        observers
+            .iter_mut()
+            .map(|observer| &mut **observer as &mut dyn Subscriber),
+    );
+    engine.run_until(horizon, &mut subscribers);
+    drop(subscribers);
+
+    // The adversary then publishes what it withheld, so the final chain is the heaviest of
+    // its tip and of the honest ones, which keep ties
+    let adversary_tip = adversary_miner.adversary.get_parent(&engine);
+    let final_tip = *std::iter::once(&adversary_tip)
+        .chain(&honest.tips)
+        .max_by(|&&a, &&b| {
+            engine
+                .block(a)
+                .chain_work
+                .total_cmp(&engine.block(b).chain_work)
+        })
+        .unwrap();
+    let mut block = final_tip;
+    let (mut honest_count, mut adversary_count) = (0, 0);
+    while block != GENESIS {
+        match engine.block(block).miner {
+            Miner::Honest(_) => honest_count += 1,
+            Miner::Adversary => adversary_count += 1,
+        }
+        block = engine.block(block).parent;
+    }
+
+    // The adversary's remaining blocks still take part in the race for k, as in the sweeps
+    let mut subscribers: Vec<&mut dyn Subscriber> = vec![&mut honest, &mut adversary_miner];
+    subscribers.extend(
+        observers
+            .iter_mut()
+            .map(|observer| &mut **observer as &mut dyn Subscriber),
+    );
+    engine.run(&mut subscribers);
+    drop(subscribers);
+    // The race lasts until the adversary's last block, even once the adversary has
+    // stopped mining without the first honest block
+    let mut adversary_progresses = adversary_miner.progresses;
+    let last_progress = *adversary_progresses.last().unwrap();
+    if adversary_blocks
+        .last()
+        .is_some_and(|block| block.timestamp > last_progress.timestamp)
+    {
+        adversary_progresses.push(Chain {
+            timestamp: INF,
+            arrival_time: INF,
+            ..last_progress
+        });
+    }
+    Attack {
+        honest_progresses: honest.progresses,
+        adversary_progresses,
+        relative_revenue: adversary_count as f64 / (honest_count + adversary_count).max(1) as f64,
+        orphan_rate: 1. - honest_count as f64 / honest_blocks.len() as f64,
+        blocks: engine.blocks().to_vec(),
+        final_tip,
+    }
+}
+
+// Per strategy, and last the policy if any, per beta
+pub struct AdversaryData {
+    pub strategy: Vec<&'static str>,
+    pub latency: Vec<Vec<f64>>,
+    pub k: Vec<Vec<f64>>,
+    pub growth_rate: Vec<Vec<f64>>,
+    pub relative_revenue: Vec<Vec<f64>>,
+    pub orphan_rate: Vec<Vec<f64>>,
+}
+
+// Pairs every strategy with the protocol at (g, gamma) for every beta, on the same samples.
+// k is the depth after which no chain the adversary mines without the first honest block
+// outweighs the one the honest parties mine, as in the sweeps, which private mining
+// reproduces. A `policy` is replayed after the strategies.
+#[allow(clippy::too_many_arguments)]
+pub fn simulate_adversaries(
+    protocol: &dyn Protocol,
+    strategies: &[Strategy],
+    policy: Option<&MdpPolicy>,
+    samples: Samples,
+    monte_carlo: usize,
+    epsilon: f64,
+    beta_range: &[f64],
+    g: f64,
+    gamma: f64,
+) -> AdversaryData {
+    let gamma = start_protocol(protocol, gamma);
+    let attackers: Vec<Attacker> = strategies
+        .iter()
+        .map(|&strategy| Attacker::Strategy(strategy))
+        .chain(policy.map(Attacker::Policy))
+        .collect();
+    let mut partials = vec![vec![PartialPerformance::default(); beta_range.len()]; attackers.len()];
+    let mut relative_revenues = vec![vec![0.0; beta_range.len()]; attackers.len()];
+    let mut orphan_rates = vec![vec![0.0; beta_range.len()]; attackers.len()];
+
+    for chunk in samples.chunks(SUMMATION_BLOCK) {
+        let executions = ChunkExecutions::sample(protocol, chunk, g, gamma);
+        let mut scaled_adversary_blocks = executions.adversary_blocks.clone();
+        let honest_parties: Vec<_> = attackers
+            .iter()
+            .map(|attacker| sample_monte_carlo_parties::<HONEST_COUNT>(chunk, attacker.parties()))
+            .collect();
+
+        for (beta_index, &beta) in beta_range.iter().enumerate() {
+            scale_monte_carlo_blocks(
+                &mut scaled_adversary_blocks,
+                &executions.adversary_blocks,
+                g * beta / (1. - beta),
+                gamma,
+            );
+            for (strategy_index, attacker) in attackers.iter().enumerate() {
+                let attacks: Vec<Attack> = executions
+                    .scaled_honest_blocks
+                    .par_iter()
+                    .zip(honest_parties[strategy_index].par_iter())
+                    .zip(scaled_adversary_blocks.par_iter())
+                    .map(|((honest_blocks, honest_parties), adversary_blocks)| {
+                        attacker.run(honest_blocks, honest_parties, adversary_blocks, 1.)
+                    })
+                    .collect();
+                let (honest_progresses, adversary_progresses): (Vec<_>, Vec<_>) = attacks
+                    .iter()
+                    .map(|attack| {
+                        (
+                            attack.honest_progresses.clone(),
+                            attack.adversary_progresses.clone(),
+                        )
+                    })
+                    .unzip();
+                let partial = &mut partials[strategy_index][beta_index];
+                partial.extend(get_monte_carlo_partial_performance(
+                    &honest_progresses,
+                    &adversary_progresses,
+                    None,
+                    None,
+                ));
+                partial.prune(monte_carlo, epsilon);
+                relative_revenues[strategy_index][beta_index] += attacks
+                    .iter()
+                    .map(|attack| attack.relative_revenue)
+                    .sum::<f64>();
+                orphan_rates[strategy_index][beta_index] +=
+                    attacks.iter().map(|attack| attack.orphan_rate).sum::<f64>();
+            }
+        }
+    }
+
+    let mut adversary_data = AdversaryData {
+        strategy: attackers.iter().map(Attacker::name).collect(),
+        latency: Vec::new(),
+        k: Vec::new(),
+        growth_rate: Vec::new(),
+        relative_revenue: relative_revenues,
+        orphan_rate: orphan_rates,
+    };
+    for (strategy_index, partials) in partials.iter().enumerate() {
+        let performances: Vec<_> = partials
+            .iter()
+            .map(|partial| partial.get_performance(epsilon))
+            .collect();
+        adversary_data.k.push(
+            performances
+                .iter()
+                .map(|performance| performance.0)
+                .collect(),
+        );
+        adversary_data.growth_rate.push(
+            performances
+                .iter()
+                .map(|performance| performance.1)
+                .collect(),
+        );
+        adversary_data.latency.push(
+            performances
+                .iter()
+                .map(|performance| performance.0 / performance.1)
+                .collect(),
+        );
+        for beta_index in 0..beta_range.len() {
+            adversary_data.relative_revenue[strategy_index][beta_index] /= samples.len() as f64;
+            adversary_data.orphan_rate[strategy_index][beta_index] /= samples.len() as f64;
+        }
+    }
+    adversary_data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        analysis::get_performance, formatting::get_monte_carlo_progresses, protocol::Poem,
+        types::block,
+    };
+
+    #[test]
+    fn test_private_mining() {
+        // Private mining through the engine races the same chains as the sweeps
+        let (honest_blocks, adversary_blocks) = Poem.sample_executions(Samples::new(5, 4));
+        let mut scaled_honest_blocks = honest_blocks.clone();
+        scale_monte_carlo_blocks(&mut scaled_honest_blocks, &honest_blocks, 2., 1.);
+        let mut scaled_adversary_blocks = adversary_blocks.clone();
+        scale_monte_carlo_blocks(&mut scaled_adversary_blocks, &adversary_blocks, 0.5, 1.);
+        let mut honest_progresses = vec![Vec::new(); honest_blocks.len()];
+        let mut adversary_progresses = vec![Vec::new(); honest_blocks.len()];
+        get_monte_carlo_progresses(&mut honest_progresses, &scaled_honest_blocks, 1.);
+        get_monte_carlo_progresses(&mut adversary_progresses, &scaled_adversary_blocks, 0.);
+
+        for sample in 0..honest_blocks.len() {
+            let attack = run_attack(
+                Strategy::PrivateMining,
+                &scaled_honest_blocks[sample],
+                &[0; HONEST_COUNT],
+                &scaled_adversary_blocks[sample],
+                1.,
+            );
+            assert_eq!(attack.honest_progresses, honest_progresses[sample]);
+            assert_eq!(attack.adversary_progresses, adversary_progresses[sample]);
+            assert_eq!(
+                get_performance(&attack.honest_progresses, &attack.adversary_progresses),
+                get_performance(&honest_progresses[sample], &adversary_progresses[sample])
+            );
+            assert_eq!(attack.relative_revenue, 0.);
+        }
+    }
+
+    #[test]
+    fn test_selfish_mining() {
+        // Bitcoin without delay: the adversary gets two blocks ahead and the honest parties
+        // find one. The selfish miner overrides it with its whole chain, which the next honest
+        // block extends, while the stubborn miner only ties it and loses both races.
+        for (strategy, relative_revenue) in [
+            (Strategy::SelfishMining, 2. / 3.),
+            (Strategy::StubbornMining, 0.),
+        ] {
+            let attack = run_attack(
+                strategy,
+                &[block(3.), block(5.)],
+                &[0, 0],
+                &[block(1.), block(2.)],
+                0.,
+            );
+            assert_eq!(attack.relative_revenue, relative_revenue);
+        }
+    }
+
+    #[test]
+    fn test_balance_attack() {
+        // The balance attack shows each party its own blocks at once and the other party's
+        // after the delay: the second block of party 0 extends its first, while the block of
+        // party 1 comes before the first arrives and forks from genesis
+        let attack = run_attack(
+            Strategy::BalanceAttack,
+            &[block(1.), block(1.2), block(1.5)],
+            &[0, 0, 1],
+            &[],
+            1.,
+        );
+        assert_eq!(attack.blocks[2].parent, 1);
+        assert_eq!(attack.blocks[3].parent, GENESIS);
+    }
+}
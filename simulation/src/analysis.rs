@@ -1,14 +1,24 @@
 use rayon::prelude::*;
-use std::sync::{Arc, Mutex};
 
 use crate::types::{Chain, INF};
 
-fn get_expected_shortfall(k: &mut Vec<f64>, epsilon: f64) -> f64 {
+// (k, f_work, f_height, max_work, max_height, adversary_max_work, adversary_max_height)
+pub type Performance = (f64, f64, f64, f64, f64, f64, f64);
+
+// The number of largest k values, out of `monte_carlo`, that the expected shortfall at
+// epsilon averages over
+pub fn get_tail_length(monte_carlo: usize, epsilon: f64) -> usize {
+    monte_carlo - ((1.0 - 2.0 * epsilon) * monte_carlo as f64).floor() as usize
+}
+
+// `k` holds the largest k values of `monte_carlo` samples, at least get_tail_length of them
+pub(crate) fn get_expected_shortfall(k: &mut [f64], monte_carlo: usize, epsilon: f64) -> f64 {
     k.sort_by(|a, b| a.partial_cmp(b).unwrap());
 
-    let n = k.len();
-    let index = ((1.0 - epsilon) * n as f64).floor() as usize;
-    let start = ((1.0 - 2.0 * epsilon) * n as f64).floor() as usize;
+    let n = monte_carlo;
+    let offset = n - k.len();
+    let index = ((1.0 - epsilon) * n as f64).floor() as usize - offset;
+    let start = ((1.0 - 2.0 * epsilon) * n as f64).floor() as usize - offset;
 
     let order_statistic = k[index];
     if order_statistic == INF {
@@ -17,22 +27,44 @@ fn get_expected_shortfall(k: &mut Vec<f64>, epsilon: f64) -> f64 {
 
     let mut sum = 0.0;
     let mut count = 0;
-    for i in start..n {
-        if k[i] == INF {
+    for &k_i in k.iter().skip(start) {
+        if k_i == INF {
             break;
         }
-        sum += k[i];
+        sum += k_i;
         count += 1;
     }
 
     sum / count as f64
 }
 
-fn get_performance(
-    honest_work_progresses: &Vec<Chain>,
-    adversary_work_progresses: &Vec<Chain>,
-) -> (f64, f64, f64, f64, f64, f64, f64) {
-    let mut k = INF;
+// Standard error of the expected shortfall, estimated from the spread of the sorted tail it averages
+fn get_expected_shortfall_error(k: &[f64], monte_carlo: usize, epsilon: f64) -> f64 {
+    let n = monte_carlo;
+    let offset = n - k.len();
+    let start = ((1.0 - 2.0 * epsilon) * n as f64).floor() as usize - offset;
+    let tail: Vec<f64> = k[start..]
+        .iter()
+        .copied()
+        .take_while(|&k_i| k_i != INF)
+        .collect();
+    if tail.len() < 2 || k[((1.0 - epsilon) * n as f64).floor() as usize - offset] == INF {
+        return INF;
+    }
+
+    let mean = tail.iter().sum::<f64>() / tail.len() as f64;
+    let variance =
+        tail.iter().map(|k_i| (k_i - mean).powi(2)).sum::<f64>() / (tail.len() - 1) as f64;
+    (variance / tail.len() as f64).sqrt()
+}
+
+// The index of the honest progress from which the adversary is never again ahead, that is,
+// the first progress at which a transaction in the first block can be confirmed
+pub fn get_confirmation_index(
+    honest_work_progresses: &[Chain],
+    adversary_work_progresses: &[Chain],
+) -> Option<usize> {
+    let mut index = None;
     let mut adversary_index = 0;
 
     // Go through each honest progress
@@ -47,25 +79,34 @@ fn get_performance(
             }
         }
 
-        // update k if needed
+        // update the index if needed
         let previous_adversary_progress = adversary_work_progresses[adversary_index - 1];
         let previous_honest_progress = honest_work_progresses[honest_index - 1];
 
         if previous_adversary_progress.work >= previous_honest_progress.work {
-            // found latest k
-            k = honest_progress.work;
+            // found latest index
+            index = Some(honest_index);
         }
         if previous_adversary_progress.work >= honest_progress.work {
-            // adversary is ahead, no k found yet
-            k = INF;
+            // adversary is ahead, no index found yet
+            index = None;
         }
     }
+    index
+}
+
+pub fn get_performance(
+    honest_work_progresses: &[Chain],
+    adversary_work_progresses: &[Chain],
+) -> Performance {
+    let k = get_confirmation_index(honest_work_progresses, adversary_work_progresses)
+        .map_or(INF, |index| honest_work_progresses[index].work);
 
     let max_work = honest_work_progresses.last().unwrap().work;
     let max_height = honest_work_progresses.last().unwrap().height as f64;
 
     let f_work = max_work / honest_work_progresses.last().unwrap().timestamp;
-    let f_height = max_height as f64 / honest_work_progresses.last().unwrap().timestamp;
+    let f_height = max_height / honest_work_progresses.last().unwrap().timestamp;
 
     let adversary_max_height = adversary_work_progresses.last().unwrap().height as f64;
     let adversary_max_work = adversary_work_progresses.last().unwrap().work;
@@ -81,90 +122,379 @@ fn get_performance(
 }
 
 pub fn get_monte_carlo_performance(
-    honest_monte_carlo_progress: &Vec<Vec<Chain>>,
-    adversary_monte_carlo_progress: &Vec<Vec<Chain>>,
+    honest_monte_carlo_progress: &[Vec<Chain>],
+    adversary_monte_carlo_progress: &[Vec<Chain>],
+    epsilon: f64,
+) -> Performance {
+    get_monte_carlo_performance_with_error(
+        honest_monte_carlo_progress,
+        adversary_monte_carlo_progress,
+        epsilon,
+    )
+    .0
+}
+
+// Same as get_monte_carlo_performance, additionally returning the standard error of k
+pub fn get_monte_carlo_performance_with_error(
+    honest_monte_carlo_progress: &[Vec<Chain>],
+    adversary_monte_carlo_progress: &[Vec<Chain>],
+    epsilon: f64,
+) -> (Performance, f64) {
+    get_monte_carlo_performance_per_epsilon(
+        honest_monte_carlo_progress,
+        adversary_monte_carlo_progress,
+        &[epsilon],
+    )
+    .remove(0)
+}
+
+// Corrects the expected shortfall `k_star` by the tail control of k, whose sum and sum of
+// squares over the `monte_carlo` samples are `control` and whose mean is known to be zero.
+// `tail` holds the (k, tail control) pairs of the largest k values in order of k. The
+// shortfall averages k over the samples from the start of the tail, so as for the averages of
+// Statistics it is corrected by the average control, times the coefficient of the regression
+// on the control of k on those samples and of zero elsewhere.
+fn get_controlled_expected_shortfall(
+    k_star: f64,
+    tail: &[(f64, f64)],
+    control: [f64; 2],
+    monte_carlo: usize,
     epsilon: f64,
-) -> (f64, f64, f64, f64, f64, f64, f64) {
-    let monte_carlo = honest_monte_carlo_progress.len();
-    let f_work_mutex = Arc::new(Mutex::new(0.0));
-    let f_height_mutex = Arc::new(Mutex::new(0.0));
-    let max_work_mutex = Arc::new(Mutex::new(0.0));
-    let max_height_mutex = Arc::new(Mutex::new(0.0));
-    let adversary_max_work_mutex = Arc::new(Mutex::new(0.0));
-    let adversary_max_height_mutex = Arc::new(Mutex::new(0.0));
-
-    let mut k: Vec<f64> = (0..monte_carlo)
-        .into_par_iter()
-        .map(|i| {
-            let honest_work_progresses = &honest_monte_carlo_progress[i];
-            let adversary_work_progresses = &adversary_monte_carlo_progress[i];
-
-            let (
+) -> f64 {
+    let n = monte_carlo as f64;
+    let mean = control[0] / n;
+    let variance = control[1] / n - mean * mean;
+    if k_star == INF || variance <= 0.0 {
+        return k_star;
+    }
+
+    let offset = monte_carlo - tail.len();
+    let start = ((1.0 - 2.0 * epsilon) * n).floor() as usize - offset;
+    let mut cross = 0.0;
+    let mut count = 0;
+    for &(k_i, control_i) in tail[start..].iter().take_while(|(k_i, _)| *k_i != INF) {
+        cross += k_i * control_i;
+        count += 1;
+    }
+    let coefficient = (cross / count as f64 - mean * k_star) / variance;
+    k_star - coefficient * mean
+}
+
+// Samples are summed in blocks of consecutive indices, and the block sums are then added
+// pairwise. Shards start on a block boundary, so that merging them reproduces the same sums.
+pub const SUMMATION_BLOCK: usize = 1024;
+
+// The statistics of the honest chain, which lead the sums and which the control variate
+// corrects
+const HONEST_STATISTICS: usize = 4;
+
+// Sums over a block of samples of every statistic but k, in the order of Performance.
+// With control variates, also the sums of the control and of its square, and of its
+// products with the honest statistics, and the sums of the tail control of k and of its
+// square.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Statistics {
+    pub count: usize,
+    pub sums: [f64; 6],
+    pub control: [f64; 2],
+    pub cross: [f64; HONEST_STATISTICS],
+    pub tail_control: [f64; 2],
+}
+
+impl Statistics {
+    fn add(&mut self, performance: &Performance, control: Option<f64>, tail_control: Option<f64>) {
+        let (_, f_work, f_height, max_work, max_height, adversary_max_work, adversary_max_height) =
+            *performance;
+        self.count += 1;
+        if let Some(tail_control) = tail_control {
+            self.tail_control[0] += tail_control;
+            self.tail_control[1] += tail_control * tail_control;
+        }
+        if let Some(control) = control {
+            self.control[0] += control;
+            self.control[1] += control * control;
+            for (cross, value) in self
+                .cross
+                .iter_mut()
+                .zip([f_work, f_height, max_work, max_height])
+            {
+                *cross += control * value;
+            }
+        }
+        for (sum, value) in self.sums.iter_mut().zip([
+            f_work,
+            f_height,
+            max_work,
+            max_height,
+            adversary_max_work,
+            adversary_max_height,
+        ]) {
+            *sum += value;
+        }
+    }
+
+    fn merge(&mut self, other: &Statistics) {
+        self.count += other.count;
+        for (sum, other_sum) in self
+            .sums
+            .iter_mut()
+            .chain(self.control.iter_mut())
+            .chain(self.cross.iter_mut())
+            .chain(self.tail_control.iter_mut())
+            .zip(
+                other
+                    .sums
+                    .iter()
+                    .chain(&other.control)
+                    .chain(&other.cross)
+                    .chain(&other.tail_control),
+            )
+        {
+            *sum += other_sum;
+        }
+    }
+
+    // The averages of the statistics. The controls have a known mean of zero, so each honest
+    // average is corrected by the average control, times the coefficient that minimizes the
+    // variance of the result as estimated from the same samples.
+    fn get_averages(&self) -> [f64; 6] {
+        let count = self.count as f64;
+        let mut averages = self.sums.map(|sum| sum / count);
+        let control = self.control[0] / count;
+        let control_variance = self.control[1] / count - control * control;
+        if control_variance > 0.0 {
+            for (average, cross) in averages.iter_mut().zip(self.cross) {
+                let coefficient = (cross / count - control * *average) / control_variance;
+                *average -= coefficient * control;
+            }
+        }
+        averages
+    }
+}
+
+// Ranges of at most this many samples are summed sequentially
+const PAIRWISE_LEAF: usize = 32;
+
+// Evaluates the samples, writing their k, and sums their other statistics pairwise: each
+// half is summed in parallel and the two sums are then added. The halves depend only on the
+// number of samples, so the sums do not depend on thread scheduling.
+fn get_pairwise_statistics(
+    honest_monte_carlo_progress: &[Vec<Chain>],
+    adversary_monte_carlo_progress: &[Vec<Chain>],
+    controls: Option<&[f64]>,
+    tail_controls: Option<&[f64]>,
+    k: &mut [f64],
+) -> Statistics {
+    if k.len() <= PAIRWISE_LEAF {
+        let mut statistics = Statistics::default();
+        for (i, k) in k.iter_mut().enumerate() {
+            let performance = get_performance(
+                &honest_monte_carlo_progress[i],
+                &adversary_monte_carlo_progress[i],
+            );
+            *k = performance.0;
+            statistics.add(
+                &performance,
+                controls.map(|controls| controls[i]),
+                tail_controls.map(|tail_controls| tail_controls[i]),
+            );
+        }
+        return statistics;
+    }
+
+    let middle = k.len() / 2;
+    let (left_k, right_k) = k.split_at_mut(middle);
+    let (mut statistics, right_statistics) = rayon::join(
+        || {
+            get_pairwise_statistics(
+                &honest_monte_carlo_progress[..middle],
+                &adversary_monte_carlo_progress[..middle],
+                controls.map(|controls| &controls[..middle]),
+                tail_controls.map(|tail_controls| &tail_controls[..middle]),
+                left_k,
+            )
+        },
+        || {
+            get_pairwise_statistics(
+                &honest_monte_carlo_progress[middle..],
+                &adversary_monte_carlo_progress[middle..],
+                controls.map(|controls| &controls[middle..]),
+                tail_controls.map(|tail_controls| &tail_controls[middle..]),
+                right_k,
+            )
+        },
+    );
+    statistics.merge(&right_statistics);
+    statistics
+}
+
+// Pairwise sum of the statistics of consecutive blocks
+fn get_total_statistics(statistics: &[Statistics]) -> Statistics {
+    match statistics.len() {
+        0 => Statistics::default(),
+        1 => statistics[0],
+        length => {
+            let (left, right) = statistics.split_at(length / 2);
+            let mut total = get_total_statistics(left);
+            total.merge(&get_total_statistics(right));
+            total
+        }
+    }
+}
+
+// Everything needed to compute the performance of a range of samples: their k values, or
+// once pruned only the largest of them, and the block sums of the other statistics in order.
+// With control variates, `tail_controls` holds the tail control of each k value.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PartialPerformance {
+    pub k: Vec<f64>,
+    pub tail_controls: Vec<f64>,
+    pub statistics: Vec<Statistics>,
+}
+
+impl PartialPerformance {
+    // Concatenates partial performances of consecutive sample ranges
+    pub fn merge(parts: Vec<PartialPerformance>) -> Self {
+        let mut merged = PartialPerformance::default();
+        for part in parts {
+            merged.extend(part);
+        }
+        merged
+    }
+
+    // Appends the partial performance of the samples that follow
+    pub fn extend(&mut self, mut other: PartialPerformance) {
+        self.k.append(&mut other.k);
+        self.tail_controls.append(&mut other.tail_controls);
+        self.statistics.append(&mut other.statistics);
+    }
+
+    // Drops the k values that the expected shortfall at epsilon over `monte_carlo` samples
+    // cannot reach. Waiting until twice the tail has accumulated keeps the cost linear.
+    pub fn prune(&mut self, monte_carlo: usize, epsilon: f64) {
+        let tail = get_tail_length(monte_carlo, epsilon);
+        if self.k.len() < 2 * tail.max(1) {
+            return;
+        }
+        let dropped = self.k.len() - tail;
+        if self.tail_controls.is_empty() {
+            self.k
+                .select_nth_unstable_by(dropped, |a, b| a.partial_cmp(b).unwrap());
+            self.k.drain(..dropped);
+        } else {
+            let mut pairs = self.get_pairs();
+            pairs.select_nth_unstable_by(dropped, |a, b| a.0.partial_cmp(&b.0).unwrap());
+            (self.k, self.tail_controls) = pairs.drain(dropped..).unzip();
+        }
+    }
+
+    // The (k, tail control) pairs
+    fn get_pairs(&self) -> Vec<(f64, f64)> {
+        self.k
+            .iter()
+            .copied()
+            .zip(self.tail_controls.iter().copied())
+            .collect()
+    }
+
+    pub fn get_performance_per_epsilon(&self, epsilons: &[f64]) -> Vec<(Performance, f64)> {
+        let total = get_total_statistics(&self.statistics);
+        let [f_work, f_height, max_work, max_height, adversary_max_work, adversary_max_height] =
+            total.get_averages();
+
+        let mut pairs = self.get_pairs();
+        pairs.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        let mut k = if pairs.is_empty() {
+            self.k.clone()
+        } else {
+            pairs.iter().map(|pair| pair.0).collect()
+        };
+        epsilons
+            .iter()
+            .map(|&epsilon| {
+                let mut k_star = get_expected_shortfall(&mut k, total.count, epsilon);
+                if !pairs.is_empty() {
+                    k_star = get_controlled_expected_shortfall(
+                        k_star,
+                        &pairs,
+                        total.tail_control,
+                        total.count,
+                        epsilon,
+                    );
+                }
+                let k_star_error = get_expected_shortfall_error(&k, total.count, epsilon);
+                (
+                    (
+                        k_star,
+                        f_work,
+                        f_height,
+                        max_work,
+                        max_height,
+                        adversary_max_work,
+                        adversary_max_height,
+                    ),
+                    k_star_error,
+                )
+            })
+            .collect()
+    }
+
+    pub fn get_performance(&self, epsilon: f64) -> Performance {
+        self.get_performance_per_epsilon(&[epsilon]).remove(0).0
+    }
+
+    // The number of samples evaluated
+    pub fn get_monte_carlo(&self) -> usize {
+        self.statistics
+            .iter()
+            .map(|statistics| statistics.count)
+            .sum()
+    }
+}
+
+// `controls` optionally holds a control variate per sample of the growth of the honest chain,
+// and `tail_controls` one of the tail of k, each with a known mean of zero
+pub fn get_monte_carlo_partial_performance(
+    honest_monte_carlo_progress: &[Vec<Chain>],
+    adversary_monte_carlo_progress: &[Vec<Chain>],
+    controls: Option<&[f64]>,
+    tail_controls: Option<&[f64]>,
+) -> PartialPerformance {
+    let mut k = vec![0.0; honest_monte_carlo_progress.len()];
+    let statistics = k
+        .par_chunks_mut(SUMMATION_BLOCK)
+        .enumerate()
+        .map(|(block, k)| {
+            let samples = block * SUMMATION_BLOCK..block * SUMMATION_BLOCK + k.len();
+            get_pairwise_statistics(
+                &honest_monte_carlo_progress[samples.clone()],
+                &adversary_monte_carlo_progress[samples.clone()],
+                controls.map(|controls| &controls[samples.clone()]),
+                tail_controls.map(|tail_controls| &tail_controls[samples]),
                 k,
-                f_work,
-                f_height,
-                max_work,
-                max_height,
-                adversary_max_work,
-                adversary_max_height,
-            ) = get_performance(honest_work_progresses, adversary_work_progresses);
-            let mut local_f_work = f_work_mutex.lock().unwrap();
-            let mut local_f_height = f_height_mutex.lock().unwrap();
-            let mut local_max_work = max_work_mutex.lock().unwrap();
-            let mut local_max_height = max_height_mutex.lock().unwrap();
-            let mut local_adversary_max_work = adversary_max_work_mutex.lock().unwrap();
-            let mut local_adversary_max_height = adversary_max_height_mutex.lock().unwrap();
-
-            *local_f_work += f_work;
-            *local_f_height += f_height;
-            *local_max_work += max_work;
-            *local_max_height += max_height;
-            *local_adversary_max_work += adversary_max_work;
-            *local_adversary_max_height += adversary_max_height;
-            k
+            )
         })
         .collect();
+    PartialPerformance {
+        k,
+        tail_controls: tail_controls.map_or_else(Vec::new, <[f64]>::to_vec),
+        statistics,
+    }
+}
 
-    // k.sort_by(|a, b| a.partial_cmp(b).unwrap());
-    // let k_star = k[((monte_carlo as f64) * (1.0 - epsilon)).floor() as usize];
-    let k_star = get_expected_shortfall(&mut k, epsilon);
-
-    let f_work = Arc::try_unwrap(f_work_mutex).unwrap().into_inner().unwrap() / monte_carlo as f64;
-    let f_height = Arc::try_unwrap(f_height_mutex)
-        .unwrap()
-        .into_inner()
-        .unwrap()
-        / monte_carlo as f64;
-    let max_work = Arc::try_unwrap(max_work_mutex)
-        .unwrap()
-        .into_inner()
-        .unwrap()
-        / monte_carlo as f64;
-    let max_height = Arc::try_unwrap(max_height_mutex)
-        .unwrap()
-        .into_inner()
-        .unwrap()
-        / monte_carlo as f64;
-    let adversary_max_work = Arc::try_unwrap(adversary_max_work_mutex)
-        .unwrap()
-        .into_inner()
-        .unwrap()
-        / monte_carlo as f64;
-    let adversary_max_height = Arc::try_unwrap(adversary_max_height_mutex)
-        .unwrap()
-        .into_inner()
-        .unwrap()
-        / monte_carlo as f64;
-
-    (
-        k_star,
-        f_work,
-        f_height,
-        max_work,
-        max_height,
-        adversary_max_work,
-        adversary_max_height,
+// Evaluates the same samples at several security levels, as only k depends on epsilon
+pub fn get_monte_carlo_performance_per_epsilon(
+    honest_monte_carlo_progress: &[Vec<Chain>],
+    adversary_monte_carlo_progress: &[Vec<Chain>],
+    epsilons: &[f64],
+) -> Vec<(Performance, f64)> {
+    get_monte_carlo_partial_performance(
+        honest_monte_carlo_progress,
+        adversary_monte_carlo_progress,
+        None,
+        None,
     )
+    .get_performance_per_epsilon(epsilons)
 }
 
 #[cfg(test)]
@@ -175,7 +505,73 @@ mod tests {
     fn test_expected_shortfall() {
         let mut k = vec![1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 10.0, 3.0, 2.0, 2.0, 11.0];
 
-        assert_eq!(get_expected_shortfall(&mut k, 0.1), 8.0);
+        assert_eq!(get_expected_shortfall(&mut k, 11, 0.1), 8.0);
+
+        // Only the largest values are needed
+        let mut tail = k[k.len() - get_tail_length(11, 0.1)..].to_vec();
+        assert_eq!(get_expected_shortfall(&mut tail, 11, 0.1), 8.0);
+    }
+
+    #[test]
+    fn test_merge_partial_performance() {
+        let sample = |i: usize| {
+            let honest = vec![
+                Chain {
+                    timestamp: 0.0,
+                    work: 0.0,
+                    height: 0,
+                    arrival_time: 0.0,
+                },
+                Chain {
+                    timestamp: 1.0 + i as f64 / 7.0,
+                    work: 1.0 + (i % 13) as f64 / 3.0,
+                    height: 1,
+                    arrival_time: 0.0,
+                },
+            ];
+            let adversary = vec![
+                Chain {
+                    timestamp: 0.0,
+                    work: 0.0,
+                    height: 0,
+                    arrival_time: 0.0,
+                },
+                Chain {
+                    timestamp: 2.0 + i as f64,
+                    work: 0.1,
+                    height: 1,
+                    arrival_time: 0.0,
+                },
+            ];
+            (honest, adversary)
+        };
+        let (honest, adversary): (Vec<_>, Vec<_>) =
+            (0..3 * SUMMATION_BLOCK + 5).map(sample).unzip();
+
+        let whole = get_monte_carlo_partial_performance(&honest, &adversary, None, None);
+        let split = 2 * SUMMATION_BLOCK;
+        let merged = PartialPerformance::merge(vec![
+            get_monte_carlo_partial_performance(&honest[..split], &adversary[..split], None, None),
+            get_monte_carlo_partial_performance(&honest[split..], &adversary[split..], None, None),
+        ]);
+        assert_eq!(merged, whole);
+
+        // The pairwise sums do not depend on how many threads share the work
+        for threads in [1, 3] {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            let partial = pool
+                .install(|| get_monte_carlo_partial_performance(&honest, &adversary, None, None));
+            assert_eq!(partial, whole);
+        }
+        assert_eq!(merged.get_performance(0.1), whole.get_performance(0.1));
+
+        let mut pruned = whole.clone();
+        pruned.prune(whole.k.len(), 0.1);
+        assert_eq!(pruned.k.len(), get_tail_length(whole.k.len(), 0.1));
+        assert_eq!(pruned.get_performance(0.1), whole.get_performance(0.1));
     }
 
     #[test]
@@ -417,4 +813,44 @@ mod tests {
             (6.0, 6.0 / 5.0, 3.0 / 5.0, 6.0, 3.0, 5.0, 3.0)
         );
     }
+
+    #[test]
+    fn test_control_variates() {
+        // Statistics that are linear in a control with a known mean of zero are corrected to
+        // their exact mean, even though the sampled controls do not average to zero
+        let controls = [0.3, -0.1, 0.7, 0.2, -0.4];
+        let mut statistics = Statistics::default();
+        for control in controls {
+            let value = 2.0 + 3.0 * control;
+            statistics.add(
+                &(0.0, value, value, value, value, value, value),
+                Some(control),
+                None,
+            );
+        }
+        let averages = statistics.get_averages();
+        for average in &averages[..HONEST_STATISTICS] {
+            assert!((average - 2.0).abs() < 1e-12);
+        }
+        // The adversary statistics are left as sampled
+        assert!((averages[4] - (2.0 + 3.0 * 0.14)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_controlled_expected_shortfall() {
+        // At epsilon 0.25 the expected shortfall of 10 samples averages the largest 5 k. With
+        // a control of 1 on those samples and of -0.5 on the others, k on the tail is linear
+        // in the control, so the shortfall is corrected to its exact value: 30 times the
+        // probability 1 / 3 that a control of mean zero is 1, over 2 * epsilon.
+        let mut tail: Vec<(f64, f64)> = (1..=5).map(|k| (k as f64, -0.5)).collect();
+        tail.extend([(30.0, 1.0); 5]);
+        let control = tail.iter().fold([0.0; 2], |[sum, squares], &(_, control)| {
+            [sum + control, squares + control * control]
+        });
+        let mut k: Vec<f64> = tail.iter().map(|pair| pair.0).collect();
+        let k_star = get_expected_shortfall(&mut k, 10, 0.25);
+        assert_eq!(k_star, 30.0);
+        let k_star = get_controlled_expected_shortfall(k_star, &tail, control, 10, 0.25);
+        assert!((k_star - 20.0).abs() < 1e-12);
+    }
 }
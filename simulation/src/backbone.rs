@@ -0,0 +1,352 @@
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::{
+    adversary::{get_common_ancestor, run_adversary, Strategy},
+    analysis::SUMMATION_BLOCK,
+    events::{BlockId, Engine, Event, EventKind, Miner, PartyId, Subscriber, TreeBlock, GENESIS},
+    formatting::scale_monte_carlo_blocks,
+    protocol::Protocol,
+    sampling::{sample_monte_carlo_parties, Samples},
+    simulations::{start_protocol, ChunkExecutions, HONEST_COUNT},
+    types::Block,
+};
+
+// The levels of the quantiles every distribution reports, the last one being the maximum
+pub const QUANTILES: [f64; 5] = [0.5, 0.9, 0.99, 0.999, 1.];
+
+// The quantiles of each distribution are those of its first this many samples, which bounds
+// the memory of a run to this many Properties per (beta, g). The means are over every sample.
+pub const MAX_QUANTILE_SAMPLES: usize = 100_000;
+
+// The Backbone properties of one execution, up to its last honest block
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Properties {
+    // The deepest honest chain, in work and in blocks, that an honest party gave up, either
+    // for a heavier chain delivered to it or for the final chain
+    pub common_prefix_work: f64,
+    pub common_prefix_height: usize,
+    // The work of the lightest honest chain per unit of time, which is the delay in the sweeps
+    pub growth_rate: f64,
+    // The largest fraction of adversary blocks among `window` consecutive blocks of the final
+    // chain, or among all of them when it is shorter
+    pub adversary_fraction: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Distribution {
+    pub mean: f64,
+    // At the levels of QUANTILES
+    pub quantiles: Vec<f64>,
+}
+
+impl Distribution {
+    pub fn new(mut values: Vec<f64>) -> Self {
+        values.sort_by(f64::total_cmp);
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let quantiles = QUANTILES
+            .iter()
+            .map(|&level| {
+                let index = (level * values.len() as f64).ceil() as usize;
+                values[index.clamp(1, values.len()) - 1]
+            })
+            .collect();
+        Distribution { mean, quantiles }
+    }
+}
+
+// Per beta, per g
+pub struct BackboneData {
+    pub common_prefix_work: Vec<Vec<Distribution>>,
+    pub common_prefix_height: Vec<Vec<Distribution>>,
+    pub growth_rate: Vec<Vec<Distribution>>,
+    pub adversary_fraction: Vec<Vec<Distribution>>,
+    // The number of samples the quantiles are over
+    pub quantile_samples: usize,
+}
+
+// The properties of every sample at one (beta, g), as their sums and the first
+// MAX_QUANTILE_SAMPLES of them
+#[derive(Clone, Default)]
+struct PropertySamples {
+    sums: [f64; 4],
+    count: usize,
+    kept: Vec<Properties>,
+}
+
+impl Properties {
+    fn values(&self) -> [f64; 4] {
+        [
+            self.common_prefix_work,
+            self.common_prefix_height as f64,
+            self.growth_rate,
+            self.adversary_fraction,
+        ]
+    }
+}
+
+impl PropertySamples {
+    fn extend(&mut self, properties: Vec<Properties>) {
+        for property in &properties {
+            for (sum, value) in self.sums.iter_mut().zip(property.values()) {
+                *sum += value;
+            }
+        }
+        self.count += properties.len();
+        let room = MAX_QUANTILE_SAMPLES - self.kept.len();
+        self.kept.extend(properties.into_iter().take(room));
+    }
+
+    // The distribution of the property of the given index in Properties::values
+    fn get_distribution(&self, index: usize) -> Distribution {
+        Distribution {
+            mean: self.sums[index] / self.count as f64,
+            ..Distribution::new(
+                self.kept
+                    .iter()
+                    .map(|properties| properties.values()[index])
+                    .collect(),
+            )
+        }
+    }
+}
+
+// The work and the number of blocks of the chain of `tip` after its common ancestor with
+// the chain of `other`
+fn get_abandoned(blocks: &[TreeBlock], tip: BlockId, other: BlockId) -> (f64, usize) {
+    let ancestor = get_common_ancestor(blocks, tip, other);
+    (
+        blocks[tip].chain_work - blocks[ancestor].chain_work,
+        blocks[tip].height - blocks[ancestor].height,
+    )
+}
+
+// Follows the chain of every honest party up to the horizon, with the adoption rule of
+// HonestParties, and records the deepest switch
+struct PrefixObserver {
+    horizon: f64,
+    tips: Vec<BlockId>,
+    work: f64,
+    height: usize,
+}
+
+impl PrefixObserver {
+    fn abandon(&mut self, blocks: &[TreeBlock], tip: BlockId, other: BlockId) {
+        let (work, height) = get_abandoned(blocks, tip, other);
+        self.work = self.work.max(work);
+        self.height = self.height.max(height);
+    }
+}
+
+impl Subscriber for PrefixObserver {
+    fn handle(&mut self, event: &Event, engine: &mut Engine) {
+        if event.time > self.horizon {
+            return;
+        }
+        if let EventKind::Deliver { party, block } = event.kind {
+            let tip = self.tips[party];
+            if engine.block(block).chain_work > engine.block(tip).chain_work {
+                self.abandon(engine.blocks(), tip, block);
+                self.tips[party] = block;
+            }
+        }
+    }
+}
+
+// Runs the strategy on the scaled blocks of one sample and measures the properties
+pub fn get_properties(
+    strategy: Strategy,
+    honest_blocks: &[Block],
+    honest_parties: &[PartyId],
+    adversary_blocks: &[Block],
+    network_delay: f64,
+    window: usize,
+) -> Properties {
+    let horizon = honest_blocks.last().map_or(0., |block| block.timestamp);
+    let mut observer = PrefixObserver {
+        horizon,
+        tips: vec![GENESIS; strategy.parties()],
+        work: 0.,
+        height: 0,
+    };
+    let attack = run_adversary(
+        strategy.get_adversary().as_mut(),
+        strategy.parties(),
+        honest_blocks,
+        honest_parties,
+        adversary_blocks,
+        network_delay,
+        &mut [&mut observer],
+    );
+    let blocks = &attack.blocks;
+    for tip in observer.tips.clone() {
+        observer.abandon(blocks, tip, attack.final_tip);
+    }
+    let lightest_work = observer
+        .tips
+        .iter()
+        .map(|&tip| blocks[tip].chain_work)
+        .fold(f64::INFINITY, f64::min);
+
+    // Whether each block of the final chain, from the tip, was mined by the adversary
+    let mut chain = Vec::new();
+    let mut block = attack.final_tip;
+    while blocks[block].height > 0 {
+        chain.push(blocks[block].miner == Miner::Adversary);
+        block = blocks[block].parent;
+    }
+    let window = window.min(chain.len()).max(1);
+    let adversary_fraction = chain
+        .windows(window)
+        .map(|blocks| blocks.iter().filter(|&&adversary| adversary).count())
+        .max()
+        .unwrap_or(0) as f64
+        / window as f64;
+
+    Properties {
+        common_prefix_work: observer.work,
+        common_prefix_height: observer.height,
+        growth_rate: lightest_work / horizon,
+        adversary_fraction,
+    }
+}
+
+// Measures the Backbone properties of the protocol under the strategy at every (beta, g), on
+// the same samples, with a delay of 1
+#[allow(clippy::too_many_arguments)]
+pub fn simulate_backbone(
+    protocol: &dyn Protocol,
+    strategy: Strategy,
+    samples: Samples,
+    beta_range: &[f64],
+    g_range: &[f64],
+    gamma: f64,
+    window: usize,
+) -> BackboneData {
+    let gamma = start_protocol(protocol, gamma);
+    let mut properties = vec![vec![PropertySamples::default(); g_range.len()]; beta_range.len()];
+
+    for chunk in samples.chunks(SUMMATION_BLOCK) {
+        let mut executions = ChunkExecutions::sample(protocol, chunk, g_range[0], gamma);
+        let mut scaled_adversary_blocks = executions.adversary_blocks.clone();
+        let honest_parties = sample_monte_carlo_parties::<HONEST_COUNT>(chunk, strategy.parties());
+        for (g_index, &g) in g_range.iter().enumerate() {
+            executions.scale_honest(protocol, g, gamma);
+            for (beta_index, &beta) in beta_range.iter().enumerate() {
+                scale_monte_carlo_blocks(
+                    &mut scaled_adversary_blocks,
+                    &executions.adversary_blocks,
+                    g * beta / (1. - beta),
+                    gamma,
+                );
+                properties[beta_index][g_index].extend(
+                    executions
+                        .scaled_honest_blocks
+                        .par_iter()
+                        .zip(honest_parties.par_iter())
+                        .zip(scaled_adversary_blocks.par_iter())
+                        .map(|((honest_blocks, honest_parties), adversary_blocks)| {
+                            get_properties(
+                                strategy,
+                                honest_blocks,
+                                honest_parties,
+                                adversary_blocks,
+                                1.,
+                                window,
+                            )
+                        })
+                        .collect(),
+                );
+            }
+        }
+    }
+
+    let get_distributions = |index| {
+        properties
+            .iter()
+            .map(|properties| {
+                properties
+                    .iter()
+                    .map(|properties| properties.get_distribution(index))
+                    .collect()
+            })
+            .collect()
+    };
+    BackboneData {
+        common_prefix_work: get_distributions(0),
+        common_prefix_height: get_distributions(1),
+        growth_rate: get_distributions(2),
+        adversary_fraction: get_distributions(3),
+        quantile_samples: samples.len().min(MAX_QUANTILE_SAMPLES),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::block;
+
+    #[test]
+    fn test_distribution() {
+        let distribution = Distribution::new((1..=1000).rev().map(f64::from).collect());
+        assert_eq!(distribution.mean, 500.5);
+        assert_eq!(distribution.quantiles, vec![500., 900., 990., 999., 1000.]);
+    }
+
+    #[test]
+    fn test_quantile_samples() {
+        // Samples past the cap count towards the means but not the quantiles
+        let properties = |growth_rate| Properties {
+            common_prefix_work: 0.,
+            common_prefix_height: 0,
+            growth_rate,
+            adversary_fraction: 0.,
+        };
+        let mut samples = PropertySamples::default();
+        samples.extend(vec![properties(1.); MAX_QUANTILE_SAMPLES - 1]);
+        samples.extend(vec![
+            properties(1.),
+            properties((MAX_QUANTILE_SAMPLES + 2) as f64),
+        ]);
+        let distribution = samples.get_distribution(2);
+        assert_eq!(distribution.mean, 2.);
+        assert_eq!(distribution.quantiles, vec![1.; QUANTILES.len()]);
+    }
+
+    #[test]
+    fn test_properties() {
+        // Bitcoin without delay, as in test_selfish_mining: the honest block at 3 is given up
+        // for the selfish miner's two blocks, which make two of the three final blocks
+        let honest_blocks = [block(3.), block(5.)];
+        let adversary_blocks = [block(1.), block(2.)];
+        let properties = get_properties(
+            Strategy::SelfishMining,
+            &honest_blocks,
+            &[0, 0],
+            &adversary_blocks,
+            0.,
+            2,
+        );
+        assert_eq!(
+            properties,
+            Properties {
+                common_prefix_work: 1.,
+                common_prefix_height: 1,
+                growth_rate: 0.6,
+                adversary_fraction: 1.,
+            }
+        );
+        // Private mining never releases, so the honest chain is only given up for the
+        // heavier private chain at the end
+        let properties = get_properties(
+            Strategy::PrivateMining,
+            &[block(1.), block(4.)],
+            &[0, 0],
+            &[block(2.), block(2.5), block(3.)],
+            0.,
+            10,
+        );
+        assert_eq!(properties.common_prefix_work, 2.);
+        assert_eq!(properties.adversary_fraction, 1.);
+    }
+}
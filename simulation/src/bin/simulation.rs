@@ -1,329 +1,1579 @@
-use std::{fs::File, io::Write};
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
 
-use clap::{ArgGroup, Parser};
+use clap::{parser::ValueSource, ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
 use serde_json::json;
 use simulation::{
-    sampling::sample_monte_carlo_execution_timestamps,
-    simulations::{simulate_bitcoin, simulate_poem, ReductionType, ADVERSARY_COUNT, HONEST_COUNT},
+    adversary::simulate_adversaries,
+    backbone::{simulate_backbone, QUANTILES},
+    checkpoint::{get_checkpoint_path, Checkpoint, RunDescription},
+    confirmation::simulate_confirmation,
+    error::Error,
+    growth::{simulate_growth, CONFIDENCE_LEVEL},
+    mdp::{Mdp, MdpPolicy},
+    merge_mining::simulate_merge_mining,
+    optimizer::optimize_protocol,
+    pareto::{simulate_pareto, ParetoData},
+    protocol::{Bitcoin, Poem},
+    ranges::parse_field_range,
+    refinement::{refine_protocol, GridPoint},
+    resilience::simulate_resilience,
+    rounds::simulate_rounds,
+    sampling::{Samples, Sequential, Shard, VarianceReduction, VarianceReductionMode},
+    scenario::{check_settings, load_scenario, Experiment},
+    simulations::{
+        get_full_grid, simulate_bitcoin, simulate_poem, simulate_poem_dag, ReductionType,
+    },
+    stationary::{simulate_stationary, Target},
+    variance::{simulate_convergence, simulate_variance, Estimate, SAMPLERS, VARIANCE_REDUCTIONS},
 };
 
-#[derive(Debug)]
-struct RangeParseError;
-
-impl std::fmt::Display for RangeParseError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Invalid range format. Use the format: start..end:step")
-    }
+#[derive(Subcommand, Debug)]
+enum Command {
+    #[command(flatten)]
+    Experiment(Experiment),
+    /// Runs every experiment of a TOML or JSON scenario file
+    Run { scenario: PathBuf },
+    /// Combines the checkpoints written by every shard of a run into its results
+    Merge {
+        #[arg(required = true)]
+        shards: Vec<PathBuf>,
+    },
 }
 
-impl std::error::Error for RangeParseError {}
-
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
-#[command(group(
-    ArgGroup::new("commands")
-        .args(&["bitcoin_vs_poem", "poem", "g_latency", "gamma_latency"])
-        .required(true)
-        .multiple(false)
-))]
 struct Args {
-    #[arg(long)]
-    gamma_latency: bool,
-    #[arg(long)]
-    g_latency: bool,
-    #[arg(long)]
-    bitcoin_vs_poem: bool,
-    #[arg(long)]
-    poem: bool,
-    #[arg(long)]
-    gamma_range: Option<String>,
-    #[arg(long)]
-    gamma: Option<f64>,
-    #[arg(long)]
-    g_range: Option<String>,
-    #[arg(long)]
-    g: Option<f64>,
-    #[arg(long)]
-    beta_range: Option<String>,
-    #[arg(long)]
-    beta: Option<f64>,
-    #[arg(long, default_value = "1000")]
+    #[command(subcommand)]
+    command: Command,
+    #[command(flatten)]
+    settings: Settings,
+}
+
+// The settings shared by every experiment
+#[derive(clap::Args, Debug)]
+struct Settings {
+    #[arg(long, global = true, default_value = "1000")]
     monte_carlo: usize,
-    #[arg(long, default_value = "0.1")]
+    #[arg(long, global = true, default_value = "0.1")]
     error: f64,
+    #[arg(long, global = true, default_value = "simulation_data")]
+    output_directory: String,
+    // Drawn at random when not given, and printed so that the run can be repeated
+    #[arg(long, global = true)]
+    seed: Option<u64>,
+    // Skips the grid points already completed by an interrupted run of the same experiment
+    #[arg(long, global = true)]
+    resume: bool,
+    // Evaluates only the i-th of n parts of the samples, to be combined with merge
+    #[arg(long, global = true)]
+    shard: Option<Shard>,
+    // Comma-separated, by default the samples are shared by every protocol and grid point
+    #[arg(
+        long,
+        global = true,
+        value_delimiter = ',',
+        default_value = "common-random-numbers"
+    )]
+    variance_reduction: Vec<VarianceReductionMode>,
+    // Keeps adding batches of --monte-carlo samples to each grid point until the confidence
+    // interval of k* is narrower than this fraction of it
+    #[arg(long, global = true)]
+    precision: Option<f64>,
+    // The most samples a grid point draws with --precision, ten batches by default
+    #[arg(long, global = true, requires = "precision")]
+    max_monte_carlo: Option<usize>,
 }
 
-fn logarithmic_range(start: f64, end: f64, num_points: usize, exponent: f64) -> Vec<f64> {
-    (0..num_points)
-        .map(|i| {
-            let fraction = (i as f64 / (num_points - 1) as f64).powf(exponent);
-            start * (end / start).powf(fraction)
-        })
-        .collect::<Vec<f64>>()
+fn grid_points_json(points: &[GridPoint]) -> serde_json::Value {
+    json!({
+        "beta": points.iter().map(|point| point.beta).collect::<Vec<_>>(),
+        "g": points.iter().map(|point| point.g).collect::<Vec<_>>(),
+        "gamma": points.iter().map(|point| point.gamma).collect::<Vec<_>>(),
+        "refinement": points.iter().map(|point| point.refinement).collect::<Vec<_>>(),
+        "latency": points.iter().map(|point| point.latency).collect::<Vec<_>>(),
+        "k": points.iter().map(|point| point.k).collect::<Vec<_>>(),
+        "throughput": points.iter().map(|point| point.throughput).collect::<Vec<_>>(),
+    })
 }
 
-fn linear_range(start: f64, end: f64, num_points: usize) -> Vec<f64> {
-    (0..num_points)
-        .map(|i| start + (end - start) * (i as f64 / (num_points - 1) as f64))
-        .collect::<Vec<f64>>()
+fn pareto_json(pareto_data: &ParetoData) -> serde_json::Value {
+    let configurations = &pareto_data.configurations;
+    json!({
+        "beta": configurations.iter().map(|c| c.beta).collect::<Vec<_>>(),
+        "g": configurations.iter().map(|c| c.g).collect::<Vec<_>>(),
+        "gamma": configurations.iter().map(|c| c.gamma).collect::<Vec<_>>(),
+        "error": configurations.iter().map(|c| c.epsilon).collect::<Vec<_>>(),
+        "latency": configurations.iter().map(|c| c.latency).collect::<Vec<_>>(),
+        "k": configurations.iter().map(|c| c.k).collect::<Vec<_>>(),
+        "throughput": configurations.iter().map(|c| c.throughput).collect::<Vec<_>>(),
+        "pareto_optimal": pareto_data.pareto_optimal,
+    })
 }
 
-fn parse_range(s: String, exponent: Option<f64>) -> Result<Vec<f64>, RangeParseError> {
-    let parts: Vec<&str> = s.split(':').collect();
+// A shard only holds part of the samples, so its results are written by merge instead
+fn write_output(
+    file_name: &str,
+    data: &serde_json::Value,
+    shard: Option<Shard>,
+) -> Result<(), Error> {
+    if shard.is_some() {
+        return Ok(());
+    }
+    let json_string = serde_json::to_string_pretty(data).unwrap();
+    let mut file = File::create(file_name)?;
+
+    file.write_all(json_string.as_bytes())?;
+    println!("Wrote to file: {}", file_name);
+    Ok(())
+}
 
-    if parts.len() != 3 {
-        return Err(RangeParseError);
+fn run(experiment: &Experiment, args: &Settings) -> Result<(), Error> {
+    check_settings(args.monte_carlo, "error", args.error)?;
+    let unsupported = |field: &str, what: &str| Error::InvalidParameter {
+        field: field.to_string(),
+        message: format!("{} {}", experiment.name(), what),
+    };
+    if args.shard.is_some() && !experiment.can_shard() {
+        return Err(unsupported("shard", "cannot be sharded"));
     }
-    let start: f64 = parts[0].parse().expect("Failed to parse start");
-    let end: f64 = parts[1].parse().expect("Failed to parse end");
-    let num_points: usize = parts[2].parse().expect("Failed to parse num_points");
-
-    if let Some(exp) = exponent {
-        if start == 0.0 {
-            let mut range = logarithmic_range(start + 0.005, end, num_points, exp);
-            if range.len() > 1 {
-                range.remove(0);
-            }
-            range.insert(0, 0.0);
-            return Ok(range);
+    if args.resume && !experiment.uses_checkpoint() {
+        return Err(unsupported("resume", "keeps no checkpoint to resume from"));
+    }
+    if args.seed.is_some() && !experiment.uses_seed() {
+        return Err(unsupported("seed", "draws no samples"));
+    }
+    let sequential = args.precision.map(|precision| Sequential {
+        precision,
+        max_monte_carlo: args.max_monte_carlo.unwrap_or(10 * args.monte_carlo),
+    });
+    if let Some(sequential) = sequential {
+        sequential.validate(args.monte_carlo)?;
+        if args.shard.is_some() {
+            return Err(unsupported(
+                "precision",
+                "cannot sample sequentially in shards",
+            ));
+        }
+        if !experiment.can_sequence() {
+            return Err(unsupported("precision", "cannot sample sequentially"));
         }
-        Ok(logarithmic_range(start, end, num_points, exp))
-    } else {
-        Ok(linear_range(start, end, num_points))
     }
+    let variance_reduction = VarianceReduction::from_modes(&args.variance_reduction);
+    let supported = experiment.get_variance_reductions();
+    let mut modes = variance_reduction.modes();
+    // Without common random numbers, every point draws its own samples as with none alone
+    if !variance_reduction.common_random_numbers {
+        modes.push(VarianceReductionMode::None);
+    }
+    if let Some(mode) = modes.into_iter().find(|mode| !supported.contains(mode)) {
+        return Err(unsupported(
+            "variance_reduction",
+            &format!("does not support {:?}, only {:?}", mode, supported),
+        ));
+    }
+    std::fs::create_dir_all(&args.output_directory)?;
+    let description = RunDescription {
+        experiment: experiment.clone(),
+        monte_carlo: args.monte_carlo,
+        error: args.error,
+        variance_reduction,
+        sequential,
+        shard: args.shard,
+    };
+    let checkpoint = Checkpoint::open(
+        &get_checkpoint_path(&args.output_directory, &description),
+        &description,
+        args.seed,
+        args.resume,
+    )?;
+    let samples = Samples {
+        variance_reduction,
+        sequential,
+        ..Samples::new(checkpoint.seed, args.monte_carlo)
+    };
+    let samples = match args.shard {
+        Some(shard) => {
+            let samples = shard.get_samples(samples)?;
+            println!(
+                "Shard {}: samples {} to {}",
+                shard,
+                samples.start,
+                samples.end - 1
+            );
+            samples
+        }
+        None => samples,
+    };
+    run_with_samples(experiment, args, checkpoint, samples)
 }
 
-fn main() {
-    let args = Args::parse();
-    let start = std::time::Instant::now();
+// Runs the experiment from the merged points of its shards, which need no samples
+fn merge(paths: &[PathBuf], output_directory: &str) -> Result<(), Error> {
+    let (description, checkpoint) = Checkpoint::merge_shards(paths)?;
+    println!(
+        "Merging {} shards of {}",
+        paths.len(),
+        description.experiment.name()
+    );
+    std::fs::create_dir_all(output_directory)?;
+    let settings = Settings {
+        monte_carlo: description.monte_carlo,
+        error: description.error,
+        output_directory: output_directory.to_string(),
+        seed: Some(checkpoint.seed),
+        resume: false,
+        shard: None,
+        variance_reduction: description.variance_reduction.modes(),
+        precision: None,
+        max_monte_carlo: None,
+    };
+    let samples = Samples {
+        variance_reduction: description.variance_reduction,
+        ..Samples::new(checkpoint.seed, 0)
+    };
+    run_with_samples(&description.experiment, &settings, checkpoint, samples)
+}
 
-    if args.gamma_latency {
-        let gamma_range = parse_range(args.gamma_range.clone().unwrap(), Some(0.5)).unwrap();
-        let beta_range = vec![args.beta.clone().unwrap()];
-        let g_range = vec![args.g.clone().unwrap()];
+fn run_with_samples(
+    experiment: &Experiment,
+    args: &Settings,
+    mut checkpoint: Checkpoint,
+    samples: Samples,
+) -> Result<(), Error> {
+    let seed = checkpoint.seed;
+    println!("Seed: {}", seed);
 
-        println!("G range: {:?}", g_range);
-        println!("Beta range: {:?}", beta_range);
-        println!("Gamma range: {:?}", gamma_range);
+    match experiment {
+        Experiment::GammaLatency {
+            beta,
+            g,
+            gamma_range: gamma_range_arg,
+        } => {
+            let gamma_range = parse_field_range("gamma_range", gamma_range_arg, Some(0.5))?;
+            let beta_range = vec![*beta];
+            let g_range = vec![*g];
 
-        let timestamps = sample_monte_carlo_execution_timestamps::<HONEST_COUNT, ADVERSARY_COUNT>(
-            args.monte_carlo,
-        );
-        let poem_data = simulate_poem(
-            timestamps.clone(),
-            args.monte_carlo,
-            args.error,
-            beta_range.clone(),
-            g_range.clone(),
-            gamma_range.clone(),
-            ReductionType::Gamma,
-        );
+            println!("G range: {:?}", g_range);
+            println!("Beta range: {:?}", beta_range);
+            println!("Gamma range: {:?}", gamma_range);
 
-        let bitcoin_data = simulate_bitcoin(
-            timestamps,
-            args.monte_carlo,
-            args.error,
-            beta_range.clone(),
-            g_range.clone(),
-        );
+            let poem_data = simulate_poem(
+                samples,
+                args.monte_carlo,
+                args.error,
+                beta_range.clone(),
+                g_range.clone(),
+                gamma_range.clone(),
+                ReductionType::Gamma,
+                &mut checkpoint,
+            );
 
-        let data = json!({
-            "monte_carlo": args.monte_carlo,
-            "error": args.error,
-            "beta": beta_range,
-            "g": g_range,
-            "gamma": gamma_range,
-            "poem_latency": poem_data.latency,
-            "bitcoin_latency": bitcoin_data.latency,
-        });
-        let json_string = serde_json::to_string_pretty(&data).unwrap();
-        let file_name = format!(
-            "simulation_data/poem_gamma_latency_beta_{}_g_{}_gamma_{}_monte_carlo_{}_error_{}.json",
-            args.beta.clone().unwrap(),
-            args.g.clone().unwrap(),
-            args.gamma_range.clone().unwrap(),
-            args.monte_carlo,
-            args.error
-        );
-        let mut file = File::create(file_name.clone()).unwrap();
+            let bitcoin_data = simulate_bitcoin(
+                samples,
+                args.monte_carlo,
+                args.error,
+                beta_range.clone(),
+                g_range.clone(),
+                ReductionType::Beta,
+                &mut checkpoint,
+            );
 
-        file.write_all(json_string.as_bytes()).unwrap();
-        println!("Wrote to file: {}", file_name);
-    }
+            let data = json!({
+                "monte_carlo": args.monte_carlo,
+                "error": args.error,
+                "seed": seed,
+                "variance_reduction": samples.variance_reduction.modes(),
+                "sequential": samples.sequential,
+                "beta": beta_range,
+                "g": g_range,
+                "gamma": gamma_range,
+                "poem_latency": poem_data.latency,
+                "bitcoin_latency": bitcoin_data.latency,
+                "poem_samples": poem_data.monte_carlo,
+                "bitcoin_samples": bitcoin_data.monte_carlo,
+            });
+            let file_name = format!(
+                "{}/poem_gamma_latency_beta_{}_g_{}_gamma_{}_monte_carlo_{}_error_{}.json",
+                args.output_directory, *beta, *g, gamma_range_arg, args.monte_carlo, args.error
+            );
+            write_output(&file_name, &data, args.shard)?;
+        }
 
-    if args.g_latency {
-        let g_range = parse_range(args.g_range.clone().unwrap(), None).unwrap();
-        let beta_range = vec![args.beta.clone().unwrap()];
-        let gamma_range = vec![args.gamma.clone().unwrap()];
+        Experiment::GLatency {
+            beta,
+            gamma,
+            g_range: g_range_arg,
+        } => {
+            let g_range = parse_field_range("g_range", g_range_arg, None)?;
+            let beta_range = vec![*beta];
+            let gamma_range = vec![*gamma];
 
-        println!("G range: {:?}", g_range);
-        println!("Beta: {:?}", beta_range);
-        println!("Gamma: {:?}", gamma_range);
+            println!("G range: {:?}", g_range);
+            println!("Beta: {:?}", beta_range);
+            println!("Gamma: {:?}", gamma_range);
 
-        let timestamps = sample_monte_carlo_execution_timestamps::<HONEST_COUNT, ADVERSARY_COUNT>(
-            args.monte_carlo,
-        );
-        let poem_data = simulate_poem(
-            timestamps,
-            args.monte_carlo,
-            args.error,
-            beta_range.clone(),
-            g_range.clone(),
-            gamma_range.clone(),
-            ReductionType::G,
-        );
+            let poem_data = simulate_poem(
+                samples,
+                args.monte_carlo,
+                args.error,
+                beta_range.clone(),
+                g_range.clone(),
+                gamma_range.clone(),
+                ReductionType::G,
+                &mut checkpoint,
+            );
 
-        let data = json!({
-            "monte_carlo": args.monte_carlo,
-            "error": args.error,
-            "beta": beta_range,
-            "g": g_range,
-            "gamma": gamma_range,
-            "latency": poem_data.latency,
-            "optimal_k": poem_data.optimal_k,
-            "optimal_g": poem_data.optimal_g,
-            "optimal_gamma": poem_data.optimal_gamma,
-            "throughput": poem_data.throughput,
-            "max_work": poem_data.max_work,
-            "max_height": poem_data.max_height,
-            "adversary_max_work": poem_data.adversary_max_work,
-            "adversary_max_height": poem_data.adversary_max_height,
-        });
-        let json_string = serde_json::to_string_pretty(&data).unwrap();
-        let file_name = format!(
-            "simulation_data/poem_g_latency_beta_{}_g_{}_gamma_{}_monte_carlo_{}_error_{}.json",
-            args.beta.clone().unwrap(),
-            args.g_range.clone().unwrap(),
-            args.gamma.clone().unwrap(),
-            args.monte_carlo,
-            args.error
-        );
-        let mut file = File::create(file_name.clone()).unwrap();
+            let data = json!({
+                "monte_carlo": args.monte_carlo,
+                "error": args.error,
+                "seed": seed,
+                "variance_reduction": samples.variance_reduction.modes(),
+                "sequential": samples.sequential,
+                "beta": beta_range,
+                "g": g_range,
+                "gamma": gamma_range,
+                "latency": poem_data.latency,
+                "optimal_k": poem_data.optimal_k,
+                "optimal_g": poem_data.optimal_g,
+                "optimal_gamma": poem_data.optimal_gamma,
+                "throughput": poem_data.throughput,
+                "max_work": poem_data.max_work,
+                "max_height": poem_data.max_height,
+                "adversary_max_work": poem_data.adversary_max_work,
+                "adversary_max_height": poem_data.adversary_max_height,
+                "samples": poem_data.monte_carlo,
+            });
+            let file_name = format!(
+                "{}/poem_g_latency_beta_{}_g_{}_gamma_{}_monte_carlo_{}_error_{}.json",
+                args.output_directory, *beta, g_range_arg, *gamma, args.monte_carlo, args.error
+            );
+            write_output(&file_name, &data, args.shard)?;
+        }
 
-        file.write_all(json_string.as_bytes()).unwrap();
-        println!("Wrote to file: {}", file_name);
-    }
+        Experiment::Poem {
+            beta_range: beta_range_arg,
+            g_range: g_range_arg,
+            gamma_range: gamma_range_arg,
+        } => {
+            let beta_range = parse_field_range("beta_range", beta_range_arg, None)?;
+            let g_range = parse_field_range("g_range", g_range_arg, Some(0.5))?;
+            let gamma_range = parse_field_range("gamma_range", gamma_range_arg, Some(0.5))?;
+            println!("Beta range: {:?}", beta_range);
+            println!("G range: {:?}", g_range);
+            println!("Gamma range: {:?}", gamma_range);
 
-    if args.poem {
-        let beta_range = parse_range(args.beta_range.clone().unwrap(), None).unwrap();
-        let g_range = parse_range(args.g_range.clone().unwrap(), Some(0.5)).unwrap();
-        let gamma_range = parse_range(args.gamma_range.clone().unwrap(), Some(0.5)).unwrap();
-        println!("Beta range: {:?}", beta_range);
-        println!("G range: {:?}", g_range);
-        println!("Gamma range: {:?}", gamma_range);
+            let poem_data = simulate_poem(
+                samples,
+                args.monte_carlo,
+                args.error,
+                beta_range.clone(),
+                g_range.clone(),
+                gamma_range.clone(),
+                ReductionType::Beta,
+                &mut checkpoint,
+            );
 
-        let timestamps = sample_monte_carlo_execution_timestamps::<HONEST_COUNT, ADVERSARY_COUNT>(
-            args.monte_carlo,
-        );
-        let poem_data = simulate_poem(
-            timestamps,
-            args.monte_carlo,
-            args.error,
-            beta_range.clone(),
-            g_range.clone(),
-            gamma_range.clone(),
-            ReductionType::Beta,
-        );
+            let data = json!({
+                "monte_carlo": args.monte_carlo,
+                "error": args.error,
+                "seed": seed,
+                "variance_reduction": samples.variance_reduction.modes(),
+                "sequential": samples.sequential,
+                "beta": beta_range,
+                "g": g_range,
+                "gamma": gamma_range,
+                "latency": poem_data.latency,
+                "optimal_k": poem_data.optimal_k,
+                "optimal_g": poem_data.optimal_g,
+                "optimal_gamma": poem_data.optimal_gamma,
+                "throughput": poem_data.throughput,
+                "max_work": poem_data.max_work,
+                "max_height": poem_data.max_height,
+                "adversary_max_work": poem_data.adversary_max_work,
+                "adversary_max_height": poem_data.adversary_max_height,
+                "samples": poem_data.monte_carlo,
+            });
+            let file_name = format!(
+                "{}/poem_beta_{}_g_{}_gamma_{}_monte_carlo_{}_error_{}.json",
+                args.output_directory,
+                beta_range_arg,
+                g_range_arg,
+                gamma_range_arg,
+                args.monte_carlo,
+                args.error
+            );
+            write_output(&file_name, &data, args.shard)?;
+        }
 
-        let data = json!({
-            "monte_carlo": args.monte_carlo,
-            "error": args.error,
-            "beta": beta_range,
-            "g": g_range,
-            "gamma": gamma_range,
-            "latency": poem_data.latency,
-            "optimal_k": poem_data.optimal_k,
-            "optimal_g": poem_data.optimal_g,
-            "optimal_gamma": poem_data.optimal_gamma,
-            "throughput": poem_data.throughput,
-            "max_work": poem_data.max_work,
-            "max_height": poem_data.max_height,
-            "adversary_max_work": poem_data.adversary_max_work,
-            "adversary_max_height": poem_data.adversary_max_height,
-        });
-        let json_string = serde_json::to_string_pretty(&data).unwrap();
-        let file_name = format!(
-            "simulation_data/poem_beta_{}_g_{}_gamma_{}_monte_carlo_{}_error_{}.json",
-            args.beta_range.clone().unwrap(),
-            args.g_range.clone().unwrap(),
-            args.gamma_range.clone().unwrap(),
-            args.monte_carlo,
-            args.error
-        );
-        let mut file = File::create(file_name.clone()).unwrap();
+        Experiment::BitcoinVsPoem {
+            beta_range: beta_range_arg,
+            g_range: g_range_arg,
+            gamma_range: gamma_range_arg,
+        } => {
+            let beta_range = parse_field_range("beta_range", beta_range_arg, None)?;
+            let g_range = parse_field_range("g_range", g_range_arg, Some(0.5))?;
+            let gamma_range = parse_field_range("gamma_range", gamma_range_arg, Some(0.5))?;
+            println!("Beta range: {:?}", beta_range);
+            println!("G range: {:?}", g_range);
+            println!("Gamma range: {:?}", gamma_range);
 
-        file.write_all(json_string.as_bytes()).unwrap();
-        println!("Wrote to file: {}", file_name);
-    }
+            let poem_data = simulate_poem(
+                samples,
+                args.monte_carlo,
+                args.error,
+                beta_range.clone(),
+                g_range.clone(),
+                gamma_range.clone(),
+                ReductionType::Beta,
+                &mut checkpoint,
+            );
 
-    if args.bitcoin_vs_poem {
-        let beta_range = parse_range(args.beta_range.clone().unwrap(), None).unwrap();
-        let g_range = parse_range(args.g_range.clone().unwrap(), Some(0.5)).unwrap();
-        let gamma_range = parse_range(args.gamma_range.clone().unwrap(), Some(0.5)).unwrap();
-        println!("Beta range: {:?}", beta_range);
-        println!("G range: {:?}", g_range);
-        println!("Gamma range: {:?}", gamma_range);
+            let bitcoin_data = simulate_bitcoin(
+                samples,
+                args.monte_carlo,
+                args.error,
+                beta_range.clone(),
+                g_range.clone(),
+                ReductionType::Beta,
+                &mut checkpoint,
+            );
 
-        let timestamps = sample_monte_carlo_execution_timestamps::<HONEST_COUNT, ADVERSARY_COUNT>(
-            args.monte_carlo,
-        );
-        let poem_data = simulate_poem(
-            timestamps.clone(),
-            args.monte_carlo,
-            args.error,
-            beta_range.clone(),
-            g_range.clone(),
-            gamma_range.clone(),
-            ReductionType::Beta,
-        );
+            let data = json!({
+                "monte_carlo": args.monte_carlo,
+                "error": args.error,
+                "seed": seed,
+                "variance_reduction": samples.variance_reduction.modes(),
+                "sequential": samples.sequential,
+                "beta": beta_range,
+                "g": g_range,
+                "gamma": gamma_range,
 
-        let bitcoin_data = simulate_bitcoin(
-            timestamps,
-            args.monte_carlo,
-            args.error,
-            beta_range.clone(),
-            g_range.clone(),
-        );
+                "bitcoin_latency": bitcoin_data.latency,
+                "bitcoin_optimal_k": bitcoin_data.optimal_k,
+                "bitcoin_optimal_g": bitcoin_data.optimal_g,
+                "bitcoin_throughput": bitcoin_data.throughput,
+                "bitcoin_max_work": bitcoin_data.max_work,
+                "bitcoin_max_height": bitcoin_data.max_height,
+                "bitcoin_adversary_max_work": bitcoin_data.adversary_max_work,
+                "bitcoin_adversary_max_height": bitcoin_data.adversary_max_height,
+                "bitcoin_samples": bitcoin_data.monte_carlo,
+
+                "poem_latency": poem_data.latency,
+                "poem_optimal_k": poem_data.optimal_k,
+                "poem_optimal_g": poem_data.optimal_g,
+                "poem_optimal_gamma": poem_data.optimal_gamma,
+                "poem_throughput": poem_data.throughput,
+                "poem_max_work": poem_data.max_work,
+                "poem_max_height": poem_data.max_height,
+                "poem_adversary_max_work": poem_data.adversary_max_work,
+                "poem_adversary_max_height": poem_data.adversary_max_height,
+                "poem_samples": poem_data.monte_carlo,
+            });
+            let file_name = format!(
+                "{}/bitcoin_vs_poem_beta_{}_g_{}_gamma_{}_monte_carlo_{}_error_{}.json",
+                args.output_directory,
+                beta_range_arg,
+                g_range_arg,
+                gamma_range_arg,
+                args.monte_carlo,
+                args.error
+            );
+            write_output(&file_name, &data, args.shard)?;
+        }
+
+        Experiment::Dag {
+            beta_range: beta_range_arg,
+            g_range: g_range_arg,
+            gamma_range: gamma_range_arg,
+        } => {
+            let beta_range = parse_field_range("beta_range", beta_range_arg, None)?;
+            let g_range = parse_field_range("g_range", g_range_arg, Some(0.5))?;
+            let gamma_range = parse_field_range("gamma_range", gamma_range_arg, Some(0.5))?;
+            println!("Beta range: {:?}", beta_range);
+            println!("G range: {:?}", g_range);
+            println!("Gamma range: {:?}", gamma_range);
+
+            let poem_data = simulate_poem(
+                samples,
+                args.monte_carlo,
+                args.error,
+                beta_range.clone(),
+                g_range.clone(),
+                gamma_range.clone(),
+                ReductionType::Beta,
+                &mut checkpoint,
+            );
+
+            let dag_data = simulate_poem_dag(
+                samples,
+                args.monte_carlo,
+                args.error,
+                beta_range.clone(),
+                g_range.clone(),
+                gamma_range.clone(),
+                ReductionType::Beta,
+                &mut checkpoint,
+            );
+
+            let data = json!({
+                "monte_carlo": args.monte_carlo,
+                "error": args.error,
+                "seed": seed,
+                "variance_reduction": samples.variance_reduction.modes(),
+                "sequential": samples.sequential,
+                "beta": beta_range,
+                "g": g_range,
+                "gamma": gamma_range,
+
+                "poem_latency": poem_data.latency,
+                "poem_optimal_k": poem_data.optimal_k,
+                "poem_optimal_g": poem_data.optimal_g,
+                "poem_optimal_gamma": poem_data.optimal_gamma,
+                "poem_throughput": poem_data.throughput,
+                "poem_max_work": poem_data.max_work,
+                "poem_max_height": poem_data.max_height,
+                "poem_adversary_max_work": poem_data.adversary_max_work,
+                "poem_adversary_max_height": poem_data.adversary_max_height,
+                "poem_samples": poem_data.monte_carlo,
+
+                "dag_latency": dag_data.latency,
+                "dag_optimal_k": dag_data.optimal_k,
+                "dag_optimal_g": dag_data.optimal_g,
+                "dag_optimal_gamma": dag_data.optimal_gamma,
+                "dag_throughput": dag_data.throughput,
+                "dag_max_work": dag_data.max_work,
+                "dag_max_height": dag_data.max_height,
+                "dag_adversary_max_work": dag_data.adversary_max_work,
+                "dag_adversary_max_height": dag_data.adversary_max_height,
+                "dag_samples": dag_data.monte_carlo,
+            });
+            let file_name = format!(
+                "{}/dag_vs_poem_beta_{}_g_{}_gamma_{}_monte_carlo_{}_error_{}.json",
+                args.output_directory,
+                beta_range_arg,
+                g_range_arg,
+                gamma_range_arg,
+                args.monte_carlo,
+                args.error
+            );
+            write_output(&file_name, &data, args.shard)?;
+        }
+
+        Experiment::MergeMining {
+            beta_range: beta_range_arg,
+            g_range: g_range_arg,
+            gamma_range: gamma_range_arg,
+            honest_split,
+            adversary_split_range: adversary_split_range_arg,
+            merge_mined_adversary,
+        } => {
+            let beta_range = parse_field_range("beta_range", beta_range_arg, None)?;
+            let g_range = parse_field_range("g_range", g_range_arg, Some(0.5))?;
+            let gamma_range = parse_field_range("gamma_range", gamma_range_arg, Some(0.5))?;
+            let adversary_split_range =
+                parse_field_range("adversary_split_range", adversary_split_range_arg, None)?;
+            println!("Beta range: {:?}", beta_range);
+            println!("G range: {:?}", g_range);
+            println!("Gamma range: {:?}", gamma_range);
+            println!("Honest split: {:?}", *honest_split);
+            println!("Adversary split range: {:?}", adversary_split_range);
+
+            let merge_mining_data = simulate_merge_mining(
+                [&Bitcoin, &Poem],
+                samples,
+                args.monte_carlo,
+                args.error,
+                beta_range.clone(),
+                g_range.clone(),
+                gamma_range.clone(),
+                *honest_split,
+                adversary_split_range.clone(),
+                *merge_mined_adversary,
+                &mut checkpoint,
+            )?;
+            let [bitcoin_data, poem_data] = &merge_mining_data.chains;
+
+            let data = json!({
+                "monte_carlo": args.monte_carlo,
+                "error": args.error,
+                "seed": seed,
+                "variance_reduction": samples.variance_reduction.modes(),
+                "sequential": samples.sequential,
+                "beta": beta_range,
+                "g": g_range,
+                "gamma": gamma_range,
+                "honest_split": *honest_split,
+                "adversary_split": adversary_split_range,
+                "merge_mined_adversary": *merge_mined_adversary,
+                // The adversary's shares on the Bitcoin and PoEM chains of each row
+                "adversary_shares": merge_mining_data.adversary_shares,
+
+                "bitcoin_latency": bitcoin_data.iter().map(|data| &data.latency).collect::<Vec<_>>(),
+                "bitcoin_optimal_k": bitcoin_data.iter().map(|data| &data.optimal_k).collect::<Vec<_>>(),
+                "bitcoin_optimal_g": bitcoin_data.iter().map(|data| &data.optimal_g).collect::<Vec<_>>(),
+                "bitcoin_throughput": bitcoin_data.iter().map(|data| &data.throughput).collect::<Vec<_>>(),
+                "bitcoin_samples": bitcoin_data.iter().map(|data| &data.monte_carlo).collect::<Vec<_>>(),
+
+                "poem_latency": poem_data.iter().map(|data| &data.latency).collect::<Vec<_>>(),
+                "poem_optimal_k": poem_data.iter().map(|data| &data.optimal_k).collect::<Vec<_>>(),
+                "poem_optimal_g": poem_data.iter().map(|data| &data.optimal_g).collect::<Vec<_>>(),
+                "poem_optimal_gamma": poem_data.iter().map(|data| &data.optimal_gamma).collect::<Vec<_>>(),
+                "poem_throughput": poem_data.iter().map(|data| &data.throughput).collect::<Vec<_>>(),
+                "poem_samples": poem_data.iter().map(|data| &data.monte_carlo).collect::<Vec<_>>(),
+            });
+            let honest_split = match *honest_split {
+                Some(split) => split.to_string(),
+                None => "merged".to_string(),
+            };
+            let file_name = format!(
+                "{}/merge_mining_beta_{}_g_{}_gamma_{}_honest_split_{}_adversary_split_{}{}_monte_carlo_{}_error_{}.json",
+                args.output_directory,
+                beta_range_arg,
+                g_range_arg,
+                gamma_range_arg,
+                honest_split,
+                adversary_split_range_arg,
+                if *merge_mined_adversary { "_merged" } else { "" },
+                args.monte_carlo,
+                args.error
+            );
+            write_output(&file_name, &data, args.shard)?;
+        }
+
+        Experiment::Optimize {
+            beta_range: beta_range_arg,
+            g_range: g_range_arg,
+            gamma_range: gamma_range_arg,
+            tolerance,
+        } => {
+            let beta_range = parse_field_range("beta_range", beta_range_arg, None)?;
+            let g_range = parse_field_range("g_range", g_range_arg, None)?;
+            let gamma_range = parse_field_range("gamma_range", gamma_range_arg, None)?;
+            // Only the endpoints of the g and gamma ranges are used, as search bounds
+            let g_bounds = (g_range[0], *g_range.last().unwrap());
+            let gamma_bounds = (gamma_range[0], *gamma_range.last().unwrap());
+            println!("Beta range: {:?}", beta_range);
+            println!("G bounds: {:?}", g_bounds);
+            println!("Gamma bounds: {:?}", gamma_bounds);
+
+            let poem_data = optimize_protocol(
+                &Poem,
+                samples,
+                args.error,
+                beta_range.clone(),
+                g_bounds,
+                gamma_bounds,
+                *tolerance,
+            );
+            let bitcoin_data = optimize_protocol(
+                &Bitcoin,
+                samples,
+                args.error,
+                beta_range.clone(),
+                g_bounds,
+                gamma_bounds,
+                *tolerance,
+            );
+
+            let data = json!({
+                "monte_carlo": args.monte_carlo,
+                "error": args.error,
+                "seed": seed,
+                "variance_reduction": samples.variance_reduction.modes(),
+                "sequential": samples.sequential,
+                "tolerance": *tolerance,
+                "beta": beta_range,
+                "g_bounds": g_bounds,
+                "gamma_bounds": gamma_bounds,
+
+                "bitcoin_latency": bitcoin_data.latency,
+                "bitcoin_latency_error": bitcoin_data.latency_error,
+                "bitcoin_optimal_k": bitcoin_data.optimal_k,
+                "bitcoin_optimal_g": bitcoin_data.optimal_g,
+                "bitcoin_optimal_g_band": bitcoin_data.optimal_g_band,
+                "bitcoin_throughput": bitcoin_data.throughput,
+                "bitcoin_evaluations": bitcoin_data.evaluations,
+
+                "poem_latency": poem_data.latency,
+                "poem_latency_error": poem_data.latency_error,
+                "poem_optimal_k": poem_data.optimal_k,
+                "poem_optimal_g": poem_data.optimal_g,
+                "poem_optimal_g_band": poem_data.optimal_g_band,
+                "poem_optimal_gamma": poem_data.optimal_gamma,
+                "poem_optimal_gamma_band": poem_data.optimal_gamma_band,
+                "poem_throughput": poem_data.throughput,
+                "poem_evaluations": poem_data.evaluations,
+            });
+            let file_name = format!(
+                "{}/optimize_beta_{}_g_{}_gamma_{}_tolerance_{}_monte_carlo_{}_error_{}.json",
+                args.output_directory,
+                beta_range_arg,
+                g_range_arg,
+                gamma_range_arg,
+                *tolerance,
+                args.monte_carlo,
+                args.error
+            );
+            write_output(&file_name, &data, args.shard)?;
+        }
+
+        Experiment::Refine {
+            beta_range: beta_range_arg,
+            g_range: g_range_arg,
+            gamma_range: gamma_range_arg,
+            refinement_points,
+            threshold,
+        } => {
+            let beta_range = parse_field_range("beta_range", beta_range_arg, None)?;
+            let g_range = parse_field_range("g_range", g_range_arg, Some(0.5))?;
+            let gamma_range = parse_field_range("gamma_range", gamma_range_arg, Some(0.5))?;
+            println!("Beta range: {:?}", beta_range);
+            println!("G range: {:?}", g_range);
+            println!("Gamma range: {:?}", gamma_range);
+
+            let poem_data = refine_protocol(
+                &Poem,
+                samples,
+                args.error,
+                beta_range.clone(),
+                g_range.clone(),
+                gamma_range.clone(),
+                *refinement_points,
+                *threshold,
+            );
+            let bitcoin_data = refine_protocol(
+                &Bitcoin,
+                samples,
+                args.error,
+                beta_range.clone(),
+                g_range.clone(),
+                gamma_range.clone(),
+                *refinement_points,
+                *threshold,
+            );
+
+            let data = json!({
+                "monte_carlo": args.monte_carlo,
+                "error": args.error,
+                "seed": seed,
+                "variance_reduction": samples.variance_reduction.modes(),
+                "sequential": samples.sequential,
+                "refinement_points": *refinement_points,
+                "threshold": *threshold,
+                "beta": beta_range,
+                "g": g_range,
+                "gamma": gamma_range,
+
+                "bitcoin_latency": bitcoin_data.latency,
+                "bitcoin_optimal_k": bitcoin_data.optimal_k,
+                "bitcoin_optimal_g": bitcoin_data.optimal_g,
+                "bitcoin_throughput": bitcoin_data.throughput,
+                "bitcoin_refinements": bitcoin_data.refinements,
+                "bitcoin_points": grid_points_json(&bitcoin_data.points),
+
+                "poem_latency": poem_data.latency,
+                "poem_optimal_k": poem_data.optimal_k,
+                "poem_optimal_g": poem_data.optimal_g,
+                "poem_optimal_gamma": poem_data.optimal_gamma,
+                "poem_throughput": poem_data.throughput,
+                "poem_refinements": poem_data.refinements,
+                "poem_points": grid_points_json(&poem_data.points),
+            });
+            let file_name = format!(
+                "{}/refine_beta_{}_g_{}_gamma_{}_threshold_{}_monte_carlo_{}_error_{}.json",
+                args.output_directory,
+                beta_range_arg,
+                g_range_arg,
+                gamma_range_arg,
+                *threshold,
+                args.monte_carlo,
+                args.error
+            );
+            write_output(&file_name, &data, args.shard)?;
+        }
+
+        Experiment::Pareto {
+            beta_range: beta_range_arg,
+            g_range: g_range_arg,
+            gamma_range: gamma_range_arg,
+            error_range: error_range_arg,
+        } => {
+            let beta_range = parse_field_range("beta_range", beta_range_arg, None)?;
+            let g_range = parse_field_range("g_range", g_range_arg, Some(0.5))?;
+            let gamma_range = parse_field_range("gamma_range", gamma_range_arg, Some(0.5))?;
+            let error_range = parse_field_range("error_range", error_range_arg, None)?;
+            println!("Beta range: {:?}", beta_range);
+            println!("G range: {:?}", g_range);
+            println!("Gamma range: {:?}", gamma_range);
+            println!("Error range: {:?}", error_range);
+
+            let poem_data = simulate_pareto(
+                &Poem,
+                samples,
+                beta_range.clone(),
+                g_range.clone(),
+                gamma_range.clone(),
+                error_range.clone(),
+            );
+            let bitcoin_data = simulate_pareto(
+                &Bitcoin,
+                samples,
+                beta_range.clone(),
+                g_range.clone(),
+                gamma_range.clone(),
+                error_range.clone(),
+            );
 
-        let data = json!({
-            "monte_carlo": args.monte_carlo,
-            "error": args.error,
-            "beta": beta_range,
-            "g": g_range,
-            "gamma": gamma_range,
-
-            "bitcoin_latency": bitcoin_data.latency,
-            "bitcoin_optimal_k": bitcoin_data.optimal_k,
-            "bitcoin_optimal_g": bitcoin_data.optimal_g,
-            "bitcoin_throughput": bitcoin_data.throughput,
-            "bitcoin_max_work": bitcoin_data.max_work,
-            "bitcoin_max_height": bitcoin_data.max_height,
-            "bitcoin_adversary_max_work": bitcoin_data.adversary_max_work,
-            "bitcoin_adversary_max_height": bitcoin_data.adversary_max_height,
-
-            "poem_latency": poem_data.latency,
-            "poem_optimal_k": poem_data.optimal_k,
-            "poem_optimal_g": poem_data.optimal_g,
-            "poem_optimal_gamma": poem_data.optimal_gamma,
-            "poem_throughput": poem_data.throughput,
-            "poem_max_work": poem_data.max_work,
-            "poem_max_height": poem_data.max_height,
-            "poem_adversary_max_work": poem_data.adversary_max_work,
-            "poem_adversary_max_height": poem_data.adversary_max_height,
-        });
-        let json_string = serde_json::to_string_pretty(&data).unwrap();
-        let file_name = format!(
-            "simulation_data/bitcoin_vs_poem_beta_{}_g_{}_gamma_{}_monte_carlo_{}_error_{}.json",
-            args.beta_range.clone().unwrap(),
-            args.g_range.clone().unwrap(),
-            args.gamma_range.clone().unwrap(),
-            args.monte_carlo,
-            args.error
+            let data = json!({
+                "monte_carlo": args.monte_carlo,
+                "error": error_range,
+                "seed": seed,
+                "variance_reduction": samples.variance_reduction.modes(),
+                "sequential": samples.sequential,
+                "beta": beta_range,
+                "g": g_range,
+                "gamma": gamma_range,
+                "bitcoin_configurations": pareto_json(&bitcoin_data),
+                "poem_configurations": pareto_json(&poem_data),
+            });
+            let file_name = format!(
+                "{}/pareto_beta_{}_g_{}_gamma_{}_monte_carlo_{}_error_{}.json",
+                args.output_directory,
+                beta_range_arg,
+                g_range_arg,
+                gamma_range_arg,
+                args.monte_carlo,
+                error_range_arg
+            );
+            write_output(&file_name, &data, args.shard)?;
+        }
+
+        Experiment::FullGrid {
+            beta_range: beta_range_arg,
+            g_range: g_range_arg,
+            gamma_range: gamma_range_arg,
+        } => {
+            let beta_range = parse_field_range("beta_range", beta_range_arg, None)?;
+            let g_range = parse_field_range("g_range", g_range_arg, Some(0.5))?;
+            let gamma_range = parse_field_range("gamma_range", gamma_range_arg, Some(0.5))?;
+            println!("Beta range: {:?}", beta_range);
+            println!("G range: {:?}", g_range);
+            println!("Gamma range: {:?}", gamma_range);
+
+            let poem_data = simulate_poem(
+                samples,
+                args.monte_carlo,
+                args.error,
+                beta_range.clone(),
+                g_range.clone(),
+                gamma_range.clone(),
+                ReductionType::Full,
+                &mut checkpoint,
+            );
+            let bitcoin_data = simulate_bitcoin(
+                samples,
+                args.monte_carlo,
+                args.error,
+                beta_range.clone(),
+                g_range.clone(),
+                ReductionType::Full,
+                &mut checkpoint,
+            );
+
+            // One row per protocol and grid cell
+            let poem_grid = get_full_grid(&beta_range, &g_range, &gamma_range);
+            let bitcoin_grid = get_full_grid(&beta_range, &g_range, &[0.0]);
+            let grid: Vec<&(f64, f64, f64)> = bitcoin_grid.iter().chain(poem_grid.iter()).collect();
+            let protocol: Vec<&str> = std::iter::repeat_n("bitcoin", bitcoin_grid.len())
+                .chain(std::iter::repeat_n("poem", poem_grid.len()))
+                .collect();
+
+            let data = json!({
+                "monte_carlo": args.monte_carlo,
+                "error": args.error,
+                "seed": seed,
+                "variance_reduction": samples.variance_reduction.modes(),
+                "sequential": samples.sequential,
+                "protocol": protocol,
+                "beta": grid.iter().map(|cell| cell.0).collect::<Vec<_>>(),
+                "g": grid.iter().map(|cell| cell.1).collect::<Vec<_>>(),
+                "gamma": grid.iter().map(|cell| cell.2).collect::<Vec<_>>(),
+                "k": ([bitcoin_data.optimal_k, poem_data.optimal_k].concat()),
+                "latency": ([bitcoin_data.latency, poem_data.latency].concat()),
+                "throughput": ([bitcoin_data.throughput, poem_data.throughput].concat()),
+                "max_work": ([bitcoin_data.max_work, poem_data.max_work].concat()),
+                "max_height": ([bitcoin_data.max_height, poem_data.max_height].concat()),
+                "adversary_max_work": ([bitcoin_data.adversary_max_work, poem_data.adversary_max_work].concat()),
+                "adversary_max_height": ([bitcoin_data.adversary_max_height, poem_data.adversary_max_height].concat()),
+                "samples": ([bitcoin_data.monte_carlo, poem_data.monte_carlo].concat()),
+            });
+            let file_name = format!(
+                "{}/full_grid_beta_{}_g_{}_gamma_{}_monte_carlo_{}_error_{}.json",
+                args.output_directory,
+                beta_range_arg,
+                g_range_arg,
+                gamma_range_arg,
+                args.monte_carlo,
+                args.error
+            );
+            write_output(&file_name, &data, args.shard)?;
+        }
+
+        Experiment::Variance {
+            beta,
+            g,
+            gamma,
+            replications,
+        } => {
+            println!("Beta: {}", beta);
+            println!("G: {}", g);
+            println!("Gamma: {}", gamma);
+            println!("Replications: {}", replications);
+
+            let variance_data = simulate_variance(
+                seed,
+                args.monte_carlo,
+                args.error,
+                *beta,
+                *g,
+                *gamma,
+                *replications,
+            );
+            let estimate_json = |estimate: &Estimate| {
+                json!({
+                    "mean": estimate.mean,
+                    "variance": estimate.variance,
+                    "variance_reduction": estimate.variance_reduction,
+                })
+            };
+
+            let data = json!({
+                "monte_carlo": args.monte_carlo,
+                "error": args.error,
+                "seed": seed,
+                "beta": *beta,
+                "g": *g,
+                "gamma": *gamma,
+                "replications": *replications,
+                "variance_reduction": VARIANCE_REDUCTIONS.map(|(name, _)| name),
+                "poem_latency": estimate_json(&variance_data.poem_latency),
+                "bitcoin_latency": estimate_json(&variance_data.bitcoin_latency),
+                "latency_difference": estimate_json(&variance_data.latency_difference),
+            });
+            let file_name = format!(
+                "{}/variance_beta_{}_g_{}_gamma_{}_replications_{}_monte_carlo_{}_error_{}.json",
+                args.output_directory,
+                *beta,
+                *g,
+                *gamma,
+                *replications,
+                args.monte_carlo,
+                args.error
+            );
+            write_output(&file_name, &data, args.shard)?;
+        }
+
+        Experiment::QmcConvergence {
+            beta,
+            g,
+            gamma,
+            monte_carlo_range: monte_carlo_range_arg,
+            replications,
+        } => {
+            let monte_carlo_range: Vec<usize> =
+                parse_field_range("monte_carlo_range", monte_carlo_range_arg, Some(1.0))?
+                    .iter()
+                    .map(|n| n.round() as usize)
+                    .collect();
+            println!("Beta: {}", beta);
+            println!("G: {}", g);
+            println!("Gamma: {}", gamma);
+            println!("Monte Carlo range: {:?}", monte_carlo_range);
+            println!("Replications: {}", replications);
+
+            let convergence_data = simulate_convergence(
+                seed,
+                &monte_carlo_range,
+                args.error,
+                *beta,
+                *g,
+                *gamma,
+                *replications,
+            );
+            let estimates_json = |estimates: &[Estimate]| {
+                json!({
+                    "mean": estimates.iter().map(|estimate| &estimate.mean).collect::<Vec<_>>(),
+                    "variance": estimates.iter().map(|estimate| &estimate.variance).collect::<Vec<_>>(),
+                    "variance_reduction": estimates.iter().map(|estimate| &estimate.variance_reduction).collect::<Vec<_>>(),
+                })
+            };
+
+            let data = json!({
+                "error": args.error,
+                "seed": seed,
+                "beta": *beta,
+                "g": *g,
+                "gamma": *gamma,
+                "replications": *replications,
+                "sampler": SAMPLERS,
+                "monte_carlo": convergence_data.monte_carlo,
+                "poem_k": estimates_json(&convergence_data.poem_k),
+                "bitcoin_k": estimates_json(&convergence_data.bitcoin_k),
+                "poem_rate": convergence_data.poem_rate,
+                "bitcoin_rate": convergence_data.bitcoin_rate,
+            });
+            let file_name = format!(
+                "{}/qmc_convergence_beta_{}_g_{}_gamma_{}_replications_{}_monte_carlo_{}_error_{}.json",
+                args.output_directory,
+                *beta,
+                *g,
+                *gamma,
+                *replications,
+                monte_carlo_range_arg,
+                args.error
+            );
+            write_output(&file_name, &data, args.shard)?;
+        }
+
+        Experiment::Adversary {
+            beta_range: beta_range_arg,
+            g,
+            gamma,
+            protocol,
+            strategies,
+            policy,
+        } => {
+            let beta_range = parse_field_range("beta_range", beta_range_arg, None)?;
+            println!("Beta range: {:?}", beta_range);
+            println!("G: {}", g);
+            println!("Gamma: {}", gamma);
+            println!("Strategies: {:?}", strategies);
+            let policy = policy
+                .as_ref()
+                .map(|path| MdpPolicy::load(Path::new(path)))
+                .transpose()?;
+            if let Some(policy) = &policy {
+                println!("Policy: beta {}, gamma {}", policy.beta, policy.gamma);
+                // The steps of the policy are fractions of the mean block work at its gamma
+                if policy.gamma != *gamma {
+                    return Err(Error::InvalidParameter {
+                        field: "policy".to_string(),
+                        message: format!(
+                            "solved for gamma {}, the experiment runs gamma {}",
+                            policy.gamma, gamma
+                        ),
+                    });
+                }
+            }
+
+            let adversary_data = simulate_adversaries(
+                protocol.get(),
+                strategies,
+                policy.as_ref(),
+                samples,
+                args.monte_carlo,
+                args.error,
+                &beta_range,
+                *g,
+                *gamma,
+            );
+
+            let data = json!({
+                "monte_carlo": args.monte_carlo,
+                "error": args.error,
+                "seed": seed,
+                "variance_reduction": samples.variance_reduction.modes(),
+                "protocol": protocol,
+                "g": *g,
+                "gamma": *gamma,
+                "beta": beta_range,
+                "strategy": adversary_data.strategy,
+                "latency": adversary_data.latency,
+                "k": adversary_data.k,
+                "growth_rate": adversary_data.growth_rate,
+                "relative_revenue": adversary_data.relative_revenue,
+                "orphan_rate": adversary_data.orphan_rate,
+            });
+            let file_name = format!(
+                "{}/adversary_{}_beta_{}_g_{}_gamma_{}_monte_carlo_{}_error_{}.json",
+                args.output_directory,
+                protocol.get().name(),
+                beta_range_arg,
+                *g,
+                *gamma,
+                args.monte_carlo,
+                args.error
+            );
+            write_output(&file_name, &data, args.shard)?;
+        }
+
+        Experiment::Backbone {
+            beta_range: beta_range_arg,
+            g_range: g_range_arg,
+            gamma,
+            protocols,
+            strategy,
+            window,
+        } => {
+            let beta_range = parse_field_range("beta_range", beta_range_arg, None)?;
+            let g_range = parse_field_range("g_range", g_range_arg, None)?;
+            println!("Beta range: {:?}", beta_range);
+            println!("G range: {:?}", g_range);
+            println!("Gamma: {}", gamma);
+            println!("Strategy: {:?}", strategy);
+
+            for protocol in protocols {
+                let backbone_data = simulate_backbone(
+                    protocol.get(),
+                    *strategy,
+                    samples,
+                    &beta_range,
+                    &g_range,
+                    *gamma,
+                    *window,
+                );
+
+                let data = json!({
+                    "monte_carlo": args.monte_carlo,
+                    "seed": seed,
+                    "variance_reduction": samples.variance_reduction.modes(),
+                    "protocol": protocol,
+                    "strategy": strategy,
+                    "gamma": *gamma,
+                    "window": *window,
+                    "beta": beta_range,
+                    "g": g_range,
+                    "quantiles": QUANTILES,
+                    // The quantiles are over the first samples only, the means over all of them
+                    "quantile_samples": backbone_data.quantile_samples,
+                    "common_prefix_work": backbone_data.common_prefix_work,
+                    "common_prefix_height": backbone_data.common_prefix_height,
+                    "growth_rate": backbone_data.growth_rate,
+                    "adversary_fraction": backbone_data.adversary_fraction,
+                });
+                let file_name = format!(
+                    "{}/backbone_{}_beta_{}_g_{}_gamma_{}_monte_carlo_{}.json",
+                    args.output_directory,
+                    protocol.get().name(),
+                    beta_range_arg,
+                    g_range_arg,
+                    *gamma,
+                    args.monte_carlo
+                );
+                write_output(&file_name, &data, args.shard)?;
+            }
+        }
+
+        Experiment::Rounds {
+            beta_range: beta_range_arg,
+            g_range: g_range_arg,
+            gamma,
+            protocols,
+            parties,
+            queries,
+        } => {
+            let beta_range = parse_field_range("beta_range", beta_range_arg, None)?;
+            let g_range = parse_field_range("g_range", g_range_arg, None)?;
+            println!("Beta range: {:?}", beta_range);
+            println!("G range: {:?}", g_range);
+            println!("Gamma: {}", gamma);
+            println!("Parties: {}, queries: {}", parties, queries);
+
+            for protocol in protocols {
+                let round_data = simulate_rounds(
+                    protocol.get(),
+                    samples,
+                    args.monte_carlo,
+                    args.error,
+                    &beta_range,
+                    &g_range,
+                    *gamma,
+                    *parties,
+                    *queries,
+                );
+
+                let data = json!({
+                    "monte_carlo": args.monte_carlo,
+                    "error": args.error,
+                    "seed": seed,
+                    "variance_reduction": samples.variance_reduction.modes(),
+                    "protocol": protocol,
+                    "gamma": *gamma,
+                    "parties": *parties,
+                    "queries": *queries,
+                    "g": g_range,
+                    "rounds": round_data,
+                });
+                let file_name = format!(
+                    "{}/rounds_{}_beta_{}_g_{}_gamma_{}_parties_{}_queries_{}_monte_carlo_{}_error_{}.json",
+                    args.output_directory,
+                    protocol.get().name(),
+                    beta_range_arg,
+                    g_range_arg,
+                    *gamma,
+                    *parties,
+                    *queries,
+                    args.monte_carlo,
+                    args.error
+                );
+                write_output(&file_name, &data, args.shard)?;
+            }
+        }
+
+        Experiment::Resilience {
+            g_range: g_range_arg,
+            gamma_range: gamma_range_arg,
+            protocols,
+            tolerance,
+        } => {
+            let g_range = parse_field_range("g_range", g_range_arg, None)?;
+            let gamma_range = parse_field_range("gamma_range", gamma_range_arg, None)?;
+            println!("G range: {:?}", g_range);
+            println!("Gamma range: {:?}", gamma_range);
+
+            for protocol in protocols {
+                let protocol = protocol.get();
+                let resilience_data = simulate_resilience(
+                    protocol,
+                    samples,
+                    args.error,
+                    &g_range,
+                    &gamma_range,
+                    *tolerance,
+                );
+
+                let data = json!({
+                    "monte_carlo": args.monte_carlo,
+                    "error": args.error,
+                    "seed": seed,
+                    "variance_reduction": samples.variance_reduction.modes(),
+                    "g": g_range,
+                    "gamma": if protocol.has_bias() { gamma_range.clone() } else { vec![0.0] },
+                    "tolerance": *tolerance,
+                    "beta": resilience_data.beta,
+                    "k": resilience_data.k,
+                    "honest_growth_rate": resilience_data.honest_growth_rate,
+                    "adversary_growth_rate": resilience_data.adversary_growth_rate,
+                    "growth_beta": resilience_data.growth_beta,
+                });
+                let file_name = format!(
+                    "{}/resilience_{}_g_{}_gamma_{}_monte_carlo_{}_error_{}.json",
+                    args.output_directory,
+                    protocol.name(),
+                    g_range_arg,
+                    gamma_range_arg,
+                    args.monte_carlo,
+                    args.error
+                );
+                write_output(&file_name, &data, args.shard)?;
+            }
+        }
+
+        Experiment::Growth {
+            beta,
+            g_range: g_range_arg,
+            gamma_range: gamma_range_arg,
+            protocols,
+        } => {
+            let g_range = parse_field_range("g_range", g_range_arg, None)?;
+            let gamma_range = parse_field_range("gamma_range", gamma_range_arg, None)?;
+            println!("Beta: {}", beta);
+            println!("G range: {:?}", g_range);
+            println!("Gamma range: {:?}", gamma_range);
+
+            for protocol in protocols {
+                let protocol = protocol.get();
+                let growth_data = simulate_growth(protocol, samples, *beta, &g_range, &gamma_range);
+
+                let data = json!({
+                    "monte_carlo": args.monte_carlo,
+                    "seed": seed,
+                    "variance_reduction": samples.variance_reduction.modes(),
+                    "beta": *beta,
+                    "g": g_range,
+                    "gamma": if protocol.has_bias() { gamma_range.clone() } else { vec![0.0] },
+                    "confidence_level": CONFIDENCE_LEVEL,
+                    "honest": growth_data.honest,
+                    "adversary": growth_data.adversary,
+                    "adversary_analytic": "the scaling factor g beta / (1 - beta) E[work] of the sampled private chain, which its rate matches by construction, so the adversary is never flagged",
+                });
+                let file_name = format!(
+                    "{}/growth_{}_beta_{}_g_{}_gamma_{}_monte_carlo_{}.json",
+                    args.output_directory,
+                    protocol.name(),
+                    *beta,
+                    g_range_arg,
+                    gamma_range_arg,
+                    args.monte_carlo
+                );
+                write_output(&file_name, &data, args.shard)?;
+            }
+        }
+
+        Experiment::Stationary {
+            beta_range: beta_range_arg,
+            g,
+            gamma,
+            protocols,
+            burn_in,
+            target_height,
+        } => {
+            let beta_range = parse_field_range("beta_range", beta_range_arg, None)?;
+            println!("Beta range: {:?}", beta_range);
+            println!("G: {}", g);
+            println!("Gamma: {}", gamma);
+            let target = match target_height {
+                Some(height) => Target::Height(*height),
+                None => Target::Random { burn_in: *burn_in },
+            };
+
+            for protocol in protocols {
+                let protocol = protocol.get();
+                let stationary_data = simulate_stationary(
+                    protocol,
+                    samples,
+                    args.monte_carlo,
+                    args.error,
+                    &beta_range,
+                    *g,
+                    *gamma,
+                    target,
+                )?;
+
+                let data = json!({
+                    "monte_carlo": args.monte_carlo,
+                    "error": args.error,
+                    "seed": seed,
+                    "variance_reduction": samples.variance_reduction.modes(),
+                    "beta": beta_range,
+                    "g": *g,
+                    "gamma": if protocol.has_bias() { *gamma } else { 0.0 },
+                    "burn_in": *burn_in,
+                    "target_height": *target_height,
+                    "latency": stationary_data.latency,
+                    "k": stationary_data.k,
+                    "growth_rate": stationary_data.growth_rate,
+                    "stationary_latency": stationary_data.stationary_latency,
+                    "stationary_k": stationary_data.stationary_k,
+                    "stationary_growth_rate": stationary_data.stationary_growth_rate,
+                    "unreached": stationary_data.unreached,
+                });
+                let target_name = match target_height {
+                    Some(height) => format!("height_{}", height),
+                    None => format!("burn_in_{}", burn_in),
+                };
+                let file_name = format!(
+                    "{}/stationary_{}_beta_{}_g_{}_gamma_{}_{}_monte_carlo_{}_error_{}.json",
+                    args.output_directory,
+                    protocol.name(),
+                    beta_range_arg,
+                    *g,
+                    *gamma,
+                    target_name,
+                    args.monte_carlo,
+                    args.error
+                );
+                write_output(&file_name, &data, args.shard)?;
+            }
+        }
+
+        Experiment::Confirmation {
+            beta_range: beta_range_arg,
+            g,
+            gamma,
+            protocols,
+            rules,
+            hybrid_time,
+        } => {
+            let beta_range = parse_field_range("beta_range", beta_range_arg, None)?;
+            println!("Beta range: {:?}", beta_range);
+            println!("G: {}", g);
+            println!("Gamma: {}", gamma);
+
+            for protocol in protocols {
+                let protocol = protocol.get();
+                let confirmation_data = simulate_confirmation(
+                    protocol,
+                    samples,
+                    args.error,
+                    &beta_range,
+                    *g,
+                    *gamma,
+                    rules,
+                    *hybrid_time,
+                );
+
+                let mut rule_data = serde_json::Map::new();
+                for ((rule, parameter), latency) in rules
+                    .iter()
+                    .zip(confirmation_data.parameter)
+                    .zip(confirmation_data.latency)
+                {
+                    rule_data.insert(
+                        rule.name().to_string(),
+                        json!({ "parameter": parameter, "latency": latency }),
+                    );
+                }
+                let data = json!({
+                    "monte_carlo": args.monte_carlo,
+                    "error": args.error,
+                    "seed": seed,
+                    "variance_reduction": samples.variance_reduction.modes(),
+                    "beta": beta_range,
+                    "g": *g,
+                    "gamma": if protocol.has_bias() { *gamma } else { 0.0 },
+                    "hybrid_time": *hybrid_time,
+                    "growth_rate": confirmation_data.growth_rate,
+                    "height_growth_rate": confirmation_data.height_growth_rate,
+                    "rules": rule_data,
+                });
+                let file_name = format!(
+                    "{}/confirmation_{}_beta_{}_g_{}_gamma_{}_hybrid_time_{}_monte_carlo_{}_error_{}.json",
+                    args.output_directory,
+                    protocol.name(),
+                    beta_range_arg,
+                    *g,
+                    *gamma,
+                    *hybrid_time,
+                    args.monte_carlo,
+                    args.error
+                );
+                write_output(&file_name, &data, args.shard)?;
+            }
+        }
+
+        Experiment::Mdp {
+            beta_range: beta_range_arg,
+            gamma,
+            k_range: k_range_arg,
+            max_lead,
+            resolution,
+        } => {
+            let beta_range = parse_field_range("beta_range", beta_range_arg, None)?;
+            let k_range = parse_field_range("k_range", k_range_arg, None)?;
+            println!("Beta range: {:?}", beta_range);
+            println!("Gamma: {}", gamma);
+            println!("K range: {:?}", k_range);
+
+            let mut revenue = Vec::with_capacity(beta_range.len());
+            let mut double_spend_success = Vec::with_capacity(beta_range.len());
+            for &beta in &beta_range {
+                println!("beta: {}", beta);
+                let mdp = Mdp::new(beta, *gamma, *max_lead, *resolution);
+                let (beta_revenue, policy) = mdp.solve_revenue();
+                revenue.push(beta_revenue);
+                double_spend_success.push(mdp.solve_double_spend(&k_range));
+                let policy_file_name = format!(
+                    "{}/mdp_policy_beta_{}_gamma_{}_max_lead_{}_resolution_{}.json",
+                    args.output_directory, beta, *gamma, *max_lead, *resolution
+                );
+                write_output(&policy_file_name, &json!(policy), args.shard)?;
+            }
+
+            let data = json!({
+                "beta": beta_range,
+                "gamma": *gamma,
+                "k": k_range,
+                "max_lead": *max_lead,
+                "resolution": *resolution,
+                "relative_revenue": revenue,
+                "double_spend_success": double_spend_success,
+            });
+            let file_name = format!(
+                "{}/mdp_beta_{}_gamma_{}_k_{}_max_lead_{}_resolution_{}.json",
+                args.output_directory, beta_range_arg, *gamma, k_range_arg, *max_lead, *resolution
+            );
+            write_output(&file_name, &data, args.shard)?;
+        }
+    }
+    checkpoint.finish()
+}
+
+// Runs every experiment of the scenario with its own settings
+fn run_scenario(path: &Path, resume: bool, shard: Option<Shard>) -> Result<(), Error> {
+    let scenario = load_scenario(path).map_err(|error| match error {
+        Error::Io(error) => Error::Io(std::io::Error::new(
+            error.kind(),
+            format!("{}: {}", path.display(), error),
+        )),
+        error => error,
+    })?;
+    let settings = Settings {
+        monte_carlo: scenario.monte_carlo,
+        error: scenario.risk.error,
+        output_directory: scenario.output.directory.clone(),
+        seed: scenario.seed,
+        resume,
+        shard,
+        variance_reduction: scenario.variance_reduction.clone(),
+        precision: scenario.sequential.map(|sequential| sequential.precision),
+        max_monte_carlo: scenario
+            .sequential
+            .map(|sequential| sequential.max_monte_carlo),
+    };
+    for (index, experiment) in scenario.experiments.iter().enumerate() {
+        println!(
+            "Experiment {}/{}: {}",
+            index + 1,
+            scenario.experiments.len(),
+            experiment.name()
         );
-        let mut file = File::create(file_name.clone()).unwrap();
+        // The seed and resumption of a scenario only apply to the experiments that use them
+        let experiment_settings = Settings {
+            seed: settings.seed.filter(|_| experiment.uses_seed()),
+            resume: settings.resume && experiment.uses_checkpoint(),
+            output_directory: settings.output_directory.clone(),
+            variance_reduction: settings.variance_reduction.clone(),
+            ..settings
+        };
+        run(experiment, &experiment_settings)?;
+    }
+    Ok(())
+}
+
+// A scenario file sets everything but --resume and --shard, so the other settings given with
+// run would be silently dropped
+fn check_run_settings(matches: &ArgMatches) -> Result<(), Error> {
+    let run_matches = matches.subcommand_matches("run").unwrap_or(matches);
+    for id in [
+        "monte_carlo",
+        "error",
+        "output_directory",
+        "seed",
+        "variance_reduction",
+        "precision",
+        "max_monte_carlo",
+    ] {
+        if run_matches.value_source(id) == Some(ValueSource::CommandLine) {
+            return Err(Error::InvalidParameter {
+                field: id.to_string(),
+                message: "run takes it from the scenario file".to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|error| error.exit());
+    let start = std::time::Instant::now();
 
-        file.write_all(json_string.as_bytes()).unwrap();
-        println!("Wrote to file: {}", file_name);
+    let result = match &args.command {
+        Command::Experiment(experiment) => experiment
+            .validate(experiment.name())
+            .and_then(|_| run(experiment, &args.settings)),
+        Command::Run { scenario } => check_run_settings(&matches)
+            .and_then(|_| run_scenario(scenario, args.settings.resume, args.settings.shard)),
+        Command::Merge { shards } => merge(shards, &args.settings.output_directory),
+    };
+    if let Err(error) = result {
+        eprintln!("Error: {}", error);
+        std::process::exit(1);
     }
 
     let duration = start.elapsed().as_secs_f64();
@@ -0,0 +1,551 @@
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    analysis::{PartialPerformance, Performance, Statistics},
+    error::Error,
+    sampling::{Sequential, Shard, VarianceReduction},
+    scenario::Experiment,
+    types::INF,
+};
+
+// Everything besides the seed that determines the results of a run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunDescription {
+    pub experiment: Experiment,
+    pub monte_carlo: usize,
+    pub error: f64,
+    pub variance_reduction: VarianceReduction,
+    pub sequential: Option<Sequential>,
+    pub shard: Option<Shard>,
+}
+
+type Key = (String, [u64; 3]);
+
+// The performance of every grid point evaluated so far, appended to a JSON lines file as
+// soon as it is known, so that an interrupted sweep can be resumed where it stopped.
+// The first line holds the seed and a description of the run the points belong to.
+// The checkpoint of a shard also records the k samples and block sums of every point, and
+// is kept as the partial result that merge combines with the other shards.
+pub struct Checkpoint {
+    pub seed: u64,
+    path: Option<PathBuf>,
+    file: Option<File>,
+    points: HashMap<Key, Performance>,
+    // The number of samples each point was evaluated on
+    monte_carlo: HashMap<Key, usize>,
+    shard: bool,
+    // The first failed write, reported by finish rather than interrupting the sweep
+    error: Option<std::io::Error>,
+}
+
+// FNV-1a, which unlike the standard library hashers is stable across Rust versions
+pub(crate) fn get_description_hash(description: &str) -> u64 {
+    description
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+// One checkpoint file per distinct run, so that the experiments of a scenario do not collide
+pub fn get_checkpoint_path(directory: &str, run: &RunDescription) -> PathBuf {
+    let hash = get_description_hash(&serde_json::to_string(run).unwrap());
+    let name = match run.shard {
+        Some(shard) => format!(
+            "{}_{:016x}_shard_{}_of_{}.jsonl",
+            run.experiment.name(),
+            hash,
+            shard.index,
+            shard.count
+        ),
+        None => format!("{}_{:016x}.jsonl", run.experiment.name(), hash),
+    };
+    Path::new(directory).join("checkpoints").join(name)
+}
+
+fn get_key(protocol: &str, beta: f64, g: f64, gamma: f64) -> Key {
+    (
+        protocol.to_string(),
+        [beta.to_bits(), g.to_bits(), gamma.to_bits()],
+    )
+}
+
+// Infinite values are stored as null, which JSON has no number for
+fn get_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Null => Some(INF),
+        value => value.as_f64(),
+    }
+}
+
+fn get_numbers(value: &Value) -> Option<Vec<f64>> {
+    value.as_array()?.iter().map(get_number).collect()
+}
+
+fn parse_statistics(value: &Value) -> Option<Statistics> {
+    Some(Statistics {
+        count: value["count"].as_u64()? as usize,
+        sums: get_numbers(&value["sums"])?.try_into().ok()?,
+        control: get_numbers(&value["control"])?.try_into().ok()?,
+        cross: get_numbers(&value["cross"])?.try_into().ok()?,
+        // Absent from the points of runs without control variates
+        tail_control: match value.get("tail_control") {
+            Some(tail_control) => get_numbers(tail_control)?.try_into().ok()?,
+            None => [0.0; 2],
+        },
+    })
+}
+
+type Point = (Key, Performance, usize, Option<PartialPerformance>);
+
+fn parse_point(line: &str) -> Option<Point> {
+    let point: Value = serde_json::from_str(line).ok()?;
+    let values = get_numbers(&point["performance"])?;
+    if values.len() != 7 {
+        return None;
+    }
+    let key = get_key(
+        point["protocol"].as_str()?,
+        point["beta"].as_f64()?,
+        point["g"].as_f64()?,
+        point["gamma"].as_f64()?,
+    );
+    // Only the points of a shard carry the samples
+    let partial = match point.get("k") {
+        Some(k) => Some(PartialPerformance {
+            k: get_numbers(k)?,
+            tail_controls: match point.get("tail_controls") {
+                Some(tail_controls) => get_numbers(tail_controls)?,
+                None => Vec::new(),
+            },
+            statistics: point["statistics"]
+                .as_array()?
+                .iter()
+                .map(parse_statistics)
+                .collect::<Option<_>>()?,
+        }),
+        None => None,
+    };
+    Some((
+        key,
+        (
+            values[0], values[1], values[2], values[3], values[4], values[5], values[6],
+        ),
+        point["monte_carlo"].as_u64()? as usize,
+        partial,
+    ))
+}
+
+struct CheckpointFile {
+    run: RunDescription,
+    seed: u64,
+    points: Vec<Point>,
+    lines: Vec<String>,
+}
+
+fn read_checkpoint(path: &Path) -> Result<CheckpointFile, Error> {
+    let invalid = |message: String| Error::Checkpoint {
+        path: path.display().to_string(),
+        message,
+    };
+    let mut lines = BufReader::new(File::open(path)?).lines();
+    let header: Value = match lines.next() {
+        Some(line) => serde_json::from_str(&line?)
+            .map_err(|error| invalid(format!("unreadable header, {}", error)))?,
+        None => return Err(invalid("empty checkpoint".to_string())),
+    };
+    let run = RunDescription::deserialize(&header["run"])
+        .map_err(|error| invalid(format!("unreadable run description, {}", error)))?;
+    let seed = header["seed"]
+        .as_u64()
+        .ok_or_else(|| invalid("missing seed".to_string()))?;
+
+    let mut lines: Vec<String> = lines.collect::<Result<_, _>>()?;
+    let mut points = Vec::with_capacity(lines.len());
+    for (index, line) in lines.iter().enumerate() {
+        match parse_point(line) {
+            Some(point) => points.push(point),
+            // The last line may have been cut short by the interruption
+            None if index + 1 == lines.len() => {}
+            None => return Err(invalid(format!("malformed point on line {}", index + 2))),
+        }
+    }
+    lines.truncate(points.len());
+    Ok(CheckpointFile {
+        run,
+        seed,
+        points,
+        lines,
+    })
+}
+
+fn write_header(file: &mut File, run: &RunDescription, seed: u64) -> std::io::Result<()> {
+    writeln!(file, "{}", json!({ "run": run, "seed": seed }))
+}
+
+impl Checkpoint {
+    // Keeps the points in memory only, for sweeps that are not meant to be resumed
+    pub fn disabled(seed: u64) -> Self {
+        Checkpoint {
+            seed,
+            path: None,
+            file: None,
+            points: HashMap::new(),
+            monte_carlo: HashMap::new(),
+            shard: false,
+            error: None,
+        }
+    }
+
+    // Starts a new checkpoint at `path`, or with `resume` continues the existing one if it
+    // was written by the same run. A resumed run takes its seed from the checkpoint, and
+    // when no seed is given a new run draws a random one.
+    pub fn open(
+        path: &Path,
+        run: &RunDescription,
+        seed: Option<u64>,
+        resume: bool,
+    ) -> Result<Self, Error> {
+        if resume && path.exists() {
+            return Self::resume(path, run, seed);
+        }
+        if let Some(directory) = path.parent() {
+            std::fs::create_dir_all(directory)?;
+        }
+        let seed = seed.unwrap_or_else(rand::random);
+        let mut file = File::create(path)?;
+        write_header(&mut file, run, seed)?;
+        Ok(Checkpoint {
+            seed,
+            path: Some(path.to_path_buf()),
+            file: Some(file),
+            points: HashMap::new(),
+            monte_carlo: HashMap::new(),
+            shard: run.shard.is_some(),
+            error: None,
+        })
+    }
+
+    fn resume(path: &Path, run: &RunDescription, seed: Option<u64>) -> Result<Self, Error> {
+        let invalid = |message: String| Error::Checkpoint {
+            path: path.display().to_string(),
+            message,
+        };
+        let checkpoint = read_checkpoint(path)?;
+        if checkpoint.run != *run {
+            return Err(invalid("written by a different run".to_string()));
+        }
+        if seed.is_some_and(|seed| seed != checkpoint.seed) {
+            return Err(invalid(format!(
+                "written with seed {}, not {}",
+                checkpoint.seed,
+                seed.unwrap()
+            )));
+        }
+
+        // Rewrites the complete lines, dropping a partially written last one
+        let mut file = File::create(path)?;
+        write_header(&mut file, run, checkpoint.seed)?;
+        for line in checkpoint.lines.iter() {
+            writeln!(file, "{}", line)?;
+        }
+        drop(file);
+        println!(
+            "Resuming from {} with {} completed points",
+            path.display(),
+            checkpoint.points.len()
+        );
+
+        Ok(Checkpoint {
+            seed: checkpoint.seed,
+            path: Some(path.to_path_buf()),
+            file: Some(OpenOptions::new().append(true).open(path)?),
+            points: checkpoint
+                .points
+                .iter()
+                .map(|(key, performance, _, _)| (key.clone(), *performance))
+                .collect(),
+            monte_carlo: checkpoint
+                .points
+                .into_iter()
+                .map(|(key, _, monte_carlo, _)| (key, monte_carlo))
+                .collect(),
+            shard: run.shard.is_some(),
+            error: None,
+        })
+    }
+
+    // Combines the checkpoints left by all shards of a run into the points of a single run
+    // over every sample, and returns them with the description of that run
+    pub fn merge_shards(paths: &[PathBuf]) -> Result<(RunDescription, Self), Error> {
+        let invalid = |message: String| Error::InvalidParameter {
+            field: "merge".to_string(),
+            message,
+        };
+        let mut shards = paths
+            .iter()
+            .map(|path| read_checkpoint(path))
+            .collect::<Result<Vec<_>, _>>()?;
+        if shards.is_empty() {
+            return Err(invalid("no shards given".to_string()));
+        }
+        shards.sort_by_key(|shard| shard.run.shard.map(|shard| shard.index));
+
+        let run = RunDescription {
+            shard: None,
+            ..shards[0].run.clone()
+        };
+        let seed = shards[0].seed;
+        for (index, shard) in shards.iter().enumerate() {
+            let expected = Shard {
+                index: index + 1,
+                count: shards.len(),
+            };
+            match shard.run.shard {
+                Some(found) if found == expected => {}
+                Some(found) if found.count != expected.count => {
+                    return Err(invalid(format!(
+                        "the run was split into {} shards, but {} were given",
+                        found.count, expected.count
+                    )))
+                }
+                Some(found) => {
+                    return Err(invalid(format!(
+                        "expected shard {}, found shard {}",
+                        expected, found
+                    )))
+                }
+                None => return Err(invalid("not the checkpoint of a shard".to_string())),
+            }
+            let shard_run = RunDescription {
+                shard: None,
+                ..shard.run.clone()
+            };
+            if shard_run != run || shard.seed != seed {
+                return Err(invalid(format!(
+                    "shard {} belongs to a different run",
+                    expected
+                )));
+            }
+        }
+
+        // The partial performances of a point, in shard order
+        let mut partials: HashMap<Key, Vec<PartialPerformance>> = HashMap::new();
+        for shard in shards {
+            for (key, _, _, partial) in shard.points {
+                let partial = partial.ok_or_else(|| invalid("missing k samples".to_string()))?;
+                partials.entry(key).or_default().push(partial);
+            }
+        }
+        let mut checkpoint = Checkpoint::disabled(seed);
+        for (key, parts) in partials {
+            if parts.len() != paths.len() {
+                return Err(invalid(format!(
+                    "{} at beta {}, g {} and gamma {} is missing from some shards",
+                    key.0,
+                    f64::from_bits(key.1[0]),
+                    f64::from_bits(key.1[1]),
+                    f64::from_bits(key.1[2])
+                )));
+            }
+            let partial = PartialPerformance::merge(parts);
+            checkpoint
+                .monte_carlo
+                .insert(key.clone(), partial.get_monte_carlo());
+            checkpoint
+                .points
+                .insert(key, partial.get_performance(run.error));
+        }
+        Ok((run, checkpoint))
+    }
+
+    pub fn get(&self, protocol: &str, beta: f64, g: f64, gamma: f64) -> Option<Performance> {
+        self.points.get(&get_key(protocol, beta, g, gamma)).copied()
+    }
+
+    pub fn get_monte_carlo(&self, protocol: &str, beta: f64, g: f64, gamma: f64) -> Option<usize> {
+        self.monte_carlo
+            .get(&get_key(protocol, beta, g, gamma))
+            .copied()
+    }
+
+    // Records the samples evaluated at a grid point and returns their performance
+    pub fn insert(
+        &mut self,
+        protocol: &str,
+        beta: f64,
+        g: f64,
+        gamma: f64,
+        partial: &PartialPerformance,
+        epsilon: f64,
+    ) -> Performance {
+        let point = partial.get_performance(epsilon);
+        let monte_carlo = partial.get_monte_carlo();
+        self.points.insert(get_key(protocol, beta, g, gamma), point);
+        self.monte_carlo
+            .insert(get_key(protocol, beta, g, gamma), monte_carlo);
+        if let (Some(file), None) = (self.file.as_mut(), self.error.as_ref()) {
+            let (k, f_work, f_height, max_work, max_height, adversary_work, adversary_height) =
+                point;
+            let mut line = json!({
+                "protocol": protocol,
+                "beta": beta,
+                "g": g,
+                "gamma": gamma,
+                "performance": [k, f_work, f_height, max_work, max_height, adversary_work, adversary_height],
+                "monte_carlo": monte_carlo,
+            });
+            if self.shard {
+                line["k"] = json!(partial.k);
+                if !partial.tail_controls.is_empty() {
+                    line["tail_controls"] = json!(partial.tail_controls);
+                }
+                line["statistics"] = partial
+                    .statistics
+                    .iter()
+                    .map(|statistics| {
+                        json!({
+                            "count": statistics.count,
+                            "sums": statistics.sums,
+                            "control": statistics.control,
+                            "cross": statistics.cross,
+                            "tail_control": statistics.tail_control,
+                        })
+                    })
+                    .collect();
+            }
+            if let Err(error) = writeln!(file, "{}", line).and_then(|_| file.flush()) {
+                self.error = Some(error);
+            }
+        }
+        point
+    }
+
+    // Reports a failed write, or once the run has completed removes the checkpoint, unless
+    // it holds the partial result of a shard
+    pub fn finish(self) -> Result<(), Error> {
+        if let Some(error) = self.error {
+            return Err(Error::Io(error));
+        }
+        drop(self.file);
+        if let Some(path) = self.path {
+            if self.shard {
+                println!("Wrote shard to file: {}", path.display());
+            } else {
+                std::fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::SUMMATION_BLOCK;
+
+    fn get_run(shard: Option<Shard>) -> RunDescription {
+        RunDescription {
+            experiment: Experiment::Poem {
+                beta_range: "0.1:0.3:3".to_string(),
+                g_range: "1:2:2".to_string(),
+                gamma_range: "0:1:2".to_string(),
+            },
+            monte_carlo: 2 * SUMMATION_BLOCK,
+            error: 0.1,
+            variance_reduction: VarianceReduction::default(),
+            sequential: None,
+            shard,
+        }
+    }
+
+    fn get_partial(offset: usize) -> PartialPerformance {
+        PartialPerformance {
+            k: (0..SUMMATION_BLOCK)
+                .map(|i| match (offset * 5 + i) % 17 {
+                    0 => INF,
+                    k => k as f64,
+                })
+                .collect(),
+            tail_controls: (0..SUMMATION_BLOCK)
+                .map(|i| ((offset + i) % 3) as f64 - 1.0)
+                .collect(),
+            statistics: vec![Statistics {
+                count: SUMMATION_BLOCK,
+                sums: [0.5, 0.25, 650.0, 1300.0, 1.0 / 3.0, 1300.0].map(|x| x * offset as f64),
+                tail_control: [1.0, 682.0],
+                ..Default::default()
+            }],
+        }
+    }
+
+    #[test]
+    fn test_resume() {
+        let directory = std::env::temp_dir().join(format!("checkpoint_{}", std::process::id()));
+        let path = directory.join("test.jsonl");
+        let run = get_run(None);
+
+        let mut checkpoint = Checkpoint::open(&path, &run, Some(7), false).unwrap();
+        let point = checkpoint.insert("poem", 0.1, 1.7, 0.0, &get_partial(1), 0.1);
+        drop(checkpoint);
+        // A write cut short by the interruption
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        write!(file, "{{\"protocol\":\"poem\",\"beta\":0.2").unwrap();
+        drop(file);
+
+        let other = RunDescription {
+            monte_carlo: 1000,
+            ..run.clone()
+        };
+        assert!(Checkpoint::open(&path, &other, None, true).is_err());
+        assert!(Checkpoint::open(&path, &run, Some(8), true).is_err());
+        let checkpoint = Checkpoint::open(&path, &run, None, true).unwrap();
+        assert_eq!(checkpoint.seed, 7);
+        assert_eq!(checkpoint.get("poem", 0.1, 1.7, 0.0), Some(point));
+        assert_eq!(
+            checkpoint.get_monte_carlo("poem", 0.1, 1.7, 0.0),
+            Some(SUMMATION_BLOCK)
+        );
+        assert_eq!(checkpoint.get("bitcoin", 0.1, 1.7, 0.0), None);
+        assert_eq!(checkpoint.get("poem", 0.2, 1.7, 0.0), None);
+
+        checkpoint.finish().unwrap();
+        assert!(!path.exists());
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_merge_shards() {
+        let directory = std::env::temp_dir().join(format!("shards_{}", std::process::id()));
+        let directory = directory.to_str().unwrap();
+        let mut paths = vec![];
+        for index in [2, 1] {
+            let run = get_run(Some(Shard { index, count: 2 }));
+            let path = get_checkpoint_path(directory, &run);
+            let mut checkpoint = Checkpoint::open(&path, &run, Some(7), false).unwrap();
+            checkpoint.insert("poem", 0.1, 1.7, 0.0, &get_partial(index), 0.1);
+            checkpoint.finish().unwrap();
+            paths.push(path);
+        }
+
+        let (run, checkpoint) = Checkpoint::merge_shards(&paths).unwrap();
+        assert_eq!(run, get_run(None));
+        assert_eq!(checkpoint.seed, 7);
+        let whole = PartialPerformance::merge(vec![get_partial(1), get_partial(2)]);
+        assert_eq!(
+            checkpoint.get("poem", 0.1, 1.7, 0.0),
+            Some(whole.get_performance(0.1))
+        );
+        assert!(Checkpoint::merge_shards(&paths[..1]).is_err());
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,258 @@
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analysis::{
+        get_confirmation_index, get_expected_shortfall, get_performance, get_tail_length,
+        SUMMATION_BLOCK,
+    },
+    protocol::Protocol,
+    sampling::Samples,
+    simulations::{start_protocol, ChunkExecutions},
+    types::{Chain, INF},
+};
+
+// When a transaction in the first block is taken as final
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConfirmationRule {
+    /// Once the honest chain has k work
+    Work,
+    /// Once the honest chain has k blocks, whatever their work
+    Blocks,
+    /// Once T time has passed
+    Time,
+    /// Once the honest chain has k work and the hybrid time has passed
+    Hybrid,
+}
+
+impl ConfirmationRule {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConfirmationRule::Work => "work",
+            ConfirmationRule::Blocks => "blocks",
+            ConfirmationRule::Time => "time",
+            ConfirmationRule::Hybrid => "hybrid",
+        }
+    }
+
+    // The smallest parameter of the rule that confirms no earlier than the honest progress
+    // at `index`, or INF when the adversary is still ahead at the end. As with k, a work
+    // threshold is the work of that progress.
+    pub fn get_parameter(
+        &self,
+        honest_progresses: &[Chain],
+        index: Option<usize>,
+        time: f64,
+    ) -> f64 {
+        let Some(index) = index else {
+            return INF;
+        };
+        match self {
+            ConfirmationRule::Work => honest_progresses[index].work,
+            // The heaviest chain may be shorter than an earlier one, so the rule has to wait
+            // for a chain higher than any before the index
+            ConfirmationRule::Blocks => {
+                let height = honest_progresses[..index]
+                    .iter()
+                    .map(|progress| progress.height)
+                    .max()
+                    .unwrap();
+                (height + 1) as f64
+            }
+            ConfirmationRule::Time => honest_progresses[index].timestamp,
+            ConfirmationRule::Hybrid if honest_progresses[index].timestamp <= time => 0.,
+            ConfirmationRule::Hybrid => honest_progresses[index].work,
+        }
+    }
+
+    // The time until the rule confirms at `parameter`, given the growth rates of the chain
+    pub fn get_latency(&self, parameter: f64, f_work: f64, f_height: f64, time: f64) -> f64 {
+        match self {
+            ConfirmationRule::Work => parameter / f_work,
+            ConfirmationRule::Blocks => parameter / f_height,
+            ConfirmationRule::Time => parameter,
+            ConfirmationRule::Hybrid => time.max(parameter / f_work),
+        }
+    }
+}
+
+// Keeps only the largest parameters the expected shortfall at epsilon needs out of
+// `monte_carlo`, as PartialPerformance::prune does for k
+fn prune_parameters(parameters: &mut Vec<f64>, monte_carlo: usize, epsilon: f64) {
+    let tail = get_tail_length(monte_carlo, epsilon);
+    if parameters.len() < 2 * tail.max(1) {
+        return;
+    }
+    let dropped = parameters.len() - tail;
+    parameters.select_nth_unstable_by(dropped, |a, b| a.partial_cmp(b).unwrap());
+    parameters.drain(..dropped);
+}
+
+// Per rule, per beta
+pub struct ConfirmationData {
+    pub parameter: Vec<Vec<f64>>,
+    pub latency: Vec<Vec<f64>>,
+    // Per beta
+    pub growth_rate: Vec<f64>,
+    pub height_growth_rate: Vec<f64>,
+}
+
+// Evaluates every rule at (g, gamma) for every beta, from the confirmation index of each
+// sample, so that all rules face the same adversary. The safe parameter of a rule is the
+// expected shortfall at epsilon of its per-sample parameters, and the hybrid rule waits for
+// `hybrid_time` before counting work.
+#[allow(clippy::too_many_arguments)]
+pub fn simulate_confirmation(
+    protocol: &dyn Protocol,
+    samples: Samples,
+    epsilon: f64,
+    beta_range: &[f64],
+    g: f64,
+    gamma: f64,
+    rules: &[ConfirmationRule],
+    hybrid_time: f64,
+) -> ConfirmationData {
+    let gamma = start_protocol(protocol, gamma);
+    // Only the tail of the parameters of every (rule, beta) is kept
+    let mut parameters = vec![vec![Vec::new(); beta_range.len()]; rules.len()];
+    let mut growth_sums = vec![(0., 0.); beta_range.len()];
+
+    for chunk in samples.chunks(SUMMATION_BLOCK) {
+        let mut executions = ChunkExecutions::sample_progresses(protocol, chunk, g, gamma);
+
+        for (beta_index, &beta) in beta_range.iter().enumerate() {
+            executions.scale_adversary(beta, g, gamma);
+            let results: Vec<_> = executions
+                .honest_progresses
+                .par_iter()
+                .zip(executions.scaled_adversary_progresses.par_iter())
+                .map(|(honest_progresses, adversary_progresses)| {
+                    let index = get_confirmation_index(honest_progresses, adversary_progresses);
+                    let performance = get_performance(honest_progresses, adversary_progresses);
+                    let parameters: Vec<_> = rules
+                        .iter()
+                        .map(|rule| rule.get_parameter(honest_progresses, index, hybrid_time))
+                        .collect();
+                    (parameters, performance.1, performance.2)
+                })
+                .collect();
+            for (sample_parameters, f_work, f_height) in results {
+                for (rule_parameters, parameter) in parameters.iter_mut().zip(sample_parameters) {
+                    rule_parameters[beta_index].push(parameter);
+                }
+                growth_sums[beta_index].0 += f_work;
+                growth_sums[beta_index].1 += f_height;
+            }
+            for rule_parameters in parameters.iter_mut() {
+                prune_parameters(&mut rule_parameters[beta_index], samples.len(), epsilon);
+            }
+        }
+    }
+
+    let growth_rate: Vec<f64> = growth_sums
+        .iter()
+        .map(|sums| sums.0 / samples.len() as f64)
+        .collect();
+    let height_growth_rate: Vec<f64> = growth_sums
+        .iter()
+        .map(|sums| sums.1 / samples.len() as f64)
+        .collect();
+    let mut confirmation_data = ConfirmationData {
+        parameter: Vec::new(),
+        latency: Vec::new(),
+        growth_rate: growth_rate.clone(),
+        height_growth_rate: height_growth_rate.clone(),
+    };
+    for (rule, rule_parameters) in rules.iter().zip(parameters.iter_mut()) {
+        let parameter: Vec<f64> = rule_parameters
+            .iter_mut()
+            .map(|parameters| get_expected_shortfall(parameters, samples.len(), epsilon))
+            .collect();
+        let latency = parameter
+            .iter()
+            .zip(growth_rate.iter().zip(&height_growth_rate))
+            .map(|(&parameter, (&f_work, &f_height))| {
+                rule.get_latency(parameter, f_work, f_height, hybrid_time)
+            })
+            .collect();
+        println!(" - {}: {:?}", rule.name(), parameter);
+        confirmation_data.parameter.push(parameter);
+        confirmation_data.latency.push(latency);
+    }
+    confirmation_data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        protocol::{Bitcoin, Poem},
+        simulations::evaluate_performance,
+        types::chain,
+    };
+
+    #[test]
+    fn test_parameters() {
+        // The adversary is last ahead before the honest progress at 4, whose chain is shorter
+        // than the one at 3
+        let honest = [
+            chain(0., 0., 0),
+            chain(1., 1., 1),
+            chain(3., 2., 3),
+            chain(4., 3., 2),
+            chain(6., 4., 4),
+        ];
+        let adversary = [chain(0., 0., 0), chain(3.5, 2.5, 2), chain(10., 3.5, 3)];
+        let index = get_confirmation_index(&honest, &adversary);
+        assert_eq!(index, Some(3));
+        let get = |rule: ConfirmationRule, time| rule.get_parameter(&honest, index, time);
+        assert_eq!(get(ConfirmationRule::Work, 0.), 3.);
+        assert_eq!(get(ConfirmationRule::Blocks, 0.), 4.);
+        assert_eq!(get(ConfirmationRule::Time, 0.), 4.);
+        assert_eq!(get(ConfirmationRule::Hybrid, 2.), 3.);
+        assert_eq!(get(ConfirmationRule::Hybrid, 4.), 0.);
+        assert_eq!(ConfirmationRule::Work.get_parameter(&honest, None, 0.), INF);
+    }
+
+    #[test]
+    fn test_confirmation() {
+        // Blocks are work in Bitcoin, and the hybrid rule needs no more work than the work rule
+        let samples = Samples::new(6, 1024);
+        let rules = [
+            ConfirmationRule::Work,
+            ConfirmationRule::Blocks,
+            ConfirmationRule::Time,
+            ConfirmationRule::Hybrid,
+        ];
+        let data = simulate_confirmation(&Bitcoin, samples, 0.1, &[0.2], 1., 0., &rules, 5.);
+        assert_eq!(data.parameter[0], data.parameter[1]);
+        assert!(data.parameter[3][0] <= data.parameter[0][0]);
+        let data = simulate_confirmation(&Poem, samples, 0.1, &[0.2], 1., 0., &rules, 5.);
+        for latency in &data.latency {
+            assert!(latency[0] > 0. && latency[0] < INF);
+        }
+    }
+
+    #[test]
+    fn test_work_rule() {
+        // Across several chunks, the kept tail of the work rule gives the k of the simulation
+        let samples = Samples::new(8, 2 * SUMMATION_BLOCK);
+        let beta_range = [0.3];
+        let data = simulate_confirmation(
+            &Bitcoin,
+            samples,
+            0.1,
+            &beta_range,
+            1.,
+            0.,
+            &[ConfirmationRule::Work],
+            0.,
+        );
+        for (&beta, &parameter) in beta_range.iter().zip(&data.parameter[0]) {
+            let performance =
+                evaluate_performance(&Bitcoin, samples, samples.len(), 0.1, beta, 1., 0.);
+            assert!((parameter - performance.0).abs() < 1e-9);
+        }
+    }
+}
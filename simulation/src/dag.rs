@@ -0,0 +1,175 @@
+use rayon::prelude::*;
+
+use crate::types::{Block, Chain};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DagBlock {
+    pub timestamp: f64,
+    pub work: f64,
+    // Total intrinsic work of the block and its past cone
+    pub weight: f64,
+    pub height: usize,
+    pub arrival_time: f64,
+    pub parents: Vec<usize>,
+}
+
+pub fn build_dag(blocks: &[Block], network_delay: f64) -> Vec<DagBlock> {
+    let mut dag = Vec::with_capacity(blocks.len() + 1);
+    dag.push(DagBlock {
+        timestamp: 0.0,
+        work: 0.0,
+        weight: 0.0,
+        height: 0,
+        arrival_time: 0.0,
+        parents: vec![],
+    });
+
+    let mut tips = vec![0];
+    let mut arrived_count = 1;
+    let mut arrived_work = 0.0;
+
+    for new_block in blocks {
+        // Before processing the newly mined block first deliver all blocks received before it
+        while arrived_count < dag.len() && dag[arrived_count].arrival_time <= new_block.timestamp {
+            let delivered: &DagBlock = &dag[arrived_count];
+            tips.retain(|tip| !delivered.parents.contains(tip));
+            tips.push(arrived_count);
+            arrived_work += delivered.work;
+            arrived_count += 1;
+        }
+
+        // The new block references all visible tips, so its past cone is every delivered block
+        let height = tips.iter().map(|&tip| dag[tip].height).max().unwrap() + 1;
+        dag.push(DagBlock {
+            timestamp: new_block.timestamp,
+            work: new_block.work,
+            weight: arrived_work + new_block.work,
+            height,
+            arrival_time: new_block.timestamp + network_delay,
+            parents: tips.clone(),
+        });
+    }
+
+    dag
+}
+
+// Orders the DAG by past cone weight. A block always outweighs its parents,
+// so this is a topological order in which heavier sub-DAGs come later.
+pub fn order_dag(dag: &[DagBlock]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..dag.len()).collect();
+    order.sort_by(|&a, &b| {
+        dag[a]
+            .weight
+            .partial_cmp(&dag[b].weight)
+            .unwrap()
+            .then(a.cmp(&b))
+    });
+    order
+}
+
+#[inline]
+fn get_dag_progresses<const N: usize>(
+    progresses: &mut Vec<Chain>,
+    blocks: &[Block; N],
+    network_delay: f64,
+) {
+    progresses.clear();
+    progresses.push(Chain {
+        timestamp: 0.0,
+        work: 0.0,
+        height: 0,
+        arrival_time: 0.0,
+    });
+
+    let dag = build_dag(blocks, network_delay);
+    let mut ranks = vec![0; dag.len()];
+    for (rank, &index) in order_dag(&dag).iter().enumerate() {
+        ranks[index] = rank;
+    }
+
+    // The tip of the DAG is the block mined so far that comes last in the order
+    let mut tip = 0;
+    for (index, dag_block) in dag.iter().enumerate().skip(1) {
+        // if the new block does not come after the tip, skip it
+        if ranks[index] < ranks[tip] {
+            continue;
+        }
+        tip = index;
+
+        progresses.push(Chain {
+            timestamp: dag_block.timestamp,
+            work: dag_block.weight,
+            height: dag_block.height,
+            arrival_time: dag_block.arrival_time,
+        });
+    }
+}
+
+#[inline]
+pub fn get_monte_carlo_dag_progresses<const N: usize>(
+    monte_carlo_progresses: &mut [Vec<Chain>],
+    monte_carlo_blocks: &[[Block; N]],
+    network_delay: f64,
+) {
+    monte_carlo_progresses
+        .par_iter_mut()
+        .zip(monte_carlo_blocks.par_iter())
+        .for_each(|(progresses, blocks)| {
+            get_dag_progresses(progresses, blocks, network_delay);
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_build_dag() {
+        let blocks = [
+            Block {
+                timestamp: 0.5,
+                work: 1.0,
+            },
+            Block {
+                timestamp: 1.0,
+                work: 2.0,
+            },
+            Block {
+                timestamp: 1.2,
+                work: 1.5,
+            },
+            Block {
+                timestamp: 2.5,
+                work: 1.0,
+            },
+            Block {
+                timestamp: 2.7,
+                work: 0.5,
+            },
+        ];
+
+        let dag = build_dag(&blocks, 1.);
+        let parents: Vec<Vec<usize>> = dag.iter().map(|block| block.parents.clone()).collect();
+        let weights: Vec<f64> = dag.iter().map(|block| block.weight).collect();
+        let heights: Vec<usize> = dag.iter().map(|block| block.height).collect();
+
+        assert_eq!(
+            parents,
+            vec![
+                vec![],
+                vec![0],
+                vec![0],
+                vec![0],
+                vec![1, 2, 3],
+                vec![1, 2, 3]
+            ]
+        );
+        assert_eq!(weights, vec![0.0, 1.0, 2.0, 1.5, 5.5, 5.0]);
+        assert_eq!(heights, vec![0, 1, 1, 1, 2, 2]);
+        assert_eq!(order_dag(&dag), vec![0, 1, 3, 2, 5, 4]);
+
+        let mut progresses = Vec::with_capacity(blocks.len() + 1);
+        get_dag_progresses(&mut progresses, &blocks, 1.);
+        let progress_work: Vec<f64> = progresses.iter().map(|chain| chain.work).collect();
+        assert_eq!(progress_work, vec![0.0, 1.0, 2.0, 5.5]);
+    }
+}
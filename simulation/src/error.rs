@@ -0,0 +1,71 @@
+use crate::ranges::RangeParseError;
+
+// Everything that can go wrong between reading the user's input and writing the results.
+// Each variant names the offending field so that the message points at what to fix.
+#[derive(Debug)]
+pub enum Error {
+    Range {
+        field: String,
+        source: RangeParseError,
+    },
+    InvalidBeta {
+        field: String,
+        beta: f64,
+    },
+    InvalidG {
+        field: String,
+        g: f64,
+    },
+    InvalidParameter {
+        field: String,
+        message: String,
+    },
+    Checkpoint {
+        path: String,
+        message: String,
+    },
+    Io(std::io::Error),
+    Scenario(String),
+    UnsupportedFormat(String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Range { field, source } => write!(f, "{}: {}", field, source),
+            Error::InvalidBeta { field, beta } => write!(
+                f,
+                "{}: beta must be strictly between 0 and 0.5, got {}",
+                field, beta
+            ),
+            Error::InvalidG { field, g } => write!(f, "{}: g must be positive, got {}", field, g),
+            Error::InvalidParameter { field, message } => write!(f, "{}: {}", field, message),
+            Error::Checkpoint { path, message } => {
+                write!(f, "Cannot read checkpoint {}: {}", path, message)
+            }
+            Error::Io(error) => write!(f, "I/O error: {}", error),
+            Error::Scenario(message) => write!(f, "Malformed scenario: {}", message),
+            Error::UnsupportedFormat(extension) => write!(
+                f,
+                "Unsupported scenario format `{}`, expected .toml or .json",
+                extension
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Range { source, .. } => Some(source),
+            Error::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error)
+    }
+}
@@ -0,0 +1,433 @@
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+use crate::types::{Block, Chain};
+
+pub type PartyId = usize;
+pub type BlockId = usize;
+
+// Every block tree starts from the genesis block
+pub const GENESIS: BlockId = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Miner {
+    Honest(PartyId),
+    Adversary,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TreeBlock {
+    pub parent: BlockId,
+    pub miner: Miner,
+    pub timestamp: f64,
+    // Work of the block itself, and of the chain it ends
+    pub work: f64,
+    pub chain_work: f64,
+    pub height: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventKind {
+    // A party finds a block of the given work
+    Mine { miner: Miner, work: f64 },
+    // A block reaches an honest party
+    Deliver { party: PartyId, block: BlockId },
+    // The adversary publishes a block it withheld, which reaches every honest party
+    Release { block: BlockId },
+    // The parties of `group` stop exchanging blocks with the others until the partition ends
+    PartitionStart { group: Vec<PartyId> },
+    PartitionEnd,
+}
+
+impl EventKind {
+    // At the same time, the network changes first, then blocks are delivered, and only then
+    // are new blocks mined on what was delivered
+    fn priority(&self) -> u8 {
+        match self {
+            EventKind::PartitionStart { .. } | EventKind::PartitionEnd => 0,
+            EventKind::Deliver { .. } | EventKind::Release { .. } => 1,
+            EventKind::Mine { .. } => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub time: f64,
+    pub kind: EventKind,
+    // Events of the same time and priority are handled in the order they were scheduled
+    sequence: u64,
+}
+
+impl Ord for Event {
+    // Reversed, so that the binary heap pops the earliest event first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .time
+            .total_cmp(&self.time)
+            .then(other.kind.priority().cmp(&self.kind.priority()))
+            .then(other.sequence.cmp(&self.sequence))
+    }
+}
+
+impl PartialOrd for Event {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Event {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Event {}
+
+// Parties and adversaries see every event the engine handles, after the engine has applied
+// it to the network, and react by mining, broadcasting or scheduling further events
+pub trait Subscriber {
+    fn handle(&mut self, event: &Event, engine: &mut Engine);
+}
+
+// A discrete-event simulation of a block tree shared by honest parties and an adversary,
+// over a network with a delay between every pair of parties
+pub struct Engine {
+    queue: BinaryHeap<Event>,
+    sequence: u64,
+    time: f64,
+    blocks: Vec<TreeBlock>,
+    // delays[from][to] between the honest parties, including a party and itself
+    delays: Vec<Vec<f64>>,
+    // From the adversary to every honest party
+    adversary_delays: Vec<f64>,
+    // Whether each party is in the partitioned group, while a partition lasts
+    partition: Option<Vec<bool>>,
+    // The (from, to, block) deliveries a partition holds back until it ends
+    held: Vec<(PartyId, PartyId, BlockId)>,
+    // The honest (from, to, block) deliveries sent during the current event, which a
+    // subscriber may take to pick their delays, and which otherwise take the network delay
+    outbox: Vec<(PartyId, PartyId, BlockId)>,
+}
+
+impl Engine {
+    pub fn new(delays: Vec<Vec<f64>>, adversary_delays: Vec<f64>) -> Self {
+        assert!(delays.iter().all(|row| row.len() == delays.len()));
+        assert_eq!(adversary_delays.len(), delays.len());
+        Engine {
+            queue: BinaryHeap::new(),
+            sequence: 0,
+            time: 0.0,
+            blocks: vec![TreeBlock {
+                parent: GENESIS,
+                miner: Miner::Adversary,
+                timestamp: 0.0,
+                work: 0.0,
+                chain_work: 0.0,
+                height: 0,
+            }],
+            delays,
+            adversary_delays,
+            partition: None,
+            held: Vec::new(),
+            outbox: Vec::new(),
+        }
+    }
+
+    // Every honest block, including to its own miner, takes `delay` to propagate, while the
+    // adversary's reach the honest parties at once
+    pub fn uniform(parties: usize, delay: f64) -> Self {
+        Engine::new(vec![vec![delay; parties]; parties], vec![0.0; parties])
+    }
+
+    pub fn parties(&self) -> usize {
+        self.delays.len()
+    }
+
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    pub fn block(&self, block: BlockId) -> &TreeBlock {
+        &self.blocks[block]
+    }
+
+    pub fn blocks(&self) -> &[TreeBlock] {
+        &self.blocks
+    }
+
+    // The time it takes a block of the party to reach every honest party
+    pub fn get_propagation_delay(&self, party: PartyId) -> f64 {
+        self.delays[party].iter().copied().fold(0.0, f64::max)
+    }
+
+    // The network delay of the blocks of `from` to `to`
+    pub fn get_delay(&self, from: PartyId, to: PartyId) -> f64 {
+        self.delays[from][to]
+    }
+
+    // The honest deliveries sent so far during the current event, which the caller must
+    // schedule
+    pub fn take_outbox(&mut self) -> Vec<(PartyId, PartyId, BlockId)> {
+        std::mem::take(&mut self.outbox)
+    }
+
+    pub fn schedule(&mut self, time: f64, kind: EventKind) {
+        assert!(time >= self.time, "event scheduled in the past");
+        self.queue.push(Event {
+            time,
+            kind,
+            sequence: self.sequence,
+        });
+        self.sequence += 1;
+    }
+
+    // Schedules the miner to find the sampled blocks, at their timestamps
+    pub fn schedule_mining(&mut self, miner: Miner, blocks: &[Block]) {
+        for block in blocks {
+            self.schedule(
+                block.timestamp,
+                EventKind::Mine {
+                    miner,
+                    work: block.work,
+                },
+            );
+        }
+    }
+
+    // Adds a block extending `parent` at the current time
+    pub fn mine(&mut self, parent: BlockId, miner: Miner, work: f64) -> BlockId {
+        let parent_block = self.blocks[parent];
+        self.blocks.push(TreeBlock {
+            parent,
+            miner,
+            timestamp: self.time,
+            work,
+            chain_work: parent_block.chain_work + work,
+            height: parent_block.height + 1,
+        });
+        self.blocks.len() - 1
+    }
+
+    fn is_cut(&self, from: PartyId, to: PartyId) -> bool {
+        self.partition
+            .as_ref()
+            .is_some_and(|group| group[from] != group[to])
+    }
+
+    // Sends the block to every honest party
+    pub fn broadcast(&mut self, from: Miner, block: BlockId) {
+        for to in 0..self.parties() {
+            match from {
+                Miner::Honest(from) if self.is_cut(from, to) => self.held.push((from, to, block)),
+                Miner::Honest(from) => self.outbox.push((from, to, block)),
+                Miner::Adversary => self.schedule(
+                    self.time + self.adversary_delays[to],
+                    EventKind::Deliver { party: to, block },
+                ),
+            }
+        }
+    }
+
+    fn apply(&mut self, kind: &EventKind) {
+        match kind {
+            EventKind::PartitionStart { group } => {
+                let mut partition = vec![false; self.parties()];
+                for &party in group {
+                    partition[party] = true;
+                }
+                self.partition = Some(partition);
+            }
+            EventKind::PartitionEnd => {
+                self.partition = None;
+                // The held blocks start propagating when the partition heals
+                self.outbox.append(&mut self.held);
+            }
+            EventKind::Release { block } => self.broadcast(Miner::Adversary, *block),
+            EventKind::Mine { .. } | EventKind::Deliver { .. } => {}
+        }
+    }
+
+    // Handles the events in order until none is left
+    pub fn run(&mut self, subscribers: &mut [&mut dyn Subscriber]) {
+        self.run_until(f64::INFINITY, subscribers);
+    }
+
+    // Handles the events up to time `end`, after which the run can be resumed
+    pub fn run_until(&mut self, end: f64, subscribers: &mut [&mut dyn Subscriber]) {
+        while self.queue.peek().is_some_and(|event| event.time <= end) {
+            let event = self.queue.pop().unwrap();
+            self.time = event.time;
+            self.apply(&event.kind);
+            for subscriber in subscribers.iter_mut() {
+                subscriber.handle(&event, self);
+            }
+            for (from, to, block) in self.take_outbox() {
+                self.schedule(
+                    self.time + self.delays[from][to],
+                    EventKind::Deliver { party: to, block },
+                );
+            }
+        }
+    }
+}
+
+// Honest parties that mine on, and adopt, the heaviest chain delivered to them. Ties keep
+// the chain that arrived first.
+pub struct HonestParties {
+    pub tips: Vec<BlockId>,
+    // Every chain that was the heaviest honest chain when it was mined
+    pub progresses: Vec<Chain>,
+}
+
+impl HonestParties {
+    pub fn new(parties: usize) -> Self {
+        HonestParties {
+            tips: vec![GENESIS; parties],
+            progresses: vec![Chain {
+                timestamp: 0.0,
+                work: 0.0,
+                height: 0,
+                arrival_time: 0.0,
+            }],
+        }
+    }
+}
+
+impl Subscriber for HonestParties {
+    fn handle(&mut self, event: &Event, engine: &mut Engine) {
+        match event.kind {
+            EventKind::Mine {
+                miner: Miner::Honest(party),
+                work,
+            } => {
+                let block = engine.mine(self.tips[party], Miner::Honest(party), work);
+                let tree_block = *engine.block(block);
+                if tree_block.chain_work > self.progresses.last().unwrap().work {
+                    self.progresses.push(Chain {
+                        timestamp: tree_block.timestamp,
+                        work: tree_block.chain_work,
+                        height: tree_block.height,
+                        arrival_time: tree_block.timestamp + engine.get_propagation_delay(party),
+                    });
+                }
+                engine.broadcast(Miner::Honest(party), block);
+            }
+            EventKind::Deliver { party, block }
+                if engine.block(block).chain_work > engine.block(self.tips[party]).chain_work =>
+            {
+                self.tips[party] = block;
+            }
+            _ => {}
+        }
+    }
+}
+
+// The honest progresses of get_monte_carlo_progresses, run through the engine: a single
+// honest party stands for many small miners, none of whom sees its own block before the
+// network delay
+pub fn get_event_progresses(progresses: &mut Vec<Chain>, blocks: &[Block], network_delay: f64) {
+    let mut engine = Engine::uniform(1, network_delay);
+    engine.schedule_mining(Miner::Honest(0), blocks);
+    let mut honest = HonestParties::new(1);
+    engine.run(&mut [&mut honest]);
+    *progresses = honest.progresses;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        formatting::get_monte_carlo_progresses,
+        protocol::{Poem, Protocol},
+        sampling::Samples,
+    };
+
+    #[test]
+    fn test_event_progresses() {
+        // The engine reproduces the progresses of the single delay walk
+        let (honest_blocks, _) = Poem.sample_executions(Samples::new(11, 2));
+        let mut expected = vec![Vec::new(); honest_blocks.len()];
+        let mut progresses = Vec::new();
+        for delay in [0., 0.5, 1., 2.] {
+            get_monte_carlo_progresses(&mut expected, &honest_blocks, delay);
+            for (expected, blocks) in expected.iter().zip(&honest_blocks) {
+                get_event_progresses(&mut progresses, blocks, delay);
+                assert_eq!(&progresses, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_partition() {
+        // Two parties without delay are split while each mines a block, so they fork, and
+        // the heavier block wins once the partition ends
+        let mut engine = Engine::uniform(2, 0.);
+        engine.schedule(1., EventKind::PartitionStart { group: vec![1] });
+        engine.schedule(
+            2.,
+            EventKind::Mine {
+                miner: Miner::Honest(0),
+                work: 1.,
+            },
+        );
+        engine.schedule(
+            3.,
+            EventKind::Mine {
+                miner: Miner::Honest(1),
+                work: 2.,
+            },
+        );
+        engine.schedule(4., EventKind::PartitionEnd);
+        let mut honest = HonestParties::new(2);
+        engine.run(&mut [&mut honest]);
+        assert_eq!(engine.block(1).parent, GENESIS);
+        assert_eq!(engine.block(2).parent, GENESIS);
+        assert_eq!(honest.tips, vec![2, 2]);
+    }
+
+    #[test]
+    fn test_release() {
+        // A withheld adversary block is adopted only once it is released, and deliveries
+        // come before mining at the same time
+        struct Withholding;
+        impl Subscriber for Withholding {
+            fn handle(&mut self, event: &Event, engine: &mut Engine) {
+                if let EventKind::Mine {
+                    miner: Miner::Adversary,
+                    work,
+                } = event.kind
+                {
+                    let block = engine.mine(GENESIS, Miner::Adversary, work);
+                    engine.schedule(engine.time() + 2., EventKind::Release { block });
+                }
+            }
+        }
+        let mut engine = Engine::uniform(1, 0.);
+        engine.schedule(
+            1.,
+            EventKind::Mine {
+                miner: Miner::Adversary,
+                work: 5.,
+            },
+        );
+        engine.schedule(
+            2.,
+            EventKind::Mine {
+                miner: Miner::Honest(0),
+                work: 1.,
+            },
+        );
+        engine.schedule(
+            3.,
+            EventKind::Mine {
+                miner: Miner::Honest(0),
+                work: 1.,
+            },
+        );
+        let mut honest = HonestParties::new(1);
+        engine.run(&mut [&mut honest, &mut Withholding]);
+        assert_eq!(engine.block(2).parent, GENESIS);
+        assert_eq!(engine.block(3).parent, 1);
+        assert_eq!(honest.tips, vec![3]);
+    }
+}
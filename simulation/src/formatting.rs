@@ -19,8 +19,8 @@ fn scale_blocks<const N: usize>(
 
 #[inline]
 pub fn scale_monte_carlo_blocks<const N: usize>(
-    scaled_monte_carlo_blocks: &mut Vec<[Block; N]>,
-    original_monte_carlo_blocks: &Vec<[Block; N]>,
+    scaled_monte_carlo_blocks: &mut [[Block; N]],
+    original_monte_carlo_blocks: &[[Block; N]],
     g: f64,
     gamma: f64,
 ) {
@@ -34,8 +34,8 @@ pub fn scale_monte_carlo_blocks<const N: usize>(
 
 #[inline]
 fn scale_progresses(
-    scaled_progresses: &mut Vec<Chain>,
-    original_progresses: &Vec<Chain>,
+    scaled_progresses: &mut [Chain],
+    original_progresses: &[Chain],
     g: f64,
     gamma: f64,
 ) {
@@ -54,8 +54,8 @@ fn scale_progresses(
 
 #[inline]
 pub fn scale_monte_carlo_progresses(
-    scaled_monte_carlo_progresses: &mut Vec<Vec<Chain>>,
-    original_monte_carlo_progresses: &Vec<Vec<Chain>>,
+    scaled_monte_carlo_progresses: &mut [Vec<Chain>],
+    original_monte_carlo_progresses: &[Vec<Chain>],
     g: f64,
     gamma: f64,
 ) {
@@ -116,8 +116,8 @@ fn get_progresses<const N: usize>(
 
 #[inline]
 pub fn get_monte_carlo_progresses<const N: usize>(
-    monte_carlo_progresses: &mut Vec<Vec<Chain>>,
-    monte_carlo_blocks: &Vec<[Block; N]>,
+    monte_carlo_progresses: &mut [Vec<Chain>],
+    monte_carlo_blocks: &[[Block; N]],
     network_delay: f64,
 ) {
     monte_carlo_progresses
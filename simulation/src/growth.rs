@@ -0,0 +1,209 @@
+use serde::Serialize;
+
+use crate::{
+    analysis::SUMMATION_BLOCK,
+    protocol::Protocol,
+    sampling::Samples,
+    simulations::{start_protocol_sweep, ChunkExecutions, ADVERSARY_COUNT, HONEST_COUNT},
+    types::Chain,
+};
+
+// The growth rate is measured between these fractions of the expected time of the last block,
+// so that the start of the chain is left out and no sample ends before the window does
+const WINDOW_START: f64 = 0.1;
+const WINDOW_END: f64 = 0.8;
+// Estimates further than this many standard errors from the analytic rate are flagged
+const DEVIATION_ERRORS: f64 = 3.;
+// The two-sided level of the confidence interval of a rate, and its normal quantile
+pub const CONFIDENCE_LEVEL: f64 = 0.95;
+const CONFIDENCE_QUANTILE: f64 = 1.959_963_984_540_054;
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct GrowthEstimate {
+    // The work per unit of time, and its standard error
+    pub rate: f64,
+    pub error: f64,
+    // The normal confidence interval of the rate at CONFIDENCE_LEVEL
+    pub lower: f64,
+    pub upper: f64,
+    pub analytic: f64,
+    // (rate - analytic) / error
+    pub deviation: f64,
+    pub flagged: bool,
+}
+
+// Per gamma, per g
+pub struct GrowthData {
+    pub honest: Vec<Vec<GrowthEstimate>>,
+    pub adversary: Vec<Vec<GrowthEstimate>>,
+}
+
+// The work of the chain at `time`
+fn get_work_at(progresses: &[Chain], time: f64) -> f64 {
+    let index = progresses.partition_point(|progress| progress.timestamp <= time);
+    progresses[index.max(1) - 1].work
+}
+
+fn get_growth_rate(progresses: &[Chain], start: f64, end: f64) -> f64 {
+    (get_work_at(progresses, end) - get_work_at(progresses, start)) / (end - start)
+}
+
+// The growth rate alpha of the honest chain when blocks come at rate g and each extends the
+// chain of one delay earlier. Taking the chain to grow at a steady alpha, a block adds the
+// work by which it exceeds alpha, so alpha = g E[max(work - alpha, 0)], which is g / (1 + g)
+// for unit work and alpha 2^alpha = g / ln 2 for Exp(ln 2) work, as in resilience.tex.
+pub fn get_analytic_growth_rate(protocol: &dyn Protocol, g: f64, gamma: f64) -> f64 {
+    let (mut low, mut high) = (0.0, g * protocol.get_mean_work(gamma));
+    for _ in 0..100 {
+        let alpha = (low + high) / 2.;
+        if alpha < g * protocol.get_excess_work(gamma, alpha) {
+            low = alpha;
+        } else {
+            high = alpha;
+        }
+    }
+    (low + high) / 2.
+}
+
+// The sums of the rates of the samples and of their squares
+#[derive(Debug, Clone, Copy, Default)]
+struct RateSums {
+    count: usize,
+    sum: f64,
+    squares: f64,
+}
+
+impl RateSums {
+    fn add(&mut self, progresses: &[Vec<Chain>], start: f64, end: f64) {
+        for progresses in progresses {
+            let rate = get_growth_rate(progresses, start, end);
+            self.count += 1;
+            self.sum += rate;
+            self.squares += rate * rate;
+        }
+    }
+
+    fn get_estimate(&self, analytic: f64) -> GrowthEstimate {
+        let count = self.count as f64;
+        let rate = self.sum / count;
+        let variance = (self.squares / count - rate * rate) * count / (count - 1.);
+        let error = (variance.max(0.) / count).sqrt();
+        let deviation = (rate - analytic) / error;
+        GrowthEstimate {
+            rate,
+            error,
+            lower: rate - CONFIDENCE_QUANTILE * error,
+            upper: rate + CONFIDENCE_QUANTILE * error,
+            analytic,
+            deviation,
+            flagged: deviation.abs() > DEVIATION_ERRORS,
+        }
+    }
+}
+
+// Estimates the growth rates of the honest chain, with a delay of 1, and of the adversary's
+// private chain at every (gamma, g), and compares them to the analytic rates. The private
+// chain is the unit-rate chain scaled by g beta / (1 - beta), so its analytic rate is that
+// scaling factor times E[work], which its estimate matches by construction: the adversary
+// check only guards the scaling and can never flag the protocol.
+pub fn simulate_growth(
+    protocol: &dyn Protocol,
+    samples: Samples,
+    beta: f64,
+    g_range: &[f64],
+    gamma_range: &[f64],
+) -> GrowthData {
+    let gamma_range = start_protocol_sweep(protocol, gamma_range);
+    let mut honest_sums = vec![vec![RateSums::default(); g_range.len()]; gamma_range.len()];
+    let mut adversary_sums = honest_sums.clone();
+
+    for chunk in samples.chunks(SUMMATION_BLOCK) {
+        let mut executions =
+            ChunkExecutions::sample_progresses(protocol, chunk, g_range[0], gamma_range[0]);
+
+        for (gamma_index, &gamma) in gamma_range.iter().enumerate() {
+            for (g_index, &g) in g_range.iter().enumerate() {
+                executions.scale_honest(protocol, g, gamma);
+                let end = WINDOW_END * HONEST_COUNT as f64 / g;
+                honest_sums[gamma_index][g_index].add(
+                    &executions.honest_progresses,
+                    WINDOW_START * end / WINDOW_END,
+                    end,
+                );
+
+                executions.scale_adversary(beta, g, gamma);
+                let end = WINDOW_END * ADVERSARY_COUNT as f64 / (g * beta / (1. - beta));
+                adversary_sums[gamma_index][g_index].add(
+                    &executions.scaled_adversary_progresses,
+                    WINDOW_START * end / WINDOW_END,
+                    end,
+                );
+            }
+        }
+    }
+
+    let mut growth_data = GrowthData {
+        honest: Vec::new(),
+        adversary: Vec::new(),
+    };
+    for (gamma_index, &gamma) in gamma_range.iter().enumerate() {
+        println!("gamma: {}", gamma);
+        let mut honest = Vec::new();
+        let mut adversary = Vec::new();
+        for (g_index, &g) in g_range.iter().enumerate() {
+            let honest_estimate = honest_sums[gamma_index][g_index]
+                .get_estimate(get_analytic_growth_rate(protocol, g, gamma));
+            let adversary_estimate = adversary_sums[gamma_index][g_index]
+                .get_estimate(g * beta / (1. - beta) * protocol.get_mean_work(gamma));
+            for (party, estimate) in [
+                ("honest", honest_estimate),
+                ("adversary", adversary_estimate),
+            ] {
+                if estimate.flagged {
+                    println!(
+                        " - g: {}, {} growth rate {} is {:.1} standard errors off {}",
+                        g, party, estimate.rate, estimate.deviation, estimate.analytic
+                    );
+                }
+            }
+            honest.push(honest_estimate);
+            adversary.push(adversary_estimate);
+        }
+        growth_data.honest.push(honest);
+        growth_data.adversary.push(adversary);
+    }
+    growth_data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{Bitcoin, Poem};
+
+    #[test]
+    fn test_analytic_growth_rate() {
+        for g in [0.5, 1., 4.] {
+            let alpha = get_analytic_growth_rate(&Bitcoin, g, 0.);
+            assert!((alpha - g / (1. + g)).abs() < 1e-12);
+            let alpha = get_analytic_growth_rate(&Poem, g, 0.);
+            assert!((alpha * 2f64.powf(alpha) - g / std::f64::consts::LN_2).abs() < 1e-9);
+        }
+        // A bias above the growth rate is always exceeded, so every block is progress
+        let alpha = get_analytic_growth_rate(&Poem, 0.5, 2.);
+        assert!((alpha - 0.5 * Poem.get_mean_work(2.) / 1.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_growth() {
+        // The growth of Bitcoin and of the private chain match their exact rates
+        let growth_data = simulate_growth(&Bitcoin, Samples::new(2, 512), 0.25, &[1.], &[0.]);
+        let honest = growth_data.honest[0][0];
+        assert_eq!(honest.analytic, 0.5);
+        assert!(!honest.flagged && honest.error < 0.01);
+        assert!(honest.lower < honest.analytic && honest.analytic < honest.upper);
+        assert!(
+            (honest.upper - honest.lower - 2. * CONFIDENCE_QUANTILE * honest.error).abs() < 1e-12
+        );
+        assert!(!growth_data.adversary[0][0].flagged);
+    }
+}
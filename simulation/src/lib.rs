@@ -1,5 +1,26 @@
+pub mod adversary;
 pub mod analysis;
+pub mod backbone;
+pub mod checkpoint;
+pub mod confirmation;
+pub mod dag;
+pub mod error;
+pub mod events;
 pub mod formatting;
+pub mod growth;
+pub mod mdp;
+pub mod merge_mining;
+pub mod optimizer;
+pub mod pareto;
+pub mod protocol;
+pub mod ranges;
+pub mod refinement;
+pub mod resilience;
+pub mod rounds;
 pub mod sampling;
+pub mod scenario;
 pub mod simulations;
+pub mod sobol;
+pub mod stationary;
 pub mod types;
+pub mod variance;
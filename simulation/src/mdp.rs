@@ -0,0 +1,530 @@
+use std::{f64::consts::LN_2, path::Path};
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    adversary::{get_common_ancestor, is_ancestor, Adversary},
+    error::Error,
+    events::{BlockId, Engine, EventKind, GENESIS},
+};
+
+// The block work distribution is cut where its tail falls below this probability, which
+// goes to the heaviest increment
+const WORK_TAIL: f64 = 1e-6;
+
+// Value iteration stops once an iteration changes the relative values by less than this
+const VALUE_TOLERANCE: f64 = 1e-9;
+const MAX_ITERATIONS: usize = 100_000;
+
+// The relative revenue is bisected to this precision
+const REVENUE_TOLERANCE: f64 = 1e-5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    // Abandons the private chain for the honest one
+    Adopt,
+    // Publishes the private chain, which outweighs the honest one
+    Override,
+    // Keeps mining privately
+    Wait,
+}
+
+// The selfish mining MDP of Sapirshtein et al., Optimal Selfish Mining Strategies in
+// Bitcoin, 2016, for the intrinsic-work fork choice. A state is the number of blocks of the
+// private and of the honest chain since they forked, and the work of the private chain less
+// the honest one, in steps of 1 / resolution of the mean block work gamma + 1 / ln 2. There
+// is no network delay, so ties have probability zero and there is no match action. The state
+// keeps no work of the prefixes of the private chain, so an override publishes all of it,
+// and the revenue is a lower bound on the optimal one.
+pub struct Mdp {
+    pub beta: f64,
+    pub gamma: f64,
+    // Forks longer than this many blocks on either side are settled
+    pub max_lead: usize,
+    pub resolution: usize,
+    // The work of a step
+    pub step: f64,
+    // The work differences run from -max_difference to max_difference steps
+    pub max_difference: usize,
+    // The probability of each number of steps the work of a block rounds to
+    pub increments: Vec<(usize, f64)>,
+}
+
+impl Mdp {
+    pub fn new(beta: f64, gamma: f64, max_lead: usize, resolution: usize) -> Self {
+        let step = (gamma + 1. / LN_2) / resolution as f64;
+        // The work gamma + X, with X ~ Exp(ln 2), rounds to n steps when it falls within half
+        // a step of n steps. Every block adds at least one step, so that it always outweighs
+        // the chain it extends.
+        let get_cdf = |work: f64| 1. - (-LN_2 * (work - gamma).max(0.)).exp();
+        let mut increments = Vec::new();
+        let mut n = ((gamma / step).round() as usize).max(1);
+        loop {
+            let upper = get_cdf((n as f64 + 0.5) * step);
+            let lower = if increments.is_empty() {
+                0.
+            } else {
+                get_cdf((n as f64 - 0.5) * step)
+            };
+            if 1. - upper < WORK_TAIL {
+                increments.push((n, 1. - lower));
+                break;
+            }
+            increments.push((n, upper - lower));
+            n += 1;
+        }
+        Mdp {
+            beta,
+            gamma,
+            max_lead,
+            resolution,
+            step,
+            max_difference: max_lead * resolution,
+            increments,
+        }
+    }
+
+    fn get_difference_count(&self) -> usize {
+        2 * self.max_difference + 1
+    }
+
+    fn get_state_count(&self) -> usize {
+        (self.max_lead + 1) * (self.max_lead + 1) * self.get_difference_count()
+    }
+
+    // `difference` is offset by max_difference
+    fn get_state(&self, adversary: usize, honest: usize, difference: usize) -> usize {
+        (adversary * (self.max_lead + 1) + honest) * self.get_difference_count() + difference
+    }
+
+    fn get_actions(&self, adversary: usize, honest: usize, difference: usize) -> Vec<Action> {
+        let mut actions = vec![Action::Adopt];
+        if difference > self.max_difference {
+            actions.push(Action::Override);
+        }
+        if adversary < self.max_lead && honest < self.max_lead {
+            actions.push(Action::Wait);
+        }
+        actions
+    }
+
+    // The expected value after the next block, mined by the adversary with probability beta
+    fn get_next_value(
+        &self,
+        values: &[f64],
+        adversary: usize,
+        honest: usize,
+        difference: usize,
+    ) -> f64 {
+        let maximum = self.get_difference_count() - 1;
+        self.increments
+            .iter()
+            .map(|&(n, probability)| {
+                let adversary_block =
+                    values[self.get_state(adversary + 1, honest, (difference + n).min(maximum))];
+                let honest_block =
+                    values[self.get_state(adversary, honest + 1, difference.saturating_sub(n))];
+                probability * (self.beta * adversary_block + (1. - self.beta) * honest_block)
+            })
+            .sum()
+    }
+
+    // The values of the actions, with a reward of the adversary blocks less rho times all
+    // blocks that end up in the chain
+    fn get_action_values(
+        &self,
+        values: &[f64],
+        rho: f64,
+        adversary: usize,
+        honest: usize,
+        difference: usize,
+    ) -> Vec<(Action, f64)> {
+        let reset = self.get_next_value(values, 0, 0, self.max_difference);
+        self.get_actions(adversary, honest, difference)
+            .into_iter()
+            .map(|action| {
+                let value = match action {
+                    Action::Adopt => -rho * honest as f64 + reset,
+                    Action::Override => (1. - rho) * adversary as f64 + reset,
+                    Action::Wait => self.get_next_value(values, adversary, honest, difference),
+                };
+                (action, value)
+            })
+            .collect()
+    }
+
+    fn for_each_state<T: Send>(&self, f: impl Fn(usize, usize, usize) -> T + Sync) -> Vec<T> {
+        (0..self.get_state_count())
+            .into_par_iter()
+            .map(|state| {
+                let difference = state % self.get_difference_count();
+                let honest = state / self.get_difference_count() % (self.max_lead + 1);
+                let adversary = state / self.get_difference_count() / (self.max_lead + 1);
+                f(adversary, honest, difference)
+            })
+            .collect()
+    }
+
+    // Relative value iteration of the average reward, from `values`, returning the gain
+    fn iterate_values(&self, values: &mut Vec<f64>, rho: f64) -> f64 {
+        let reference = self.get_state(0, 0, self.max_difference);
+        let mut gain = 0.;
+        for _ in 0..MAX_ITERATIONS {
+            let next_values = self.for_each_state(|adversary, honest, difference| {
+                self.get_action_values(values, rho, adversary, honest, difference)
+                    .into_iter()
+                    .map(|(_, value)| value)
+                    .fold(f64::NEG_INFINITY, f64::max)
+            });
+            gain = next_values[reference] - values[reference];
+            let (minimum, maximum) = next_values
+                .iter()
+                .zip(values.iter())
+                .map(|(next, value)| next - value)
+                .fold(
+                    (f64::INFINITY, f64::NEG_INFINITY),
+                    |(minimum, maximum), change| (minimum.min(change), maximum.max(change)),
+                );
+            *values = next_values
+                .iter()
+                .map(|value| value - next_values[reference])
+                .collect();
+            if maximum - minimum < VALUE_TOLERANCE {
+                break;
+            }
+        }
+        gain
+    }
+
+    // The optimal relative revenue, the share of the chain the adversary mines, and the
+    // policy that attains it. The gain of the adversary blocks less rho times all blocks
+    // decreases with rho, and is zero at the optimal revenue.
+    pub fn solve_revenue(&self) -> (f64, MdpPolicy) {
+        let mut values = vec![0.; self.get_state_count()];
+        let (mut low, mut high) = (0., 1.);
+        while high - low > REVENUE_TOLERANCE {
+            let rho = (low + high) / 2.;
+            if self.iterate_values(&mut values, rho) > 0. {
+                low = rho;
+            } else {
+                high = rho;
+            }
+        }
+        let revenue = (low + high) / 2.;
+        self.iterate_values(&mut values, revenue);
+        let actions = self.for_each_state(|adversary, honest, difference| {
+            self.get_action_values(&values, revenue, adversary, honest, difference)
+                .into_iter()
+                .fold(
+                    (Action::Adopt, f64::NEG_INFINITY),
+                    |best, (action, value)| {
+                        // Prefers the earlier action unless a later one is clearly better
+                        if value > best.1 + VALUE_TOLERANCE {
+                            (action, value)
+                        } else {
+                            best
+                        }
+                    },
+                )
+                .0
+        });
+        let policy = MdpPolicy {
+            beta: self.beta,
+            gamma: self.gamma,
+            max_lead: self.max_lead,
+            resolution: self.resolution,
+            step: self.step,
+            max_difference: self.max_difference,
+            actions,
+        };
+        (revenue, policy)
+    }
+
+    // The probability that a private chain started with the block of a transaction ever
+    // outweighs the honest chain once that chain holds k work from the transaction on, for
+    // every k of `k_range`. The honest work counts up to the largest k, so that the others
+    // are read from the states with the difference already accumulated.
+    pub fn solve_double_spend(&self, k_range: &[f64]) -> Vec<f64> {
+        let steps = |work: f64| (work / self.step).round() as usize;
+        let confirmation = k_range.iter().map(|&k| steps(k)).max().unwrap_or(0);
+        let differences = self.get_difference_count();
+        let maximum = differences - 1;
+        let get_state = |work: usize, difference: usize| work * differences + difference;
+        // Once confirmed, the adversary wins as soon as it outweighs the honest chain. Chains
+        // further behind than max_difference steps are given up.
+        let mut success = vec![0.; (confirmation + 1) * differences];
+        for _ in 0..MAX_ITERATIONS {
+            let mut change: f64 = 0.;
+            for work in (0..=confirmation).rev() {
+                for difference in 0..differences {
+                    let value = if work == confirmation && difference > self.max_difference {
+                        1.
+                    } else {
+                        self.increments
+                            .iter()
+                            .map(|&(n, probability)| {
+                                let adversary_block =
+                                    success[get_state(work, (difference + n).min(maximum))];
+                                let honest_block = match difference.checked_sub(n) {
+                                    Some(difference) => {
+                                        success[get_state((work + n).min(confirmation), difference)]
+                                    }
+                                    None => 0.,
+                                };
+                                probability
+                                    * (self.beta * adversary_block
+                                        + (1. - self.beta) * honest_block)
+                            })
+                            .sum()
+                    };
+                    let state = get_state(work, difference);
+                    change = change.max((value - success[state]).abs());
+                    success[state] = value;
+                }
+            }
+            if change < VALUE_TOLERANCE {
+                break;
+            }
+        }
+        k_range
+            .iter()
+            .map(|&k| success[get_state(confirmation - steps(k), self.max_difference)])
+            .collect()
+    }
+}
+
+// The actions of a solved MDP in every state, to be replayed in the simulator
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MdpPolicy {
+    pub beta: f64,
+    pub gamma: f64,
+    pub max_lead: usize,
+    pub resolution: usize,
+    pub step: f64,
+    pub max_difference: usize,
+    // Indexed by adversary blocks, then honest blocks, then the work difference in steps
+    // offset by max_difference
+    pub actions: Vec<Action>,
+}
+
+impl MdpPolicy {
+    // Reads a policy written by the mdp experiment
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let contents = std::fs::read_to_string(path)?;
+        let policy: MdpPolicy =
+            serde_json::from_str(&contents).map_err(|error| Error::InvalidParameter {
+                field: "policy".to_string(),
+                message: format!("cannot parse {}: {}", path.display(), error),
+            })?;
+        let states = (policy.max_lead + 1).pow(2) * (2 * policy.max_difference + 1);
+        if policy.actions.len() != states {
+            return Err(Error::InvalidParameter {
+                field: "policy".to_string(),
+                message: format!(
+                    "{} has {} actions, expected one for each of its {} states",
+                    path.display(),
+                    policy.actions.len(),
+                    states
+                ),
+            });
+        }
+        Ok(policy)
+    }
+
+    // The action for a fork, with the counts and the difference clamped to the MDP's states
+    pub fn get_action(&self, adversary: usize, honest: usize, difference: f64) -> Action {
+        let steps = (difference / self.step).round() as i64;
+        let difference =
+            (steps + self.max_difference as i64).clamp(0, 2 * self.max_difference as i64);
+        let state = (adversary.min(self.max_lead) * (self.max_lead + 1)
+            + honest.min(self.max_lead))
+            * (2 * self.max_difference + 1)
+            + difference as usize;
+        self.actions[state]
+    }
+}
+
+// Replays an MDP policy against the honest parties, deciding after every block
+pub struct PolicyMining<'a> {
+    policy: &'a MdpPolicy,
+    // The last block of the private and of the honest chain, and where they fork
+    private: BlockId,
+    public: BlockId,
+    fork: BlockId,
+}
+
+impl<'a> PolicyMining<'a> {
+    pub fn new(policy: &'a MdpPolicy) -> Self {
+        PolicyMining {
+            policy,
+            private: GENESIS,
+            public: GENESIS,
+            fork: GENESIS,
+        }
+    }
+
+    fn act(&mut self, engine: &mut Engine) {
+        let fork = engine.block(self.fork);
+        let (private, public) = (engine.block(self.private), engine.block(self.public));
+        let action = self.policy.get_action(
+            private.height - fork.height,
+            public.height - fork.height,
+            private.chain_work - public.chain_work,
+        );
+        match action {
+            Action::Adopt => {
+                self.private = self.public;
+                self.fork = self.public;
+            }
+            Action::Override if private.chain_work > public.chain_work => {
+                engine.schedule(
+                    engine.time(),
+                    EventKind::Release {
+                        block: self.private,
+                    },
+                );
+                self.public = self.private;
+                self.fork = self.private;
+            }
+            Action::Override | Action::Wait => {}
+        }
+    }
+}
+
+impl Adversary for PolicyMining<'_> {
+    fn get_parent(&self, _engine: &Engine) -> BlockId {
+        self.private
+    }
+
+    fn on_mined(&mut self, block: BlockId, engine: &mut Engine) {
+        self.private = block;
+        self.act(engine);
+    }
+
+    fn on_public_block(&mut self, block: BlockId, engine: &mut Engine) {
+        // The adversary's own releases
+        if is_ancestor(engine, block, self.private) {
+            return;
+        }
+        // Within the network delay, the honest parties may extend a block below the fork
+        self.public = block;
+        self.fork = get_common_ancestor(engine.blocks(), self.private, block);
+        self.act(engine);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        adversary::{run_adversary, simulate_adversaries, Strategy},
+        formatting::scale_monte_carlo_blocks,
+        protocol::{Poem, Protocol},
+        sampling::Samples,
+        simulations::HONEST_COUNT,
+    };
+
+    #[test]
+    fn test_increments() {
+        let mdp = Mdp::new(0.3, 1., 8, 3);
+        let total: f64 = mdp
+            .increments
+            .iter()
+            .map(|(_, probability)| probability)
+            .sum();
+        assert!((total - 1.).abs() < 1e-12);
+        let mean: f64 = mdp
+            .increments
+            .iter()
+            .map(|&(n, probability)| n as f64 * mdp.step * probability)
+            .sum();
+        assert!((mean - (1. + 1. / LN_2)).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_double_spend() {
+        // Waiting longer only lowers the success, and a weak adversary rarely catches up
+        let success = Mdp::new(0.1, 0., 8, 3).solve_double_spend(&[0., 5., 10.]);
+        assert!(success[0] > success[1] && success[1] > success[2]);
+        assert!(success[2] < 0.01);
+    }
+
+    #[test]
+    fn test_revenue() {
+        // A weak adversary gains nothing over honest mining, and the policy replayed in the
+        // simulator earns about what the MDP predicts
+        let (revenue, _) = Mdp::new(0.1, 0., 6, 2).solve_revenue();
+        assert!((revenue - 0.1).abs() < 0.01);
+
+        let (beta, gamma) = (0.4, 0.);
+        let (revenue, policy) = Mdp::new(beta, gamma, 6, 2).solve_revenue();
+        assert!(revenue > beta);
+        let (honest_blocks, adversary_blocks) = Poem.sample_executions(Samples::new(1, 8));
+        let mut scaled_honest_blocks = honest_blocks.clone();
+        scale_monte_carlo_blocks(&mut scaled_honest_blocks, &honest_blocks, 1., gamma);
+        let mut scaled_adversary_blocks = adversary_blocks.clone();
+        scale_monte_carlo_blocks(
+            &mut scaled_adversary_blocks,
+            &adversary_blocks,
+            beta / (1. - beta),
+            gamma,
+        );
+        let simulated: f64 = scaled_honest_blocks
+            .iter()
+            .zip(&scaled_adversary_blocks)
+            .map(|(honest_blocks, adversary_blocks)| {
+                run_adversary(
+                    &mut PolicyMining::new(&policy),
+                    1,
+                    honest_blocks,
+                    &[0; HONEST_COUNT],
+                    adversary_blocks,
+                    0.,
+                    &mut [],
+                )
+                .relative_revenue
+            })
+            .sum::<f64>()
+            / honest_blocks.len() as f64;
+        assert!(
+            (simulated - revenue).abs() < 0.03,
+            "{} {}",
+            simulated,
+            revenue
+        );
+    }
+
+    #[test]
+    fn test_policy_replay() {
+        // A policy written by the mdp experiment loads back and replays against the protocol
+        let (_, policy) = Mdp::new(0.4, 0., 4, 1).solve_revenue();
+        let path = std::env::temp_dir().join("test_policy_replay.json");
+        std::fs::write(&path, serde_json::to_string(&policy).unwrap()).unwrap();
+        assert_eq!(MdpPolicy::load(&path).unwrap(), policy);
+
+        let adversary_data = simulate_adversaries(
+            &Poem,
+            &[Strategy::SelfishMining],
+            Some(&policy),
+            Samples::new(1, 8),
+            8,
+            0.1,
+            &[0.4],
+            1.,
+            0.,
+        );
+        assert_eq!(adversary_data.strategy, ["selfish-mining", "mdp-policy"]);
+        assert!(adversary_data.relative_revenue[1][0] > 0.);
+
+        // A policy cut short is rejected instead of indexing past its actions
+        let truncated = MdpPolicy {
+            actions: policy.actions[1..].to_vec(),
+            ..policy
+        };
+        std::fs::write(&path, serde_json::to_string(&truncated).unwrap()).unwrap();
+        assert!(MdpPolicy::load(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,174 @@
+use crate::{
+    checkpoint::Checkpoint,
+    error::Error,
+    protocol::Protocol,
+    sampling::Samples,
+    simulations::{simulate_protocol, ReductionType, SweepData},
+};
+
+// Results per chain and adversary split, each holding the per-beta optima of the chain
+pub struct MergeMiningData {
+    // The fractions of the adversarial hash power on the first and on the second chain
+    pub adversary_shares: Vec<(f64, f64)>,
+    pub chains: [Vec<SweepData>; 2],
+}
+
+// The adversarial fraction of the hash power pointed at a single chain, when the honest
+// parties put `honest_share` and the adversary `adversary_share` of their power on it
+pub fn get_effective_beta(beta: f64, honest_share: f64, adversary_share: f64) -> f64 {
+    let adversary_rate = beta * adversary_share;
+    let honest_rate = (1. - beta) * honest_share;
+    adversary_rate / (adversary_rate + honest_rate)
+}
+
+// Simulates two chains, each run by the fork choice rule of its protocol, that share the
+// same miners. `honest_split` is the fraction of honest hash power on the first chain, the
+// rest going to the second; `None` means the honest parties merge-mine both chains at full
+// power. Each value of `adversary_split_range` is the fraction of adversarial hash power
+// concentrated on the first chain, the rest attacking the second, so that 0 and 1 leave one
+// chain unattacked. With `merge_mined_adversary`, a last row has the adversary mine both
+// private chains at full power.
+// The g of each chain is the block rate the full honest hash power would achieve on it.
+#[allow(clippy::too_many_arguments)]
+pub fn simulate_merge_mining(
+    protocols: [&dyn Protocol; 2],
+    samples: Samples,
+    monte_carlo: usize,
+    epsilon: f64,
+    beta_range: Vec<f64>,
+    g_range: Vec<f64>,
+    gamma_range: Vec<f64>,
+    honest_split: Option<f64>,
+    adversary_split_range: Vec<f64>,
+    merge_mined_adversary: bool,
+    checkpoint: &mut Checkpoint,
+) -> Result<MergeMiningData, Error> {
+    let honest_shares = match honest_split {
+        Some(split) if split > 0. && split < 1. => [split, 1. - split],
+        Some(split) => {
+            return Err(Error::InvalidParameter {
+                field: "honest_split".to_string(),
+                message: format!("{} must be strictly between 0 and 1", split),
+            })
+        }
+        None => [1., 1.],
+    };
+    let mut adversary_shares = Vec::with_capacity(adversary_split_range.len() + 1);
+    for &split in &adversary_split_range {
+        if !(0. ..=1.).contains(&split) {
+            return Err(Error::InvalidParameter {
+                field: "adversary_split_range".to_string(),
+                message: format!("{} must be between 0 and 1", split),
+            });
+        }
+        adversary_shares.push((split, 1. - split));
+    }
+    if merge_mined_adversary {
+        adversary_shares.push((1., 1.));
+    }
+
+    let mut merge_mining_data = MergeMiningData {
+        adversary_shares: adversary_shares.clone(),
+        chains: [
+            Vec::with_capacity(adversary_shares.len()),
+            Vec::with_capacity(adversary_shares.len()),
+        ],
+    };
+
+    for (first_share, second_share) in adversary_shares {
+        println!(
+            "adversary shares: {} on {}, {} on {}",
+            first_share,
+            protocols[0].title(),
+            second_share,
+            protocols[1].title()
+        );
+        for (chain, adversary_share) in [first_share, second_share].into_iter().enumerate() {
+            let honest_share = honest_shares[chain];
+            let effective_beta_range: Vec<f64> = beta_range
+                .iter()
+                .map(|&beta| get_effective_beta(beta, honest_share, adversary_share))
+                .collect();
+            let mut data = simulate_protocol(
+                protocols[chain],
+                samples,
+                monte_carlo,
+                epsilon,
+                effective_beta_range,
+                g_range.iter().map(|g| g * honest_share).collect(),
+                gamma_range.clone(),
+                ReductionType::Beta,
+                checkpoint,
+            );
+            // Report g in terms of the full honest hash power
+            data.optimal_g.iter_mut().for_each(|g| *g /= honest_share);
+            merge_mining_data.chains[chain].push(data);
+        }
+    }
+
+    Ok(merge_mining_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        protocol::{Bitcoin, Poem},
+        types::INF,
+    };
+
+    #[test]
+    fn test_effective_beta() {
+        // Merge-mined honest parties against a merge-mining adversary
+        assert_eq!(get_effective_beta(0.25, 1., 1.), 0.25);
+        // The adversary leaves the chain alone
+        assert_eq!(get_effective_beta(0.25, 0.5, 0.), 0.);
+        // Honest parties split evenly, the adversary concentrates on this chain
+        assert_eq!(get_effective_beta(0.25, 0.5, 1.), 0.4);
+    }
+
+    #[test]
+    fn test_merge_mining() {
+        let samples = Samples::new(3, 256);
+        let mut checkpoint = Checkpoint::disabled(samples.seed);
+        let data = simulate_merge_mining(
+            [&Bitcoin, &Poem],
+            samples,
+            256,
+            0.1,
+            vec![0.3],
+            vec![1.],
+            vec![0.],
+            Some(0.5),
+            vec![0., 1.],
+            true,
+            &mut checkpoint,
+        )
+        .unwrap();
+        assert_eq!(data.adversary_shares, vec![(0., 1.), (1., 0.), (1., 1.)]);
+        // A chain the adversary leaves alone is safe after its first block, and one it
+        // concentrates on faces an effective beta of 0.46
+        let [bitcoin, poem] = &data.chains;
+        assert!(bitcoin[0].optimal_k[0] <= 1.);
+        assert!(poem[1].optimal_k[0] < INF);
+        assert!(bitcoin[1].optimal_k[0] > bitcoin[0].optimal_k[0]);
+        // Merge-mining attacks both chains at once
+        assert_eq!(bitcoin[2].optimal_k[0], bitcoin[1].optimal_k[0]);
+        assert_eq!(poem[2].optimal_k[0], poem[0].optimal_k[0]);
+
+        let result = simulate_merge_mining(
+            [&Bitcoin, &Poem],
+            samples,
+            256,
+            0.1,
+            vec![0.3],
+            vec![1.],
+            vec![0.],
+            None,
+            vec![1.5],
+            false,
+            &mut checkpoint,
+        );
+        assert!(matches!(result, Err(Error::InvalidParameter { .. })));
+    }
+}
@@ -0,0 +1,314 @@
+use crate::{
+    analysis::{get_monte_carlo_partial_performance, PartialPerformance, SUMMATION_BLOCK},
+    protocol::Protocol,
+    sampling::Samples,
+    simulations::ChunkExecutions,
+    types::INF,
+};
+
+const INVERSE_PHI: f64 = 0.618_033_988_749_894_9;
+// The bracket shrinks this many times before noise may stop the search, to a tenth of its
+// initial width
+const MIN_SHRINK_STEPS: usize = 5;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Evaluation {
+    pub latency: f64,
+    pub latency_error: f64,
+    pub k: f64,
+    // The work the honest chain gains per unit of time
+    pub growth_rate: f64,
+    pub throughput: f64,
+}
+
+pub struct OptimizerData {
+    pub latency: Vec<f64>,
+    pub latency_error: Vec<f64>,
+    pub optimal_k: Vec<f64>,
+    pub optimal_g: Vec<f64>,
+    pub optimal_g_band: Vec<(f64, f64)>,
+    pub optimal_gamma: Vec<f64>,
+    pub optimal_gamma_band: Vec<(f64, f64)>,
+    pub throughput: Vec<f64>,
+    pub evaluations: Vec<usize>,
+}
+
+// Evaluates single (beta, g, gamma) points of a protocol on one set of samples, so that every
+// point an optimizer visits is compared on the same samples. Only the samples are held: each
+// evaluation draws them again a chunk at a time, which common random numbers reproduce, so
+// that the memory does not grow with their number.
+pub struct Evaluator<'a> {
+    protocol: &'a dyn Protocol,
+    samples: Samples,
+    epsilon: f64,
+    pub evaluations: usize,
+}
+
+impl<'a> Evaluator<'a> {
+    pub fn new(protocol: &'a dyn Protocol, samples: Samples, epsilon: f64) -> Self {
+        Evaluator {
+            protocol,
+            samples,
+            epsilon,
+            evaluations: 0,
+        }
+    }
+
+    pub fn evaluate(&mut self, beta: f64, g: f64, gamma: f64) -> Evaluation {
+        let epsilon = self.epsilon;
+        self.evaluate_per_epsilon(beta, g, gamma, &[epsilon])[0]
+    }
+
+    pub fn evaluate_per_epsilon(
+        &mut self,
+        beta: f64,
+        g: f64,
+        gamma: f64,
+        epsilons: &[f64],
+    ) -> Vec<Evaluation> {
+        self.evaluations += 1;
+        // The largest epsilon needs the longest tail
+        let epsilon = epsilons.iter().copied().fold(0.0, f64::max);
+        let mut partial = PartialPerformance::default();
+        for chunk in self.samples.chunks(SUMMATION_BLOCK) {
+            let mut executions = ChunkExecutions::sample_progresses(self.protocol, chunk, g, gamma);
+            executions.scale_adversary(beta, g, gamma);
+            partial.extend(get_monte_carlo_partial_performance(
+                &executions.honest_progresses,
+                &executions.scaled_adversary_progresses,
+                None,
+                None,
+            ));
+            partial.prune(self.samples.len(), epsilon);
+        }
+
+        partial
+            .get_performance_per_epsilon(epsilons)
+            .into_iter()
+            .map(|((k, f_work, f_height, _, _, _, _), k_error)| Evaluation {
+                latency: k / f_work,
+                latency_error: k_error / f_work,
+                k,
+                growth_rate: f_work,
+                throughput: f_height,
+            })
+            .collect()
+    }
+}
+
+// Golden-section search for the minimum latency over [lo, hi]. The search stops once the
+// bracket is narrower than `tolerance` times its initial width, or, after MIN_SHRINK_STEPS,
+// once the two interior points are indistinguishable within their Monte Carlo standard
+// error. The final bracket is returned as the uncertainty band of the optimum.
+fn golden_section_search<T: Copy>(
+    mut lo: f64,
+    mut hi: f64,
+    tolerance: f64,
+    mut objective: impl FnMut(f64) -> (Evaluation, T),
+) -> (f64, Evaluation, T, (f64, f64)) {
+    let min_width = tolerance * (hi - lo);
+    let mut x1 = hi - INVERSE_PHI * (hi - lo);
+    let mut x2 = lo + INVERSE_PHI * (hi - lo);
+    let mut f1 = objective(x1);
+    let mut f2 = objective(x2);
+    let mut steps = 0;
+
+    while hi - lo > min_width {
+        let (latency1, latency2) = (f1.0.latency, f2.0.latency);
+        if steps >= MIN_SHRINK_STEPS && latency1 != INF && latency2 != INF {
+            let noise = (f1.0.latency_error.powi(2) + f2.0.latency_error.powi(2)).sqrt();
+            if (latency1 - latency2).abs() <= noise {
+                break;
+            }
+        }
+
+        // When neither point is secure, shrink towards the lower end of the bracket
+        if latency1 <= latency2 {
+            hi = x2;
+            x2 = x1;
+            f2 = f1;
+            x1 = hi - INVERSE_PHI * (hi - lo);
+            f1 = objective(x1);
+        } else {
+            lo = x1;
+            x1 = x2;
+            f1 = f2;
+            x2 = lo + INVERSE_PHI * (hi - lo);
+            f2 = objective(x2);
+        }
+        steps += 1;
+    }
+
+    if f1.0.latency <= f2.0.latency {
+        (x1, f1.0, f1.1, (lo, hi))
+    } else {
+        (x2, f2.0, f2.1, (lo, hi))
+    }
+}
+
+// Nested golden-section search: log g on the outside, gamma on the inside. Latency is convex
+// in both, so each inner search returns the best gamma for the g under consideration.
+pub fn optimize(
+    evaluator: &mut Evaluator,
+    beta: f64,
+    (g_min, g_max): (f64, f64),
+    (gamma_min, gamma_max): (f64, f64),
+    tolerance: f64,
+) -> (f64, (f64, f64), f64, (f64, f64), Evaluation) {
+    let (log_g, evaluation, (gamma, gamma_band), (log_g_lo, log_g_hi)) =
+        golden_section_search(g_min.ln(), g_max.ln(), tolerance, |log_g| {
+            let g = log_g.exp();
+            if gamma_min == gamma_max {
+                return (
+                    evaluator.evaluate(beta, g, gamma_min),
+                    (gamma_min, (gamma_min, gamma_max)),
+                );
+            }
+            let (gamma, evaluation, _, gamma_band) =
+                golden_section_search(gamma_min, gamma_max, tolerance, |gamma| {
+                    (evaluator.evaluate(beta, g, gamma), ())
+                });
+            (evaluation, (gamma, gamma_band))
+        });
+
+    (
+        log_g.exp(),
+        (log_g_lo.exp(), log_g_hi.exp()),
+        gamma,
+        gamma_band,
+        evaluation,
+    )
+}
+
+fn optimize_beta_range(
+    mut evaluator: Evaluator,
+    beta_range: Vec<f64>,
+    g_bounds: (f64, f64),
+    gamma_bounds: (f64, f64),
+    tolerance: f64,
+) -> OptimizerData {
+    let mut optimizer_data = OptimizerData {
+        latency: vec![INF; beta_range.len()],
+        latency_error: vec![INF; beta_range.len()],
+        optimal_k: vec![INF; beta_range.len()],
+        optimal_g: vec![0.0; beta_range.len()],
+        optimal_g_band: vec![(0.0, 0.0); beta_range.len()],
+        optimal_gamma: vec![0.0; beta_range.len()],
+        optimal_gamma_band: vec![(0.0, 0.0); beta_range.len()],
+        throughput: vec![0.0; beta_range.len()],
+        evaluations: vec![0; beta_range.len()],
+    };
+
+    for (beta_index, &beta) in beta_range.iter().enumerate() {
+        println!("beta: {}", beta);
+        evaluator.evaluations = 0;
+        let (g, g_band, gamma, gamma_band, evaluation) =
+            optimize(&mut evaluator, beta, g_bounds, gamma_bounds, tolerance);
+
+        optimizer_data.latency[beta_index] = evaluation.latency;
+        optimizer_data.latency_error[beta_index] = evaluation.latency_error;
+        optimizer_data.optimal_k[beta_index] = evaluation.k;
+        optimizer_data.optimal_g[beta_index] = g;
+        optimizer_data.optimal_g_band[beta_index] = g_band;
+        optimizer_data.optimal_gamma[beta_index] = gamma;
+        optimizer_data.optimal_gamma_band[beta_index] = gamma_band;
+        optimizer_data.throughput[beta_index] = evaluation.throughput;
+        optimizer_data.evaluations[beta_index] = evaluator.evaluations;
+    }
+    optimizer_data
+}
+
+// A protocol without a bias is searched over g alone
+pub fn optimize_protocol(
+    protocol: &dyn Protocol,
+    samples: Samples,
+    epsilon: f64,
+    beta_range: Vec<f64>,
+    g_bounds: (f64, f64),
+    gamma_bounds: (f64, f64),
+    tolerance: f64,
+) -> OptimizerData {
+    println!("Optimizing {}...", protocol.title());
+    let gamma_bounds = if protocol.has_bias() {
+        gamma_bounds
+    } else {
+        (0.0, 0.0)
+    };
+    optimize_beta_range(
+        Evaluator::new(protocol, samples, epsilon),
+        beta_range,
+        g_bounds,
+        gamma_bounds,
+        tolerance,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        protocol::{Bitcoin, Poem, PoemDag},
+        simulations::evaluate_performance,
+    };
+
+    #[test]
+    fn test_evaluator() {
+        // The evaluator scores the chains of each protocol like the sweeps do
+        let samples = Samples::new(5, 256);
+        for protocol in [&Bitcoin as &dyn Protocol, &Poem, &PoemDag] {
+            let gamma = if protocol.has_bias() { 0.5 } else { 0.0 };
+            let evaluation = Evaluator::new(protocol, samples, 0.1).evaluate(0.25, 1.5, gamma);
+            let (k, f_work, ..) =
+                evaluate_performance(protocol, samples, 256, 0.1, 0.25, 1.5, gamma);
+            assert_eq!(evaluation.k, k);
+            assert!((evaluation.latency - k / f_work).abs() < 1e-9 * evaluation.latency);
+        }
+    }
+    #[test]
+    fn test_evaluator_chunks() {
+        // Streamed over several chunks, the evaluator keeps the tail the sweeps keep
+        let samples = Samples::new(8, 2 * SUMMATION_BLOCK);
+        let evaluation = Evaluator::new(&Bitcoin, samples, 0.1).evaluate(0.3, 1., 0.);
+        let (k, f_work, ..) =
+            evaluate_performance(&Bitcoin, samples, samples.len(), 0.1, 0.3, 1., 0.);
+        assert!((evaluation.k - k).abs() < 1e-9);
+        assert!((evaluation.growth_rate - f_work).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_golden_section_search() {
+        let parabola = |x: f64| {
+            (
+                Evaluation {
+                    latency: (x - 2.0).powi(2) + 1.0,
+                    latency_error: 0.0,
+                    k: 0.0,
+                    growth_rate: 0.0,
+                    throughput: 0.0,
+                },
+                (),
+            )
+        };
+        let (x, evaluation, _, (lo, hi)) = golden_section_search(0.0, 5.0, 1e-6, parabola);
+        assert!((x - 2.0).abs() < 1e-4);
+        assert!((evaluation.latency - 1.0).abs() < 1e-8);
+        assert!(lo <= x && x <= hi);
+
+        // Points within the noise of each other stop the search with a wide band, but only
+        // once it has shrunk MIN_SHRINK_STEPS times
+        let noisy_parabola = |x: f64| {
+            let (evaluation, _) = parabola(x);
+            (
+                Evaluation {
+                    latency_error: 10.0,
+                    ..evaluation
+                },
+                (),
+            )
+        };
+        let (_, _, _, (lo, hi)) = golden_section_search(0.0, 5.0, 1e-6, noisy_parabola);
+        let width = 5.0 * INVERSE_PHI.powi(MIN_SHRINK_STEPS as i32);
+        assert!((hi - lo - width).abs() < 1e-9);
+        assert!(lo <= 2.0 && 2.0 <= hi);
+    }
+}
@@ -0,0 +1,161 @@
+use crate::{optimizer::Evaluator, protocol::Protocol, sampling::Samples, types::INF};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Configuration {
+    pub beta: f64,
+    pub g: f64,
+    pub gamma: f64,
+    pub epsilon: f64,
+    pub latency: f64,
+    pub k: f64,
+    pub throughput: f64,
+}
+
+pub struct ParetoData {
+    pub configurations: Vec<Configuration>,
+    pub pareto_optimal: Vec<bool>,
+}
+
+// Lower latency, higher throughput and lower epsilon are better
+fn dominates(a: &Configuration, b: &Configuration) -> bool {
+    a.latency <= b.latency
+        && a.throughput >= b.throughput
+        && a.epsilon <= b.epsilon
+        && (a.latency < b.latency || a.throughput > b.throughput || a.epsilon < b.epsilon)
+}
+
+// Marks the configurations that no other configuration with the same beta dominates.
+// Sorting by latency, then throughput, then epsilon puts every dominating configuration
+// before the ones it dominates, so each candidate only needs checking against the
+// frontier found so far. Insecure configurations are never Pareto-optimal.
+pub fn get_pareto_optimal(configurations: &[Configuration]) -> Vec<bool> {
+    let mut pareto_optimal = vec![false; configurations.len()];
+    let mut order: Vec<usize> = (0..configurations.len())
+        .filter(|&i| configurations[i].latency != INF)
+        .collect();
+    order.sort_by(|&a, &b| {
+        let (a, b) = (&configurations[a], &configurations[b]);
+        a.beta
+            .partial_cmp(&b.beta)
+            .unwrap()
+            .then(a.latency.partial_cmp(&b.latency).unwrap())
+            .then(b.throughput.partial_cmp(&a.throughput).unwrap())
+            .then(a.epsilon.partial_cmp(&b.epsilon).unwrap())
+    });
+
+    let mut frontier: Vec<usize> = vec![];
+    for i in order {
+        if frontier
+            .last()
+            .is_some_and(|&f| configurations[f].beta != configurations[i].beta)
+        {
+            frontier.clear();
+        }
+        if frontier
+            .iter()
+            .any(|&f| dominates(&configurations[f], &configurations[i]))
+        {
+            continue;
+        }
+        frontier.push(i);
+        pareto_optimal[i] = true;
+    }
+    pareto_optimal
+}
+
+fn sweep_configurations(
+    mut evaluator: Evaluator,
+    beta_range: Vec<f64>,
+    g_range: Vec<f64>,
+    gamma_range: Vec<f64>,
+    epsilon_range: Vec<f64>,
+) -> ParetoData {
+    let mut configurations =
+        Vec::with_capacity(beta_range.len() * g_range.len() * gamma_range.len());
+
+    for &gamma in gamma_range.iter() {
+        println!("gamma: {}", gamma);
+        for &g in g_range.iter() {
+            println!(" - g: {}", g);
+            for &beta in beta_range.iter() {
+                let evaluations = evaluator.evaluate_per_epsilon(beta, g, gamma, &epsilon_range);
+                for (&epsilon, evaluation) in epsilon_range.iter().zip(evaluations) {
+                    configurations.push(Configuration {
+                        beta,
+                        g,
+                        gamma,
+                        epsilon,
+                        latency: evaluation.latency,
+                        k: evaluation.k,
+                        throughput: evaluation.throughput,
+                    });
+                }
+            }
+        }
+    }
+
+    let pareto_optimal = get_pareto_optimal(&configurations);
+    ParetoData {
+        configurations,
+        pareto_optimal,
+    }
+}
+
+// A protocol without a bias is swept over the single gamma = 0
+pub fn simulate_pareto(
+    protocol: &dyn Protocol,
+    samples: Samples,
+    beta_range: Vec<f64>,
+    g_range: Vec<f64>,
+    gamma_range: Vec<f64>,
+    epsilon_range: Vec<f64>,
+) -> ParetoData {
+    println!("Working on {}...", protocol.title());
+    let gamma_range = if protocol.has_bias() {
+        gamma_range
+    } else {
+        vec![0.0]
+    };
+    sweep_configurations(
+        Evaluator::new(protocol, samples, epsilon_range[0]),
+        beta_range,
+        g_range,
+        gamma_range,
+        epsilon_range,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_pareto_optimal() {
+        let configuration = |beta, latency, throughput, epsilon| Configuration {
+            beta,
+            g: 1.0,
+            gamma: 0.0,
+            epsilon,
+            latency,
+            k: 0.0,
+            throughput,
+        };
+        let configurations = vec![
+            configuration(0.1, 5.0, 1.0, 0.1),
+            // Dominated by the first in every objective
+            configuration(0.1, 6.0, 0.5, 0.1),
+            // Slower but more secure
+            configuration(0.1, 7.0, 1.0, 0.01),
+            // Faster but with lower throughput
+            configuration(0.1, 4.0, 0.5, 0.1),
+            // Insecure
+            configuration(0.1, INF, 2.0, 0.1),
+            // A different beta is never compared against the others
+            configuration(0.2, 9.0, 0.1, 0.1),
+        ];
+
+        assert_eq!(
+            get_pareto_optimal(&configurations),
+            vec![true, false, true, true, false, true]
+        );
+    }
+}
@@ -0,0 +1,208 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dag::get_monte_carlo_dag_progresses,
+    formatting::get_monte_carlo_progresses,
+    sampling::{
+        get_monte_carlo_bitcoin_executions, sample_monte_carlo_execution_timestamps,
+        sample_monte_carlo_poem_executions, Samples,
+    },
+    simulations::{ADVERSARY_COUNT, HONEST_COUNT},
+    types::{Block, Chain},
+};
+
+// The honest and adversary blocks of every sample
+pub type Executions = (Vec<[Block; HONEST_COUNT]>, Vec<[Block; ADVERSARY_COUNT]>);
+
+// What the sweeps need to know about a protocol. The adversary always mines a private chain,
+// which the protocol scores with get_adversary_progresses.
+pub trait Protocol: Sync {
+    // Names the points of the protocol in checkpoints
+    fn name(&self) -> &'static str;
+
+    fn title(&self) -> &'static str;
+
+    // Samples the blocks of both parties, timestamps and work
+    fn sample_executions(&self, samples: Samples) -> Executions;
+
+    // Whether every block adds the bias gamma to its work. A protocol without one is swept
+    // over the single gamma = 0.
+    fn has_bias(&self) -> bool;
+
+    // The expected work of a block with the bias gamma
+    fn get_mean_work(&self, gamma: f64) -> f64;
+
+    // E[max(work - threshold, 0)], the expected work by which a block exceeds the threshold
+    fn get_excess_work(&self, gamma: f64, threshold: f64) -> f64;
+
+    // Scores the honest blocks of each sample, with a delay of 1, by the fork choice rule of
+    // the honest parties: the progress of the chain they extend, in the order it was made
+    fn get_honest_progresses(
+        &self,
+        progresses: &mut [Vec<Chain>],
+        blocks: &[[Block; HONEST_COUNT]],
+    ) {
+        get_monte_carlo_progresses(progresses, blocks, 1.);
+    }
+
+    // Scores the private chain of the adversary in each sample, which it extends without
+    // delay, by the fork choice rule: by default the sum of the work of its blocks. The
+    // sweeps scale the progress per beta and add the bias gamma once per block of height.
+    fn get_adversary_progresses(
+        &self,
+        progresses: &mut [Vec<Chain>],
+        blocks: &[[Block; ADVERSARY_COUNT]],
+    ) {
+        get_monte_carlo_progresses(progresses, blocks, 0.);
+    }
+}
+
+// The protocols an experiment can be asked to run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProtocolName {
+    Bitcoin,
+    Poem,
+    PoemDag,
+}
+
+impl ProtocolName {
+    pub fn get(&self) -> &'static dyn Protocol {
+        match self {
+            ProtocolName::Bitcoin => &Bitcoin,
+            ProtocolName::Poem => &Poem,
+            ProtocolName::PoemDag => &PoemDag,
+        }
+    }
+}
+
+// Every block has work 1, so the heaviest chain is the longest
+pub struct Bitcoin;
+
+// Every block has exponentially distributed intrinsic work, plus the bias gamma
+pub struct Poem;
+
+// PoEM blocks forming a DAG, in which each block references every visible tip and the
+// honest parties extend the heaviest past cone
+pub struct PoemDag;
+
+impl Protocol for Bitcoin {
+    fn name(&self) -> &'static str {
+        "bitcoin"
+    }
+
+    fn title(&self) -> &'static str {
+        "Bitcoin"
+    }
+
+    fn sample_executions(&self, samples: Samples) -> Executions {
+        get_monte_carlo_bitcoin_executions(&sample_monte_carlo_execution_timestamps::<
+            HONEST_COUNT,
+            ADVERSARY_COUNT,
+        >(samples))
+    }
+
+    fn has_bias(&self) -> bool {
+        false
+    }
+
+    fn get_mean_work(&self, _gamma: f64) -> f64 {
+        1.
+    }
+
+    fn get_excess_work(&self, _gamma: f64, threshold: f64) -> f64 {
+        (1. - threshold).max(0.)
+    }
+}
+
+impl Protocol for Poem {
+    fn name(&self) -> &'static str {
+        "poem"
+    }
+
+    fn title(&self) -> &'static str {
+        "PoEM"
+    }
+
+    fn sample_executions(&self, samples: Samples) -> Executions {
+        sample_monte_carlo_poem_executions(
+            &sample_monte_carlo_execution_timestamps::<HONEST_COUNT, ADVERSARY_COUNT>(samples),
+            samples,
+        )
+    }
+
+    fn has_bias(&self) -> bool {
+        true
+    }
+
+    // Exp(ln 2) has mean 1 / ln 2
+    fn get_mean_work(&self, gamma: f64) -> f64 {
+        gamma + std::f64::consts::LOG2_E
+    }
+
+    // Past gamma, the exponential is memoryless
+    fn get_excess_work(&self, gamma: f64, threshold: f64) -> f64 {
+        if threshold <= gamma {
+            self.get_mean_work(gamma) - threshold
+        } else {
+            2f64.powf(gamma - threshold) * std::f64::consts::LOG2_E
+        }
+    }
+}
+
+impl Protocol for PoemDag {
+    fn name(&self) -> &'static str {
+        "poem-dag"
+    }
+
+    fn title(&self) -> &'static str {
+        "PoEM DAG"
+    }
+
+    fn sample_executions(&self, samples: Samples) -> Executions {
+        Poem.sample_executions(samples)
+    }
+
+    fn has_bias(&self) -> bool {
+        true
+    }
+
+    fn get_mean_work(&self, gamma: f64) -> f64 {
+        Poem.get_mean_work(gamma)
+    }
+
+    fn get_excess_work(&self, gamma: f64, threshold: f64) -> f64 {
+        Poem.get_excess_work(gamma, threshold)
+    }
+
+    fn get_honest_progresses(
+        &self,
+        progresses: &mut [Vec<Chain>],
+        blocks: &[[Block; HONEST_COUNT]],
+    ) {
+        get_monte_carlo_dag_progresses(progresses, blocks, 1.);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_common_timestamps() {
+        // Every protocol mines its blocks at the same times on the same samples, and only
+        // their work differs
+        let samples = Samples::new(7, 2);
+        let (bitcoin_honest, bitcoin_adversary) = Bitcoin.sample_executions(samples);
+        let (poem_honest, poem_adversary) = Poem.sample_executions(samples);
+        for (bitcoin, poem) in bitcoin_honest.iter().zip(&poem_honest) {
+            for (bitcoin, poem) in bitcoin.iter().zip(poem) {
+                assert_eq!(bitcoin.timestamp, poem.timestamp);
+                assert_eq!(bitcoin.work, 1.);
+            }
+        }
+        for (bitcoin, poem) in bitcoin_adversary.iter().zip(&poem_adversary) {
+            assert_eq!(bitcoin[0].timestamp, poem[0].timestamp);
+        }
+        assert!(!Bitcoin.has_bias() && Poem.has_bias() && PoemDag.has_bias());
+    }
+}
@@ -0,0 +1,72 @@
+use crate::error::Error;
+
+#[derive(Debug)]
+pub struct RangeParseError;
+
+impl std::fmt::Display for RangeParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Invalid range format. Use the format: start:end:num_points"
+        )
+    }
+}
+
+impl std::error::Error for RangeParseError {}
+
+pub fn logarithmic_range(start: f64, end: f64, num_points: usize, exponent: f64) -> Vec<f64> {
+    if num_points == 1 {
+        return vec![start];
+    }
+    (0..num_points)
+        .map(|i| {
+            let fraction = (i as f64 / (num_points - 1) as f64).powf(exponent);
+            start * (end / start).powf(fraction)
+        })
+        .collect::<Vec<f64>>()
+}
+
+pub fn linear_range(start: f64, end: f64, num_points: usize) -> Vec<f64> {
+    if num_points == 1 {
+        return vec![start];
+    }
+    (0..num_points)
+        .map(|i| start + (end - start) * (i as f64 / (num_points - 1) as f64))
+        .collect::<Vec<f64>>()
+}
+
+pub fn parse_range(s: &str, exponent: Option<f64>) -> Result<Vec<f64>, RangeParseError> {
+    let parts: Vec<&str> = s.split(':').collect();
+
+    if parts.len() != 3 {
+        return Err(RangeParseError);
+    }
+    let start: f64 = parts[0].trim().parse().map_err(|_| RangeParseError)?;
+    let end: f64 = parts[1].trim().parse().map_err(|_| RangeParseError)?;
+    let num_points: usize = parts[2].trim().parse().map_err(|_| RangeParseError)?;
+    if num_points == 0 {
+        return Err(RangeParseError);
+    }
+
+    if let Some(exp) = exponent {
+        if start == 0.0 {
+            let mut range = logarithmic_range(start + 0.005, end, num_points, exp);
+            if range.len() > 1 {
+                range.remove(0);
+            }
+            range.insert(0, 0.0);
+            return Ok(range);
+        }
+        Ok(logarithmic_range(start, end, num_points, exp))
+    } else {
+        Ok(linear_range(start, end, num_points))
+    }
+}
+
+// Parses the range given for `field`, naming the field in the error
+pub fn parse_field_range(field: &str, s: &str, exponent: Option<f64>) -> Result<Vec<f64>, Error> {
+    parse_range(s, exponent).map_err(|source| Error::Range {
+        field: field.to_string(),
+        source,
+    })
+}
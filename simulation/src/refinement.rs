@@ -0,0 +1,212 @@
+use crate::{
+    optimizer::{Evaluation, Evaluator},
+    protocol::Protocol,
+    sampling::Samples,
+    types::INF,
+};
+
+const MAX_REFINEMENTS: usize = 20;
+
+#[derive(Debug, Clone, Copy)]
+pub struct GridPoint {
+    pub beta: f64,
+    pub g: f64,
+    pub gamma: f64,
+    pub refinement: usize,
+    pub latency: f64,
+    pub k: f64,
+    pub throughput: f64,
+}
+
+pub struct RefinementData {
+    pub latency: Vec<f64>,
+    pub optimal_k: Vec<f64>,
+    pub optimal_g: Vec<f64>,
+    pub optimal_gamma: Vec<f64>,
+    pub throughput: Vec<f64>,
+    pub refinements: Vec<usize>,
+    pub points: Vec<GridPoint>,
+}
+
+// The grid values adjacent to the best one, which bracket the refined grid
+fn get_neighbours(grid: &[f64], index: usize) -> (f64, f64) {
+    let lo = grid[index.saturating_sub(1)];
+    let hi = grid[(index + 1).min(grid.len() - 1)];
+    (lo, hi)
+}
+
+fn refine_range(lo: f64, hi: f64, num_points: usize, logarithmic: bool) -> Vec<f64> {
+    if lo == hi || num_points < 2 {
+        return vec![lo];
+    }
+    (0..num_points)
+        .map(|i| {
+            let fraction = i as f64 / (num_points - 1) as f64;
+            if logarithmic && lo > 0.0 {
+                lo * (hi / lo).powf(fraction)
+            } else {
+                lo + (hi - lo) * fraction
+            }
+        })
+        .collect()
+}
+
+// Evaluates every (g, gamma) of the grid and returns the indices and evaluation of the best
+fn evaluate_grid(
+    evaluator: &mut Evaluator,
+    beta: f64,
+    g_grid: &[f64],
+    gamma_grid: &[f64],
+    refinement: usize,
+    points: &mut Vec<GridPoint>,
+) -> (usize, usize, Evaluation) {
+    let mut best = (0, 0, None::<Evaluation>);
+    for (gamma_index, &gamma) in gamma_grid.iter().enumerate() {
+        for (g_index, &g) in g_grid.iter().enumerate() {
+            let evaluation = evaluator.evaluate(beta, g, gamma);
+            points.push(GridPoint {
+                beta,
+                g,
+                gamma,
+                refinement,
+                latency: evaluation.latency,
+                k: evaluation.k,
+                throughput: evaluation.throughput,
+            });
+            if best.2.is_none_or(|b| evaluation.latency < b.latency) {
+                best = (g_index, gamma_index, Some(evaluation));
+            }
+        }
+    }
+    (best.0, best.1, best.2.unwrap())
+}
+
+// Starts from the coarse g x gamma grid and repeatedly zooms in between the neighbours of the
+// best point, with `refinement_points` values per axis, until the relative latency improvement
+// of a pass drops below `threshold`
+fn refine_beta_range(
+    mut evaluator: Evaluator,
+    beta_range: Vec<f64>,
+    g_range: Vec<f64>,
+    gamma_range: Vec<f64>,
+    refinement_points: usize,
+    threshold: f64,
+) -> RefinementData {
+    let mut refinement_data = RefinementData {
+        latency: vec![INF; beta_range.len()],
+        optimal_k: vec![INF; beta_range.len()],
+        optimal_g: vec![0.0; beta_range.len()],
+        optimal_gamma: vec![0.0; beta_range.len()],
+        throughput: vec![0.0; beta_range.len()],
+        refinements: vec![0; beta_range.len()],
+        points: vec![],
+    };
+
+    for (beta_index, &beta) in beta_range.iter().enumerate() {
+        println!("beta: {}", beta);
+        let mut g_grid = g_range.clone();
+        let mut gamma_grid = gamma_range.clone();
+        let (mut g_index, mut gamma_index, mut best) = evaluate_grid(
+            &mut evaluator,
+            beta,
+            &g_grid,
+            &gamma_grid,
+            0,
+            &mut refinement_data.points,
+        );
+        let mut best_g = g_grid[g_index];
+        let mut best_gamma = gamma_grid[gamma_index];
+
+        for refinement in 1..=MAX_REFINEMENTS {
+            let (g_lo, g_hi) = get_neighbours(&g_grid, g_index);
+            let (gamma_lo, gamma_hi) = get_neighbours(&gamma_grid, gamma_index);
+            g_grid = refine_range(g_lo, g_hi, refinement_points, true);
+            gamma_grid = refine_range(gamma_lo, gamma_hi, refinement_points, false);
+            println!(
+                " - refinement {}: g in [{}, {}], gamma in [{}, {}]",
+                refinement, g_lo, g_hi, gamma_lo, gamma_hi
+            );
+
+            let (new_g_index, new_gamma_index, evaluated) = evaluate_grid(
+                &mut evaluator,
+                beta,
+                &g_grid,
+                &gamma_grid,
+                refinement,
+                &mut refinement_data.points,
+            );
+            g_index = new_g_index;
+            gamma_index = new_gamma_index;
+            refinement_data.refinements[beta_index] = refinement;
+
+            let improvement = if best.latency == INF {
+                if evaluated.latency == INF {
+                    0.0
+                } else {
+                    INF
+                }
+            } else {
+                (best.latency - evaluated.latency) / best.latency
+            };
+            if evaluated.latency < best.latency {
+                best = evaluated;
+                best_g = g_grid[g_index];
+                best_gamma = gamma_grid[gamma_index];
+            }
+            if improvement < threshold {
+                break;
+            }
+        }
+
+        refinement_data.latency[beta_index] = best.latency;
+        refinement_data.optimal_k[beta_index] = best.k;
+        refinement_data.optimal_g[beta_index] = best_g;
+        refinement_data.optimal_gamma[beta_index] = best_gamma;
+        refinement_data.throughput[beta_index] = best.throughput;
+    }
+    refinement_data
+}
+
+// A protocol without a bias is refined over g alone
+#[allow(clippy::too_many_arguments)]
+pub fn refine_protocol(
+    protocol: &dyn Protocol,
+    samples: Samples,
+    epsilon: f64,
+    beta_range: Vec<f64>,
+    g_range: Vec<f64>,
+    gamma_range: Vec<f64>,
+    refinement_points: usize,
+    threshold: f64,
+) -> RefinementData {
+    println!("Refining {}...", protocol.title());
+    let gamma_range = if protocol.has_bias() {
+        gamma_range
+    } else {
+        vec![0.0]
+    };
+    refine_beta_range(
+        Evaluator::new(protocol, samples, epsilon),
+        beta_range,
+        g_range,
+        gamma_range,
+        refinement_points,
+        threshold,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_refine_range() {
+        let grid = vec![0.0, 1.0, 2.0, 4.0];
+        assert_eq!(get_neighbours(&grid, 0), (0.0, 1.0));
+        assert_eq!(get_neighbours(&grid, 2), (1.0, 4.0));
+        assert_eq!(get_neighbours(&grid, 3), (2.0, 4.0));
+
+        assert_eq!(refine_range(1.0, 4.0, 3, true), vec![1.0, 2.0, 4.0]);
+        assert_eq!(refine_range(0.0, 4.0, 3, false), vec![0.0, 2.0, 4.0]);
+        assert_eq!(refine_range(2.0, 2.0, 3, false), vec![2.0]);
+    }
+}
@@ -0,0 +1,153 @@
+use crate::{
+    optimizer::Evaluator, protocol::Protocol, sampling::Samples, simulations::start_protocol_sweep,
+    types::INF,
+};
+
+// The largest tolerable beta at one (g, gamma), with the point that decided it
+#[derive(Debug, Clone, Copy)]
+pub struct Resilience {
+    pub beta: f64,
+    // k at that beta
+    pub k: f64,
+    pub honest_growth_rate: f64,
+    pub adversary_growth_rate: f64,
+    // The beta at which the adversary would grow as fast as the honest chain, which bounds
+    // the resilience from above
+    pub growth_beta: f64,
+}
+
+// The largest tolerable beta at each (gamma, g), with the point that decided it
+pub struct ResilienceData {
+    pub beta: Vec<Vec<f64>>,
+    pub k: Vec<Vec<f64>>,
+    pub honest_growth_rate: Vec<Vec<f64>>,
+    pub adversary_growth_rate: Vec<Vec<f64>>,
+    pub growth_beta: Vec<Vec<f64>>,
+}
+
+// The work the adversary's private chain gains per unit of time
+fn get_adversary_growth_rate(protocol: &dyn Protocol, beta: f64, g: f64, gamma: f64) -> f64 {
+    g * beta / (1. - beta) * protocol.get_mean_work(gamma)
+}
+
+// Bisects beta over (0, 1/2) for the largest one at which k is finite and the private chain
+// grows slower than the honest chain, to within `tolerance`. Both only get worse as beta
+// grows. Every beta is evaluated on the samples of the evaluator, streamed a chunk at a time.
+// When no beta is safe, everything but k is 0.
+pub fn search_resilience(
+    protocol: &dyn Protocol,
+    evaluator: &mut Evaluator,
+    g: f64,
+    gamma: f64,
+    tolerance: f64,
+) -> Resilience {
+    let (mut safe, mut unsafe_beta) = (0.0, 0.5);
+    let (mut k, mut honest_growth_rate) = (INF, 0.0);
+    while unsafe_beta - safe > tolerance {
+        let beta = (safe + unsafe_beta) / 2.;
+        let evaluation = evaluator.evaluate(beta, g, gamma);
+        if evaluation.k < INF
+            && get_adversary_growth_rate(protocol, beta, g, gamma) < evaluation.growth_rate
+        {
+            safe = beta;
+            k = evaluation.k;
+            honest_growth_rate = evaluation.growth_rate;
+        } else {
+            unsafe_beta = beta;
+        }
+    }
+    // g beta / (1 - beta) E[work] = f_work
+    let ratio = honest_growth_rate / (g * protocol.get_mean_work(gamma));
+    Resilience {
+        beta: safe,
+        k,
+        honest_growth_rate,
+        adversary_growth_rate: get_adversary_growth_rate(protocol, safe, g, gamma),
+        growth_beta: ratio / (1. + ratio),
+    }
+}
+
+// Searches the resilience of the protocol at every (gamma, g), all on the same samples. A
+// protocol without a per-block bias is searched at the single gamma = 0.
+pub fn simulate_resilience(
+    protocol: &dyn Protocol,
+    samples: Samples,
+    epsilon: f64,
+    g_range: &[f64],
+    gamma_range: &[f64],
+    tolerance: f64,
+) -> ResilienceData {
+    let gamma_range = start_protocol_sweep(protocol, gamma_range);
+    let mut evaluator = Evaluator::new(protocol, samples, epsilon);
+    let mut resilience_data = ResilienceData {
+        beta: Vec::new(),
+        k: Vec::new(),
+        honest_growth_rate: Vec::new(),
+        adversary_growth_rate: Vec::new(),
+        growth_beta: Vec::new(),
+    };
+    for &gamma in gamma_range {
+        println!("gamma: {}", gamma);
+        let results: Vec<_> = g_range
+            .iter()
+            .map(|&g| {
+                let result = search_resilience(protocol, &mut evaluator, g, gamma, tolerance);
+                println!(" - g: {}, beta: {}", g, result.beta);
+                result
+            })
+            .collect();
+        resilience_data
+            .beta
+            .push(results.iter().map(|result| result.beta).collect());
+        resilience_data
+            .k
+            .push(results.iter().map(|result| result.k).collect());
+        resilience_data.honest_growth_rate.push(
+            results
+                .iter()
+                .map(|result| result.honest_growth_rate)
+                .collect(),
+        );
+        resilience_data.adversary_growth_rate.push(
+            results
+                .iter()
+                .map(|result| result.adversary_growth_rate)
+                .collect(),
+        );
+        resilience_data
+            .growth_beta
+            .push(results.iter().map(|result| result.growth_beta).collect());
+    }
+    resilience_data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{Bitcoin, Poem};
+
+    #[test]
+    fn test_resilience() {
+        // The honest chain of Bitcoin grows by g / (1 + g) blocks per delay, so the growth
+        // rates meet at beta = 1 / (g + 2), which bounds the resilience
+        let samples = Samples::new(9, 256);
+        let mut bitcoin = Evaluator::new(&Bitcoin, samples, 0.1);
+        for g in [0.5, 1., 2.] {
+            let resilience = search_resilience(&Bitcoin, &mut bitcoin, g, 0., 0.01);
+            assert!((resilience.growth_beta - 1. / (g + 2.)).abs() < 0.01);
+            assert!(
+                resilience.beta <= resilience.growth_beta
+                    && resilience.beta > resilience.growth_beta - 0.1
+            );
+            assert!(
+                resilience.k < INF
+                    && resilience.adversary_growth_rate < resilience.honest_growth_rate
+            );
+        }
+        // PoEM tolerates more
+        let mut poem = Evaluator::new(&Poem, samples, 0.1);
+        let poem_beta = search_resilience(&Poem, &mut poem, 2., 0., 0.01).beta;
+        let bitcoin_beta = search_resilience(&Bitcoin, &mut bitcoin, 2., 0., 0.01).beta;
+        assert!(poem_beta > bitcoin_beta);
+    }
+}
@@ -0,0 +1,350 @@
+use serde::Serialize;
+
+use crate::{
+    analysis::{get_monte_carlo_partial_performance, PartialPerformance, SUMMATION_BLOCK},
+    formatting::{get_monte_carlo_progresses, scale_monte_carlo_blocks},
+    protocol::{Executions, Protocol},
+    sampling::{sample_monte_carlo_execution_rounds, Samples},
+    simulations::{evaluate_performance, start_protocol, ADVERSARY_COUNT, HONEST_COUNT},
+    types::{Block, Chain},
+};
+
+// The q-bounded model of model.tex: n parties, t of them corrupted, each making q queries
+// per round, every query being a block with probability p. A block reaches every honest
+// party in the next round, while the adversary sees its own blocks at once.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RoundModel {
+    pub parties: usize,
+    pub corrupted: usize,
+    pub queries: usize,
+    pub success: f64,
+}
+
+impl RoundModel {
+    // t is the nearest to beta n, and p gives the honest parties g blocks per round on
+    // average, as the rate g per delay of the continuous model
+    pub fn new(parties: usize, queries: usize, beta: f64, g: f64) -> Self {
+        let corrupted = (beta * parties as f64).round() as usize;
+        RoundModel {
+            parties,
+            corrupted,
+            queries,
+            success: g / (queries * (parties - corrupted)) as f64,
+        }
+    }
+
+    pub fn get_honest_queries(&self) -> usize {
+        self.queries * (self.parties - self.corrupted)
+    }
+
+    pub fn get_adversary_queries(&self) -> usize {
+        self.queries * self.corrupted
+    }
+
+    // The adversarial power t / n that the rounding of t leaves
+    pub fn get_beta(&self) -> f64 {
+        self.corrupted as f64 / self.parties as f64
+    }
+
+    // f, the probability that a round is successful
+    pub fn get_success_rate(&self) -> f64 {
+        1. - (1. - self.success).powi(self.get_honest_queries() as i32)
+    }
+
+    // The probability that exactly one honest query of a round succeeds, a convergence
+    // opportunity
+    pub fn get_convergence_rate(&self) -> f64 {
+        let honest_queries = self.get_honest_queries() as f64;
+        honest_queries * self.success * (1. - self.success).powf(honest_queries - 1.)
+    }
+
+    // Blocks with the sampled work, found at the sampled rounds instead of the timestamps
+    pub fn sample_executions(&self, protocol: &dyn Protocol, samples: Samples) -> Executions {
+        let (mut honest_blocks, mut adversary_blocks) = protocol.sample_executions(samples);
+        let (honest_rounds, adversary_rounds) =
+            sample_monte_carlo_execution_rounds::<HONEST_COUNT, ADVERSARY_COUNT>(
+                samples,
+                self.get_honest_queries() as u64,
+                self.get_adversary_queries() as u64,
+                self.success,
+            );
+        for (blocks, rounds) in honest_blocks.iter_mut().zip(&honest_rounds) {
+            set_rounds(blocks, rounds);
+        }
+        for (blocks, rounds) in adversary_blocks.iter_mut().zip(&adversary_rounds) {
+            set_rounds(blocks, rounds);
+        }
+        (honest_blocks, adversary_blocks)
+    }
+}
+
+fn set_rounds(blocks: &mut [Block], rounds: &[f64]) {
+    for (block, &round) in blocks.iter_mut().zip(rounds) {
+        block.timestamp = round;
+    }
+}
+
+// The honest progress round by round: every block of a round extends the heaviest chain of
+// the rounds before, so the chain gains the heaviest of them, X_r of app-analysis.tex, and
+// the others fork off it. Each block that is the heaviest of its round so far is progress.
+pub fn get_round_progresses(progresses: &mut Vec<Chain>, blocks: &[Block]) {
+    progresses.clear();
+    progresses.push(Chain {
+        timestamp: 0.0,
+        work: 0.0,
+        height: 0,
+        arrival_time: 0.0,
+    });
+    let mut round = 0.0;
+    let mut base = progresses[0];
+    for block in blocks {
+        if block.timestamp != round {
+            round = block.timestamp;
+            base = *progresses.last().unwrap();
+        }
+        if base.work + block.work > progresses.last().unwrap().work {
+            progresses.push(Chain {
+                timestamp: round,
+                work: base.work + block.work,
+                height: base.height + 1,
+                arrival_time: round + 1.,
+            });
+        }
+    }
+}
+
+// Sums over the rounds up to the last honest block of one execution, with the variables of
+// app-analysis.tex
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RoundSums {
+    pub rounds: f64,
+    pub successful_rounds: f64,
+    // Rounds in which more than one honest block is found
+    pub forking_rounds: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl RoundSums {
+    pub fn new(honest_blocks: &[Block], adversary_blocks: &[Block]) -> Self {
+        let rounds = honest_blocks.last().map_or(0., |block| block.timestamp);
+        let mut sums = RoundSums {
+            rounds,
+            ..Default::default()
+        };
+        for round in honest_blocks.chunk_by(|a, b| a.timestamp == b.timestamp) {
+            let work = round.iter().map(|block| block.work).fold(0., f64::max);
+            sums.successful_rounds += 1.;
+            sums.x += work;
+            if round.len() == 1 {
+                sums.y += work;
+            } else {
+                sums.forking_rounds += 1.;
+            }
+        }
+        sums.z = adversary_blocks
+            .iter()
+            .take_while(|block| block.timestamp <= rounds)
+            .map(|block| block.work)
+            .sum();
+        sums
+    }
+
+    fn add(&mut self, other: &RoundSums) {
+        self.rounds += other.rounds;
+        self.successful_rounds += other.successful_rounds;
+        self.forking_rounds += other.forking_rounds;
+        self.x += other.x;
+        self.y += other.y;
+        self.z += other.z;
+    }
+}
+
+// Per beta, per g
+#[derive(Debug, Clone, Serialize)]
+pub struct RoundData {
+    // t / n, per beta
+    pub beta: Vec<f64>,
+    pub success: Vec<Vec<f64>>,
+    pub k: Vec<Vec<f64>>,
+    pub latency: Vec<Vec<f64>>,
+    pub growth_rate: Vec<Vec<f64>>,
+    // The continuous-time model at the same beta and g
+    pub continuous_k: Vec<Vec<f64>>,
+    pub continuous_latency: Vec<Vec<f64>>,
+    pub continuous_growth_rate: Vec<Vec<f64>>,
+    // The means per round, against E[X_r] between the work of the first and of every honest
+    // block, and the exact E[Y_r] and E[Z_r]
+    pub x: Vec<Vec<f64>>,
+    pub x_lower: Vec<Vec<f64>>,
+    pub x_upper: Vec<Vec<f64>>,
+    pub y: Vec<Vec<f64>>,
+    pub y_expected: Vec<Vec<f64>>,
+    pub z: Vec<Vec<f64>>,
+    pub z_expected: Vec<Vec<f64>>,
+    // The fraction of successful rounds with more than one honest block
+    pub fork_rate: Vec<Vec<f64>>,
+    pub fork_rate_expected: Vec<Vec<f64>>,
+}
+
+impl RoundData {
+    fn new(betas: usize, gs: usize) -> Self {
+        let zeros = vec![vec![0.0; gs]; betas];
+        RoundData {
+            beta: vec![0.0; betas],
+            success: zeros.clone(),
+            k: zeros.clone(),
+            latency: zeros.clone(),
+            growth_rate: zeros.clone(),
+            continuous_k: zeros.clone(),
+            continuous_latency: zeros.clone(),
+            continuous_growth_rate: zeros.clone(),
+            x: zeros.clone(),
+            x_lower: zeros.clone(),
+            x_upper: zeros.clone(),
+            y: zeros.clone(),
+            y_expected: zeros.clone(),
+            z: zeros.clone(),
+            z_expected: zeros.clone(),
+            fork_rate: zeros.clone(),
+            fork_rate_expected: zeros,
+        }
+    }
+}
+
+// Runs the protocol in the round model of `parties` parties making `queries` queries each,
+// at every (beta, g), and in the continuous model on the same number of samples
+#[allow(clippy::too_many_arguments)]
+pub fn simulate_rounds(
+    protocol: &dyn Protocol,
+    samples: Samples,
+    monte_carlo: usize,
+    epsilon: f64,
+    beta_range: &[f64],
+    g_range: &[f64],
+    gamma: f64,
+    parties: usize,
+    queries: usize,
+) -> RoundData {
+    let gamma = start_protocol(protocol, gamma);
+    let mean_work = protocol.get_mean_work(gamma);
+    let mut round_data = RoundData::new(beta_range.len(), g_range.len());
+
+    for (beta_index, &beta) in beta_range.iter().enumerate() {
+        println!("beta: {}", beta);
+        for (g_index, &g) in g_range.iter().enumerate() {
+            let model = RoundModel::new(parties, queries, beta, g);
+            let mut partial = PartialPerformance::default();
+            let mut sums = RoundSums::default();
+            for chunk in samples.chunks(SUMMATION_BLOCK) {
+                let (honest_blocks, adversary_blocks) = model.sample_executions(protocol, chunk);
+                let mut scaled_honest_blocks = honest_blocks.clone();
+                scale_monte_carlo_blocks(&mut scaled_honest_blocks, &honest_blocks, 1., gamma);
+                let mut scaled_adversary_blocks = adversary_blocks.clone();
+                scale_monte_carlo_blocks(
+                    &mut scaled_adversary_blocks,
+                    &adversary_blocks,
+                    1.,
+                    gamma,
+                );
+
+                let mut honest_progresses = vec![Vec::new(); chunk.len()];
+                for (progresses, blocks) in honest_progresses.iter_mut().zip(&scaled_honest_blocks)
+                {
+                    get_round_progresses(progresses, blocks);
+                }
+                let mut adversary_progresses = vec![Vec::new(); chunk.len()];
+                get_monte_carlo_progresses(&mut adversary_progresses, &scaled_adversary_blocks, 0.);
+                partial.extend(get_monte_carlo_partial_performance(
+                    &honest_progresses,
+                    &adversary_progresses,
+                    None,
+                    None,
+                ));
+                partial.prune(monte_carlo, epsilon);
+                for (honest_blocks, adversary_blocks) in
+                    scaled_honest_blocks.iter().zip(&scaled_adversary_blocks)
+                {
+                    sums.add(&RoundSums::new(honest_blocks, adversary_blocks));
+                }
+            }
+            let performance = partial.get_performance(epsilon);
+            let continuous = evaluate_performance(
+                protocol,
+                samples,
+                monte_carlo,
+                epsilon,
+                model.get_beta(),
+                g,
+                gamma,
+            );
+
+            let success_rate = model.get_success_rate();
+            let convergence_rate = model.get_convergence_rate();
+            round_data.success[beta_index][g_index] = model.success;
+            round_data.k[beta_index][g_index] = performance.0;
+            round_data.latency[beta_index][g_index] = performance.0 / performance.1;
+            round_data.growth_rate[beta_index][g_index] = performance.1;
+            round_data.continuous_k[beta_index][g_index] = continuous.0;
+            round_data.continuous_latency[beta_index][g_index] = continuous.0 / continuous.1;
+            round_data.continuous_growth_rate[beta_index][g_index] = continuous.1;
+            round_data.x[beta_index][g_index] = sums.x / sums.rounds;
+            round_data.x_lower[beta_index][g_index] = success_rate * mean_work;
+            round_data.x_upper[beta_index][g_index] =
+                model.get_honest_queries() as f64 * model.success * mean_work;
+            round_data.y[beta_index][g_index] = sums.y / sums.rounds;
+            round_data.y_expected[beta_index][g_index] = convergence_rate * mean_work;
+            round_data.z[beta_index][g_index] = sums.z / sums.rounds;
+            round_data.z_expected[beta_index][g_index] =
+                model.get_adversary_queries() as f64 * model.success * mean_work;
+            round_data.fork_rate[beta_index][g_index] =
+                sums.forking_rounds / sums.successful_rounds;
+            round_data.fork_rate_expected[beta_index][g_index] =
+                1. - convergence_rate / success_rate;
+        }
+        round_data.beta[beta_index] = RoundModel::new(parties, queries, beta, 1.).get_beta();
+    }
+    round_data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{Bitcoin, Poem};
+
+    #[test]
+    fn test_round_progresses() {
+        // Blocks that reach the honest parties one round later, through the continuous
+        // progress builder, give the same progress as the rounds
+        let model = RoundModel::new(10, 100, 0.2, 2.);
+        assert_eq!((model.corrupted, model.success), (2, 2. / 800.));
+        let (honest_blocks, _) = model.sample_executions(&Poem, Samples::new(3, 4));
+        let mut expected = vec![Vec::new(); honest_blocks.len()];
+        get_monte_carlo_progresses(&mut expected, &honest_blocks, 1.);
+        let mut progresses = Vec::new();
+        for (expected, blocks) in expected.iter().zip(&honest_blocks) {
+            get_round_progresses(&mut progresses, blocks);
+            assert_eq!(&progresses, expected);
+        }
+    }
+
+    #[test]
+    fn test_round_sums() {
+        // The sums stay within their bounds, and under Bitcoin every block is a unit of work
+        let model = RoundModel::new(4, 5, 0.25, 1.5);
+        let (honest_blocks, adversary_blocks) =
+            model.sample_executions(&Bitcoin, Samples::new(5, 64));
+        let mut sums = RoundSums::default();
+        for (honest_blocks, adversary_blocks) in honest_blocks.iter().zip(&adversary_blocks) {
+            sums.add(&RoundSums::new(honest_blocks, adversary_blocks));
+        }
+        assert_eq!(sums.x, sums.successful_rounds);
+        assert!(sums.y <= sums.x && sums.forking_rounds > 0.);
+        let x = sums.x / sums.rounds;
+        assert!((x - model.get_success_rate()).abs() < 0.02);
+        let z = sums.z / sums.rounds;
+        let z_expected = model.get_adversary_queries() as f64 * model.success;
+        assert!((z - z_expected).abs() < 0.02);
+    }
+}
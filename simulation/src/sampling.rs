@@ -1,26 +1,273 @@
-use rand_distr::{Distribution, Exp};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rand_distr::{Binomial, Distribution, Exp, Open01};
 use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 
-use crate::types::Block;
+use crate::{
+    analysis::SUMMATION_BLOCK,
+    error::Error,
+    sobol::{get_sobol_uniform, mix},
+    types::{Block, INF},
+};
 
-fn sample_timestamps<T: rand::Rng, const N: usize>(mut rng: &mut T) -> [f64; N] {
-    let time_distribution = Exp::new(1.0).unwrap();
+// Independent random streams drawn from the same seed
+const HONEST_TIMESTAMP_STREAM: u64 = 0;
+const ADVERSARY_TIMESTAMP_STREAM: u64 = 1;
+const HONEST_WORK_STREAM: u64 = 2;
+const ADVERSARY_WORK_STREAM: u64 = 3;
+const HONEST_ROUND_STREAM: u64 = 4;
+const ADVERSARY_ROUND_STREAM: u64 = 5;
+const PARTY_STREAM: u64 = 6;
+const TARGET_STREAM: u64 = 7;
+
+// The variance reductions given by `--variance-reduction`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum VarianceReductionMode {
+    /// Every protocol and grid point draws its own samples
+    None,
+    /// All protocols and grid points share the same samples, work included
+    CommonRandomNumbers,
+    /// Samples come in pairs, the second drawn from the complementary uniforms of the first
+    Antithetic,
+    /// The averages are corrected by the deviation of the honest mining rate from its known mean
+    ControlVariates,
+    /// Samples are the points of a scrambled Sobol sequence, mapped through inverse CDFs
+    QuasiMonteCarlo,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VarianceReduction {
+    pub common_random_numbers: bool,
+    pub antithetic: bool,
+    pub control_variates: bool,
+    pub quasi_monte_carlo: bool,
+}
+
+impl Default for VarianceReduction {
+    fn default() -> Self {
+        VarianceReduction {
+            common_random_numbers: true,
+            antithetic: false,
+            control_variates: false,
+            quasi_monte_carlo: false,
+        }
+    }
+}
+
+impl VarianceReduction {
+    pub fn from_modes(modes: &[VarianceReductionMode]) -> Self {
+        VarianceReduction {
+            common_random_numbers: modes.contains(&VarianceReductionMode::CommonRandomNumbers),
+            antithetic: modes.contains(&VarianceReductionMode::Antithetic),
+            control_variates: modes.contains(&VarianceReductionMode::ControlVariates),
+            quasi_monte_carlo: modes.contains(&VarianceReductionMode::QuasiMonteCarlo),
+        }
+    }
+
+    pub fn modes(&self) -> Vec<VarianceReductionMode> {
+        let modes = [
+            (
+                self.common_random_numbers,
+                VarianceReductionMode::CommonRandomNumbers,
+            ),
+            (self.antithetic, VarianceReductionMode::Antithetic),
+            (
+                self.control_variates,
+                VarianceReductionMode::ControlVariates,
+            ),
+            (
+                self.quasi_monte_carlo,
+                VarianceReductionMode::QuasiMonteCarlo,
+            ),
+        ];
+        let modes: Vec<_> = modes
+            .into_iter()
+            .filter_map(|(enabled, mode)| enabled.then_some(mode))
+            .collect();
+        if modes.is_empty() {
+            vec![VarianceReductionMode::None]
+        } else {
+            modes
+        }
+    }
+}
+
+// Sequential sampling, given by `--precision`: each grid point keeps drawing batches of the
+// run's samples until the 95% confidence interval of k* is narrower than `precision` times
+// k*, or until it has drawn `max_monte_carlo` samples
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Sequential {
+    pub precision: f64,
+    pub max_monte_carlo: usize,
+}
+
+impl Sequential {
+    pub fn validate(&self, monte_carlo: usize) -> Result<(), Error> {
+        if !(self.precision > 0. && self.precision.is_finite()) {
+            return Err(Error::InvalidParameter {
+                field: "precision".to_string(),
+                message: format!("{} must be positive", self.precision),
+            });
+        }
+        if self.max_monte_carlo < monte_carlo {
+            return Err(Error::InvalidParameter {
+                field: "max_monte_carlo".to_string(),
+                message: format!(
+                    "{} must be at least the {} samples of the first batch",
+                    self.max_monte_carlo, monte_carlo
+                ),
+            });
+        }
+        Ok(())
+    }
+
+    // Whether the k* estimate, with the given standard error, is precise enough. The latency
+    // k* / f_work shares its relative precision, up to the far smaller error of the rate.
+    pub fn is_precise(&self, k: f64, error: f64) -> bool {
+        k.is_finite() && error.is_finite() && 2. * 1.96 * error <= self.precision * k
+    }
+}
+
+// The range of samples a run draws. Sample i of every stream depends only on the seed, on i
+// and on the point, so any range reproduces its part of a run over all the samples.
+// With common random numbers every grid point uses point 0 and so sees the same samples.
+// With sequential sampling the range is the first batch, which later batches continue.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Samples {
+    pub seed: u64,
+    pub start: usize,
+    pub end: usize,
+    pub variance_reduction: VarianceReduction,
+    pub point: u64,
+    pub sequential: Option<Sequential>,
+}
+
+impl Samples {
+    pub fn new(seed: u64, monte_carlo: usize) -> Self {
+        Samples {
+            seed,
+            start: 0,
+            end: monte_carlo,
+            variance_reduction: VarianceReduction::default(),
+            point: 0,
+            sequential: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    // Consecutive ranges of at most `size` samples, together covering these samples
+    pub fn chunks(&self, size: usize) -> impl Iterator<Item = Samples> {
+        let samples = *self;
+        (samples.start..samples.end)
+            .step_by(size)
+            .map(move |start| Samples {
+                start,
+                end: (start + size).min(samples.end),
+                ..samples
+            })
+    }
+}
+
+// How a sample draws its exponentials: directly, or by inverting uniforms, either random
+// or the coordinates of a scrambled Sobol point. The second sample of an antithetic pair
+// takes the uniforms complemented.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Draw {
+    Direct,
+    Inversion {
+        complementary: bool,
+    },
+    Sobol {
+        index: usize,
+        stream: u64,
+        seed: u64,
+        complementary: bool,
+    },
+}
+
+// Draws the exponential of the given block, which picks the Sobol dimension
+fn sample_exponential<T: rand::Rng>(rng: &mut T, rate: f64, draw: Draw, block: usize) -> f64 {
+    let (uniform, complementary) = match draw {
+        Draw::Direct => return Exp::new(rate).unwrap().sample(rng),
+        Draw::Inversion { complementary } => (rng.sample(Open01), complementary),
+        Draw::Sobol {
+            index,
+            stream,
+            seed,
+            complementary,
+        } => (
+            get_sobol_uniform(index, 4 * block + stream as usize, seed),
+            complementary,
+        ),
+    };
+    let uniform: f64 = if complementary {
+        1.0 - uniform
+    } else {
+        uniform
+    };
+    -uniform.ln() / rate
+}
+
+// Each sample gets its own generator, keyed by the seed, the stream, the sample index and the
+// point, so that the samples do not depend on how the parallel iterators split the work.
+// Both samples of an antithetic pair share the generator of the pair. With quasi-Monte Carlo
+// the sample, or pair, is the Sobol point of its index, scrambled by the seed and the point.
+fn get_sample_rng(samples: &Samples, stream: u64, index: usize) -> (StdRng, Draw) {
+    let variance_reduction = samples.variance_reduction;
+    let (key_index, complementary) = if variance_reduction.antithetic {
+        (index / 2, index % 2 == 1)
+    } else {
+        (index, false)
+    };
+    let draw = if variance_reduction.quasi_monte_carlo {
+        Draw::Sobol {
+            index: key_index,
+            stream,
+            seed: mix(samples.seed) ^ samples.point,
+            complementary,
+        }
+    } else if variance_reduction.antithetic {
+        Draw::Inversion { complementary }
+    } else {
+        Draw::Direct
+    };
+    let mut key = [0u8; 32];
+    key[..8].copy_from_slice(&samples.seed.to_le_bytes());
+    key[8..16].copy_from_slice(&stream.to_le_bytes());
+    key[16..24].copy_from_slice(&(key_index as u64).to_le_bytes());
+    key[24..].copy_from_slice(&samples.point.to_le_bytes());
+    (StdRng::from_seed(key), draw)
+}
+
+fn sample_timestamps<T: rand::Rng, const N: usize>(rng: &mut T, draw: Draw) -> [f64; N] {
     let mut block_time = 0.0;
     let mut block_timestamps = [0.0; N];
 
-    for i in 0..N {
-        block_time += time_distribution.sample(&mut rng);
-        block_timestamps[i] = block_time;
+    for (block, block_timestamp) in block_timestamps.iter_mut().enumerate() {
+        block_time += sample_exponential(rng, 1.0, draw, block);
+        *block_timestamp = block_time;
     }
 
     block_timestamps
 }
 
-fn sample_monte_carlo_timestamps<const N: usize>(monte_carlo: usize) -> Vec<[f64; N]> {
-    (0..monte_carlo)
+fn sample_monte_carlo_timestamps<const N: usize>(samples: Samples, stream: u64) -> Vec<[f64; N]> {
+    (samples.start..samples.end)
         // .into_iter()
         .into_par_iter()
-        .map(|_| sample_timestamps(&mut rand::thread_rng()))
+        .map(|i| {
+            let (mut rng, draw) = get_sample_rng(&samples, stream, i);
+            sample_timestamps(&mut rng, draw)
+        })
         .collect()
 }
 
@@ -28,13 +275,98 @@ pub fn sample_monte_carlo_execution_timestamps<
     const HONEST_HEIGHT: usize,
     const ADVERSARY_HEIGHT: usize,
 >(
-    monte_carlo: usize,
+    samples: Samples,
 ) -> (Vec<[f64; HONEST_HEIGHT]>, Vec<[f64; ADVERSARY_HEIGHT]>) {
-    let honest_timestamps = sample_monte_carlo_timestamps::<HONEST_HEIGHT>(monte_carlo);
-    let adversary_timestamps = sample_monte_carlo_timestamps::<ADVERSARY_HEIGHT>(monte_carlo);
+    let honest_timestamps =
+        sample_monte_carlo_timestamps::<HONEST_HEIGHT>(samples, HONEST_TIMESTAMP_STREAM);
+    let adversary_timestamps =
+        sample_monte_carlo_timestamps::<ADVERSARY_HEIGHT>(samples, ADVERSARY_TIMESTAMP_STREAM);
     (honest_timestamps, adversary_timestamps)
 }
 
+// The rounds of the lockstep model in which the blocks are found, when each round makes
+// `queries` queries that succeed with probability `success`, so that several blocks can
+// share a round. Without queries no block is ever found.
+fn sample_rounds<T: rand::Rng, const N: usize>(
+    rng: &mut T,
+    queries: u64,
+    success: f64,
+) -> [f64; N] {
+    let mut block_rounds = [INF; N];
+    if queries == 0 || success == 0. {
+        return block_rounds;
+    }
+    let binomial = Binomial::new(queries, success).unwrap();
+    let mut round = 0.0;
+    let mut block = 0;
+    while block < N {
+        round += 1.0;
+        let successes = (binomial.sample(rng) as usize).min(N - block);
+        block_rounds[block..block + successes].fill(round);
+        block += successes;
+    }
+    block_rounds
+}
+
+fn sample_monte_carlo_rounds<const N: usize>(
+    samples: Samples,
+    stream: u64,
+    queries: u64,
+    success: f64,
+) -> Vec<[f64; N]> {
+    (samples.start..samples.end)
+        .into_par_iter()
+        .map(|i| {
+            // The counts are discrete, so only the generator of the sample is used and both
+            // samples of an antithetic pair share their rounds
+            let (mut rng, _) = get_sample_rng(&samples, stream, i);
+            sample_rounds(&mut rng, queries, success)
+        })
+        .collect()
+}
+
+// The honest party, out of `parties`, that mines each honest block of each sample
+pub fn sample_monte_carlo_parties<const N: usize>(
+    samples: Samples,
+    parties: usize,
+) -> Vec<[usize; N]> {
+    (samples.start..samples.end)
+        .into_par_iter()
+        .map(|i| {
+            // As for the rounds, only the generator of the sample is used
+            let (mut rng, _) = get_sample_rng(&samples, PARTY_STREAM, i);
+            std::array::from_fn(|_| rng.gen_range(0..parties))
+        })
+        .collect()
+}
+
+// A uniform offset in 0..range per sample
+pub fn sample_monte_carlo_offsets(samples: Samples, range: usize) -> Vec<usize> {
+    (samples.start..samples.end)
+        .into_par_iter()
+        .map(|i| {
+            let (mut rng, _) = get_sample_rng(&samples, TARGET_STREAM, i);
+            rng.gen_range(0..range)
+        })
+        .collect()
+}
+
+// The rounds of the honest and adversary blocks, which take the place of their timestamps
+pub fn sample_monte_carlo_execution_rounds<
+    const HONEST_HEIGHT: usize,
+    const ADVERSARY_HEIGHT: usize,
+>(
+    samples: Samples,
+    honest_queries: u64,
+    adversary_queries: u64,
+    success: f64,
+) -> (Vec<[f64; HONEST_HEIGHT]>, Vec<[f64; ADVERSARY_HEIGHT]>) {
+    (
+        sample_monte_carlo_rounds(samples, HONEST_ROUND_STREAM, honest_queries, success),
+        sample_monte_carlo_rounds(samples, ADVERSARY_ROUND_STREAM, adversary_queries, success),
+    )
+}
+
 fn get_bitcoin_blocks<const N: usize>(block_timestamps: [f64; N]) -> [Block; N] {
     block_timestamps.map(|t| Block {
         timestamp: t,
@@ -61,33 +393,40 @@ pub fn get_monte_carlo_bitcoin_executions<
         Vec<[f64; ADVERSARY_HEIGHT]>,
     ),
 ) -> (Vec<[Block; HONEST_HEIGHT]>, Vec<[Block; ADVERSARY_HEIGHT]>) {
-    let honest_bitcoin_blocks = get_monte_carlo_bitcoin_blocks::<HONEST_HEIGHT>(&honest_timestamps);
+    let honest_bitcoin_blocks = get_monte_carlo_bitcoin_blocks::<HONEST_HEIGHT>(honest_timestamps);
     let adversary_bitcoin_blocks =
-        get_monte_carlo_bitcoin_blocks::<ADVERSARY_HEIGHT>(&adversary_timestamps);
+        get_monte_carlo_bitcoin_blocks::<ADVERSARY_HEIGHT>(adversary_timestamps);
 
     (honest_bitcoin_blocks, adversary_bitcoin_blocks)
 }
 
 fn sample_poem_blocks<T: rand::Rng, const N: usize>(
     block_timestamps: [f64; N],
-    work_distribution: Exp<f64>,
     rng: &mut T,
+    draw: Draw,
 ) -> [Block; N] {
-    block_timestamps.map(|t| Block {
-        timestamp: t,
-        work: work_distribution.sample(rng),
+    let mut block = 0;
+    block_timestamps.map(|t| {
+        block += 1;
+        Block {
+            timestamp: t,
+            work: sample_exponential(rng, std::f64::consts::LN_2, draw, block - 1),
+        }
     })
 }
 
 fn sample_monte_carlo_poem_blocks<const N: usize>(
-    monte_carlo_timestamps: &Vec<[f64; N]>,
+    monte_carlo_timestamps: &[[f64; N]],
+    samples: Samples,
+    stream: u64,
 ) -> Vec<[Block; N]> {
-    let work_distribution = Exp::new(std::f64::consts::LN_2).unwrap();
     monte_carlo_timestamps
         // .iter()
         .par_iter()
-        .map(|&block_timestamps| {
-            sample_poem_blocks(block_timestamps, work_distribution, &mut rand::thread_rng())
+        .enumerate()
+        .map(|(i, &block_timestamps)| {
+            let (mut rng, draw) = get_sample_rng(&samples, stream, samples.start + i);
+            sample_poem_blocks(block_timestamps, &mut rng, draw)
         })
         .collect()
 }
@@ -100,10 +439,108 @@ pub fn sample_monte_carlo_poem_executions<
         Vec<[f64; HONEST_HEIGHT]>,
         Vec<[f64; ADVERSARY_HEIGHT]>,
     ),
+    samples: Samples,
 ) -> (Vec<[Block; HONEST_HEIGHT]>, Vec<[Block; ADVERSARY_HEIGHT]>) {
-    let honest_poem_blocks = sample_monte_carlo_poem_blocks::<HONEST_HEIGHT>(&honest_timestamps);
-    let adversary_poem_blocks =
-        sample_monte_carlo_poem_blocks::<ADVERSARY_HEIGHT>(&adversary_timestamps);
+    let honest_poem_blocks = sample_monte_carlo_poem_blocks::<HONEST_HEIGHT>(
+        honest_timestamps,
+        samples,
+        HONEST_WORK_STREAM,
+    );
+    let adversary_poem_blocks = sample_monte_carlo_poem_blocks::<ADVERSARY_HEIGHT>(
+        adversary_timestamps,
+        samples,
+        ADVERSARY_WORK_STREAM,
+    );
 
     (honest_poem_blocks, adversary_poem_blocks)
 }
+
+// The i-th of n parts of the samples, counting from 1, as given by `--shard i/n`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Shard {
+    pub index: usize,
+    pub count: usize,
+}
+
+impl std::str::FromStr for Shard {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid shard `{}`. Use the format: index/count", s);
+        let (index, count) = s.split_once('/').ok_or_else(invalid)?;
+        let index: usize = index.trim().parse().map_err(|_| invalid())?;
+        let count: usize = count.trim().parse().map_err(|_| invalid())?;
+        if index == 0 || index > count {
+            return Err(format!(
+                "Invalid shard `{}`, the index must lie between 1 and {}",
+                s, count
+            ));
+        }
+        Ok(Shard { index, count })
+    }
+}
+
+impl std::fmt::Display for Shard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+impl Shard {
+    // Splits the samples of a run into whole summation blocks, so that merging the shards adds
+    // up the same block sums as a single run. Blocks have an even size, which keeps the pairs
+    // of antithetic samples together.
+    pub fn get_samples(&self, samples: Samples) -> Result<Samples, Error> {
+        let monte_carlo = samples.len();
+        let blocks = monte_carlo.div_ceil(SUMMATION_BLOCK);
+        if self.count > blocks {
+            return Err(Error::InvalidParameter {
+                field: "shard".to_string(),
+                message: format!(
+                    "{} samples can be split into at most {} shards of {} samples",
+                    monte_carlo, blocks, SUMMATION_BLOCK
+                ),
+            });
+        }
+        let start_block = (self.index - 1) * blocks / self.count;
+        let end_block = self.index * blocks / self.count;
+        Ok(Samples {
+            start: samples.start + start_block * SUMMATION_BLOCK,
+            end: (samples.start + end_block * SUMMATION_BLOCK).min(samples.end),
+            ..samples
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_antithetic_samples() {
+        // The two samples of a pair draw their first block time from complementary uniforms
+        let samples = Samples {
+            variance_reduction: VarianceReduction::from_modes(&[VarianceReductionMode::Antithetic]),
+            ..Samples::new(3, 4)
+        };
+        let (honest_timestamps, _) = sample_monte_carlo_execution_timestamps::<5, 5>(samples);
+        for pair in honest_timestamps.chunks(2) {
+            let uniforms = (-pair[0][0]).exp() + (-pair[1][0]).exp();
+            assert!((uniforms - 1.0).abs() < 1e-12);
+        }
+        assert_ne!(honest_timestamps[0], honest_timestamps[2]);
+    }
+
+    #[test]
+    fn test_sequential() {
+        let sequential = Sequential {
+            precision: 0.1,
+            max_monte_carlo: 4096,
+        };
+        assert!(sequential.validate(1024).is_ok());
+        assert!(sequential.validate(8192).is_err());
+        // The 95% confidence interval of k* = 10 is 3.92 standard errors wide
+        assert!(sequential.is_precise(10.0, 0.25));
+        assert!(!sequential.is_precise(10.0, 0.26));
+        assert!(!sequential.is_precise(f64::INFINITY, 0.0));
+    }
+}
@@ -0,0 +1,1201 @@
+use std::path::Path;
+
+use clap::Subcommand;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    adversary::Strategy,
+    confirmation::ConfirmationRule,
+    error::Error,
+    protocol::ProtocolName,
+    ranges::parse_field_range,
+    rounds::RoundModel,
+    sampling::{Sequential, VarianceReductionMode},
+    simulations::HONEST_COUNT,
+};
+
+// A declarative description of a batch of experiments, read from a TOML or JSON file.
+// The top-level settings are shared by all experiments of the scenario.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    #[serde(default = "default_monte_carlo")]
+    pub monte_carlo: usize,
+    // Blocks per sampled execution, fixed at compile time by HONEST_COUNT
+    pub horizon: Option<usize>,
+    // Seeds every experiment, which otherwise draw their own random seed
+    pub seed: Option<u64>,
+    #[serde(default = "default_variance_reduction")]
+    pub variance_reduction: Vec<VarianceReductionMode>,
+    // Samples each grid point sequentially, in batches of monte_carlo samples
+    pub sequential: Option<Sequential>,
+    #[serde(default)]
+    pub risk: Risk,
+    #[serde(default)]
+    pub output: Output,
+    #[serde(rename = "experiment")]
+    pub experiments: Vec<Experiment>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Risk {
+    #[serde(default = "default_error")]
+    pub error: f64,
+}
+
+impl Default for Risk {
+    fn default() -> Self {
+        Risk {
+            error: default_error(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Output {
+    #[serde(default = "default_directory")]
+    pub directory: String,
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Output {
+            directory: default_directory(),
+        }
+    }
+}
+
+// One run of the simulation binary, given either as a subcommand or as an experiment of a
+// scenario file
+#[derive(Debug, Clone, PartialEq, Subcommand, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum Experiment {
+    /// PoEM and Bitcoin latency over gamma for a single beta and g
+    GammaLatency {
+        #[arg(long)]
+        beta: f64,
+        #[arg(long)]
+        g: f64,
+        #[arg(long)]
+        gamma_range: String,
+    },
+    /// PoEM and Bitcoin latency over g for a single beta and gamma
+    GLatency {
+        #[arg(long)]
+        beta: f64,
+        #[arg(long)]
+        gamma: f64,
+        #[arg(long)]
+        g_range: String,
+    },
+    /// PoEM latency over a beta x g x gamma grid
+    Poem {
+        #[arg(long)]
+        beta_range: String,
+        #[arg(long)]
+        g_range: String,
+        #[arg(long)]
+        gamma_range: String,
+    },
+    /// Optimal PoEM and Bitcoin latency per beta over a g x gamma grid
+    BitcoinVsPoem {
+        #[arg(long)]
+        beta_range: String,
+        #[arg(long)]
+        g_range: String,
+        #[arg(long)]
+        gamma_range: String,
+    },
+    /// PoEM chain vs PoEM DAG latency per beta
+    Dag {
+        #[arg(long)]
+        beta_range: String,
+        #[arg(long)]
+        g_range: String,
+        #[arg(long)]
+        gamma_range: String,
+    },
+    /// Merge-mined Bitcoin and PoEM chains with a shared adversary
+    MergeMining {
+        #[arg(long)]
+        beta_range: String,
+        #[arg(long)]
+        g_range: String,
+        #[arg(long)]
+        gamma_range: String,
+        // Defaults to merged mining, where every honest party mines both chains
+        #[arg(long)]
+        honest_split: Option<f64>,
+        // Between 0 and 1, where 0 and 1 put all adversarial hash power on one chain
+        #[arg(long)]
+        adversary_split_range: String,
+        // Adds an adversary that merge-mines both chains at full power
+        #[arg(long)]
+        #[serde(default)]
+        merge_mined_adversary: bool,
+    },
+    /// Optimal g and gamma per beta through golden-section search
+    Optimize {
+        #[arg(long)]
+        beta_range: String,
+        #[arg(long)]
+        g_range: String,
+        #[arg(long)]
+        gamma_range: String,
+        #[arg(long, default_value_t = default_tolerance())]
+        #[serde(default = "default_tolerance")]
+        tolerance: f64,
+    },
+    /// Coarse g x gamma grid refined around the latency minimum of each beta
+    Refine {
+        #[arg(long)]
+        beta_range: String,
+        #[arg(long)]
+        g_range: String,
+        #[arg(long)]
+        gamma_range: String,
+        #[arg(long, default_value_t = default_refinement_points())]
+        #[serde(default = "default_refinement_points")]
+        refinement_points: usize,
+        #[arg(long, default_value_t = default_threshold())]
+        #[serde(default = "default_threshold")]
+        threshold: f64,
+    },
+    /// Pareto frontier of latency, throughput and security level
+    Pareto {
+        #[arg(long)]
+        beta_range: String,
+        #[arg(long)]
+        g_range: String,
+        #[arg(long)]
+        gamma_range: String,
+        #[arg(long)]
+        error_range: String,
+    },
+    /// Every (beta, g, gamma) cell of the PoEM vs Bitcoin sweep
+    FullGrid {
+        #[arg(long)]
+        beta_range: String,
+        #[arg(long)]
+        g_range: String,
+        #[arg(long)]
+        gamma_range: String,
+    },
+    /// Variance of the PoEM and Bitcoin latency under each variance reduction
+    Variance {
+        #[arg(long)]
+        beta: f64,
+        #[arg(long)]
+        g: f64,
+        #[arg(long)]
+        gamma: f64,
+        #[arg(long, default_value_t = default_replications())]
+        #[serde(default = "default_replications")]
+        replications: usize,
+    },
+    /// Convergence of the k* estimate with pseudo-random and scrambled Sobol samples
+    QmcConvergence {
+        #[arg(long)]
+        beta: f64,
+        #[arg(long)]
+        g: f64,
+        #[arg(long)]
+        gamma: f64,
+        // Sample counts, spaced logarithmically
+        #[arg(long)]
+        monte_carlo_range: String,
+        #[arg(long, default_value_t = default_replications())]
+        #[serde(default = "default_replications")]
+        replications: usize,
+    },
+    /// Latency, revenue and orphan rate of each adversary strategy against a protocol
+    Adversary {
+        #[arg(long)]
+        beta_range: String,
+        #[arg(long)]
+        g: f64,
+        #[arg(long)]
+        gamma: f64,
+        #[arg(long, value_enum, default_value = "poem")]
+        #[serde(default = "default_protocol")]
+        protocol: ProtocolName,
+        #[arg(
+            long,
+            value_delimiter = ',',
+            default_value = "private-mining,selfish-mining,stubborn-mining,balance-attack"
+        )]
+        #[serde(default = "default_strategies")]
+        strategies: Vec<Strategy>,
+        // A policy file written by the mdp experiment, replayed after the strategies
+        #[arg(long)]
+        #[serde(default)]
+        policy: Option<String>,
+    },
+    /// Distributions of the common-prefix, chain-growth and chain-quality properties
+    Backbone {
+        #[arg(long)]
+        beta_range: String,
+        #[arg(long)]
+        g_range: String,
+        #[arg(long)]
+        gamma: f64,
+        #[arg(long, value_delimiter = ',', default_value = "bitcoin,poem")]
+        #[serde(default = "default_protocols")]
+        protocols: Vec<ProtocolName>,
+        #[arg(long, value_enum, default_value = "selfish-mining")]
+        #[serde(default = "default_strategy")]
+        strategy: Strategy,
+        // Consecutive blocks of the final chain over which chain quality is measured
+        #[arg(long, default_value_t = default_window())]
+        #[serde(default = "default_window")]
+        window: usize,
+    },
+    /// The lockstep round model of model.tex against the continuous-time model
+    Rounds {
+        #[arg(long)]
+        beta_range: String,
+        #[arg(long)]
+        g_range: String,
+        #[arg(long)]
+        gamma: f64,
+        #[arg(long, value_delimiter = ',', default_value = "bitcoin,poem")]
+        #[serde(default = "default_protocols")]
+        protocols: Vec<ProtocolName>,
+        // n, of which beta n, rounded, are corrupted
+        #[arg(long, default_value_t = default_parties())]
+        #[serde(default = "default_parties")]
+        parties: usize,
+        // q, the queries of each party per round
+        #[arg(long, default_value_t = default_queries())]
+        #[serde(default = "default_queries")]
+        queries: usize,
+    },
+    /// The largest beta each protocol tolerates at every (g, gamma), by bisection
+    Resilience {
+        #[arg(long)]
+        g_range: String,
+        #[arg(long)]
+        gamma_range: String,
+        #[arg(long, value_delimiter = ',', default_value = "bitcoin,poem")]
+        #[serde(default = "default_protocols")]
+        protocols: Vec<ProtocolName>,
+        // The width of the final bracket of beta
+        #[arg(long, default_value_t = default_resilience_tolerance())]
+        #[serde(default = "default_resilience_tolerance")]
+        tolerance: f64,
+    },
+    /// Honest and private chain growth rates against their analytic values
+    Growth {
+        #[arg(long)]
+        beta: f64,
+        #[arg(long)]
+        g_range: String,
+        #[arg(long)]
+        gamma_range: String,
+        #[arg(long, value_delimiter = ',', default_value = "bitcoin,poem")]
+        #[serde(default = "default_protocols")]
+        protocols: Vec<ProtocolName>,
+    },
+    /// Latency of a transaction deep into the execution against one in the first block
+    Stationary {
+        #[arg(long)]
+        beta_range: String,
+        #[arg(long)]
+        g: f64,
+        #[arg(long)]
+        gamma: f64,
+        #[arg(long, value_delimiter = ',', default_value = "bitcoin,poem")]
+        #[serde(default = "default_protocols")]
+        protocols: Vec<ProtocolName>,
+        // Honest blocks before the transaction, which is then placed at a random height up to
+        // twice as deep
+        #[arg(long, default_value_t = default_burn_in())]
+        #[serde(default = "default_burn_in")]
+        burn_in: usize,
+        // Places the transaction at this height instead
+        #[arg(long)]
+        #[serde(default)]
+        target_height: Option<usize>,
+    },
+    /// Safe parameters and latencies of several confirmation rules on the same samples
+    Confirmation {
+        #[arg(long)]
+        beta_range: String,
+        #[arg(long)]
+        g: f64,
+        #[arg(long)]
+        gamma: f64,
+        #[arg(long, value_delimiter = ',', default_value = "bitcoin,poem")]
+        #[serde(default = "default_protocols")]
+        protocols: Vec<ProtocolName>,
+        #[arg(long, value_delimiter = ',', default_value = "work,blocks,time,hybrid")]
+        #[serde(default = "default_rules")]
+        rules: Vec<ConfirmationRule>,
+        // The time the hybrid rule waits for on top of its work
+        #[arg(long, default_value_t = default_hybrid_time())]
+        #[serde(default = "default_hybrid_time")]
+        hybrid_time: f64,
+    },
+    /// Optimal selfish mining revenue and double-spend success against PoEM, from an MDP
+    Mdp {
+        #[arg(long)]
+        beta_range: String,
+        #[arg(long)]
+        gamma: f64,
+        // Confirmation depths in work
+        #[arg(long)]
+        k_range: String,
+        #[arg(long, default_value_t = default_max_lead())]
+        #[serde(default = "default_max_lead")]
+        max_lead: usize,
+        // Steps per mean block work
+        #[arg(long, default_value_t = default_resolution())]
+        #[serde(default = "default_resolution")]
+        resolution: usize,
+    },
+}
+
+fn default_monte_carlo() -> usize {
+    1000
+}
+
+fn default_error() -> f64 {
+    0.1
+}
+
+fn default_directory() -> String {
+    "simulation_data".to_string()
+}
+
+fn default_tolerance() -> f64 {
+    0.01
+}
+
+fn default_refinement_points() -> usize {
+    5
+}
+
+fn default_threshold() -> f64 {
+    0.01
+}
+
+fn default_replications() -> usize {
+    20
+}
+
+fn default_max_lead() -> usize {
+    10
+}
+
+fn default_resolution() -> usize {
+    4
+}
+
+fn default_protocol() -> ProtocolName {
+    ProtocolName::Poem
+}
+
+fn default_protocols() -> Vec<ProtocolName> {
+    vec![ProtocolName::Bitcoin, ProtocolName::Poem]
+}
+
+fn default_strategy() -> Strategy {
+    Strategy::SelfishMining
+}
+
+fn default_window() -> usize {
+    10
+}
+
+fn default_parties() -> usize {
+    10
+}
+
+fn default_queries() -> usize {
+    100
+}
+
+fn default_resilience_tolerance() -> f64 {
+    0.001
+}
+
+fn default_burn_in() -> usize {
+    200
+}
+
+fn default_rules() -> Vec<ConfirmationRule> {
+    vec![
+        ConfirmationRule::Work,
+        ConfirmationRule::Blocks,
+        ConfirmationRule::Time,
+        ConfirmationRule::Hybrid,
+    ]
+}
+
+fn default_hybrid_time() -> f64 {
+    10.
+}
+
+fn default_strategies() -> Vec<Strategy> {
+    vec![
+        Strategy::PrivateMining,
+        Strategy::SelfishMining,
+        Strategy::StubbornMining,
+        Strategy::BalanceAttack,
+    ]
+}
+
+fn default_variance_reduction() -> Vec<VarianceReductionMode> {
+    vec![VarianceReductionMode::CommonRandomNumbers]
+}
+
+fn check_beta(field: &str, beta: f64) -> Result<(), Error> {
+    if beta > 0. && beta < 0.5 {
+        Ok(())
+    } else {
+        Err(Error::InvalidBeta {
+            field: field.to_string(),
+            beta,
+        })
+    }
+}
+
+fn check_g(field: &str, g: f64) -> Result<(), Error> {
+    if g > 0. && g.is_finite() {
+        Ok(())
+    } else {
+        Err(Error::InvalidG {
+            field: field.to_string(),
+            g,
+        })
+    }
+}
+
+fn check_parameter(
+    field: &str,
+    value: f64,
+    is_valid: impl Fn(f64) -> bool,
+    expected: &str,
+) -> Result<(), Error> {
+    if is_valid(value) {
+        Ok(())
+    } else {
+        Err(Error::InvalidParameter {
+            field: field.to_string(),
+            message: format!("{} must be {}", value, expected),
+        })
+    }
+}
+
+fn check_gamma(field: &str, gamma: f64) -> Result<(), Error> {
+    check_parameter(
+        field,
+        gamma,
+        |gamma| gamma >= 0. && gamma.is_finite(),
+        "non-negative",
+    )
+}
+
+fn check_fraction(field: &str, fraction: f64) -> Result<(), Error> {
+    check_parameter(
+        field,
+        fraction,
+        |fraction| fraction > 0. && fraction < 1.,
+        "strictly between 0 and 1",
+    )
+}
+
+fn check_error(field: &str, error: f64) -> Result<(), Error> {
+    check_parameter(
+        field,
+        error,
+        |error| error > 0. && error < 0.5,
+        "strictly between 0 and 0.5",
+    )
+}
+
+// The sample count and error every experiment shares, from the command line or a scenario
+pub fn check_settings(monte_carlo: usize, error_field: &str, error: f64) -> Result<(), Error> {
+    if monte_carlo == 0 {
+        return Err(Error::InvalidParameter {
+            field: "monte_carlo".to_string(),
+            message: "must be positive".to_string(),
+        });
+    }
+    check_error(error_field, error)
+}
+
+fn check_replications(field: &str, replications: usize) -> Result<(), Error> {
+    if replications < 2 {
+        return Err(Error::InvalidParameter {
+            field: field.to_string(),
+            message: format!("{} must be at least 2", replications),
+        });
+    }
+    Ok(())
+}
+
+// Checks every value of the range, which has the same bounds whether sampled linearly or not
+fn check_range(
+    field: &str,
+    range: &str,
+    check: impl Fn(&str, f64) -> Result<(), Error>,
+) -> Result<(), Error> {
+    for value in parse_field_range(field, range, None)? {
+        check(field, value)?;
+    }
+    Ok(())
+}
+
+impl Experiment {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Experiment::GammaLatency { .. } => "gamma-latency",
+            Experiment::GLatency { .. } => "g-latency",
+            Experiment::Poem { .. } => "poem",
+            Experiment::BitcoinVsPoem { .. } => "bitcoin-vs-poem",
+            Experiment::Dag { .. } => "dag",
+            Experiment::MergeMining { .. } => "merge-mining",
+            Experiment::Optimize { .. } => "optimize",
+            Experiment::Refine { .. } => "refine",
+            Experiment::Pareto { .. } => "pareto",
+            Experiment::FullGrid { .. } => "full-grid",
+            Experiment::Variance { .. } => "variance",
+            Experiment::QmcConvergence { .. } => "qmc-convergence",
+            Experiment::Adversary { .. } => "adversary",
+            Experiment::Backbone { .. } => "backbone",
+            Experiment::Rounds { .. } => "rounds",
+            Experiment::Resilience { .. } => "resilience",
+            Experiment::Growth { .. } => "growth",
+            Experiment::Stationary { .. } => "stationary",
+            Experiment::Confirmation { .. } => "confirmation",
+            Experiment::Mdp { .. } => "mdp",
+        }
+    }
+
+    // The sweeps evaluate each grid point on its own and record it in the checkpoint, which
+    // is what --resume skips and merge combines
+    pub fn uses_checkpoint(&self) -> bool {
+        matches!(
+            self,
+            Experiment::GammaLatency { .. }
+                | Experiment::GLatency { .. }
+                | Experiment::Poem { .. }
+                | Experiment::BitcoinVsPoem { .. }
+                | Experiment::Dag { .. }
+                | Experiment::MergeMining { .. }
+                | Experiment::FullGrid { .. }
+        )
+    }
+
+    // The shards of a run only meet again through their checkpoints
+    pub fn can_shard(&self) -> bool {
+        self.uses_checkpoint()
+    }
+
+    // Only a sweep lets each grid point draw as many samples as it needs; the searches pick
+    // their next point from a fixed set of samples and the rest fix their number
+    pub fn can_sequence(&self) -> bool {
+        self.uses_checkpoint()
+    }
+
+    // The MDP is solved exactly and draws nothing
+    pub fn uses_seed(&self) -> bool {
+        !matches!(self, Experiment::Mdp { .. })
+    }
+
+    // The variance reductions the experiment honours. Common random numbers are the default,
+    // so every experiment accepts them, even those that draw no samples or compare the
+    // samplers over their own replications.
+    pub fn get_variance_reductions(&self) -> &'static [VarianceReductionMode] {
+        match self {
+            _ if self.uses_checkpoint() => &[
+                VarianceReductionMode::None,
+                VarianceReductionMode::CommonRandomNumbers,
+                VarianceReductionMode::Antithetic,
+                VarianceReductionMode::ControlVariates,
+                VarianceReductionMode::QuasiMonteCarlo,
+            ],
+            Experiment::Variance { .. }
+            | Experiment::QmcConvergence { .. }
+            | Experiment::Mdp { .. } => &[VarianceReductionMode::CommonRandomNumbers],
+            // The others evaluate every point on the same samples, without control variates
+            _ => &[
+                VarianceReductionMode::CommonRandomNumbers,
+                VarianceReductionMode::Antithetic,
+                VarianceReductionMode::QuasiMonteCarlo,
+            ],
+        }
+    }
+
+    pub fn validate(&self, location: &str) -> Result<(), Error> {
+        let field = |name: &str| format!("{}, {}", location, name);
+
+        match self {
+            Experiment::GammaLatency {
+                beta,
+                g,
+                gamma_range,
+            } => {
+                check_beta(&field("beta"), *beta)?;
+                check_g(&field("g"), *g)?;
+                check_range(&field("gamma_range"), gamma_range, check_gamma)
+            }
+            Experiment::GLatency {
+                beta,
+                gamma,
+                g_range,
+            } => {
+                check_beta(&field("beta"), *beta)?;
+                check_gamma(&field("gamma"), *gamma)?;
+                check_range(&field("g_range"), g_range, check_g)
+            }
+            Experiment::Poem {
+                beta_range,
+                g_range,
+                gamma_range,
+            }
+            | Experiment::BitcoinVsPoem {
+                beta_range,
+                g_range,
+                gamma_range,
+            }
+            | Experiment::Dag {
+                beta_range,
+                g_range,
+                gamma_range,
+            }
+            | Experiment::FullGrid {
+                beta_range,
+                g_range,
+                gamma_range,
+            } => {
+                check_range(&field("beta_range"), beta_range, check_beta)?;
+                check_range(&field("g_range"), g_range, check_g)?;
+                check_range(&field("gamma_range"), gamma_range, check_gamma)
+            }
+            Experiment::MergeMining {
+                beta_range,
+                g_range,
+                gamma_range,
+                honest_split,
+                adversary_split_range,
+                ..
+            } => {
+                check_range(&field("beta_range"), beta_range, check_beta)?;
+                check_range(&field("g_range"), g_range, check_g)?;
+                check_range(&field("gamma_range"), gamma_range, check_gamma)?;
+                if let Some(split) = honest_split {
+                    check_fraction(&field("honest_split"), *split)?;
+                }
+                check_range(
+                    &field("adversary_split_range"),
+                    adversary_split_range,
+                    |field, split| {
+                        check_parameter(
+                            field,
+                            split,
+                            |split| (0. ..=1.).contains(&split),
+                            "between 0 and 1",
+                        )
+                    },
+                )
+            }
+            Experiment::Optimize {
+                beta_range,
+                g_range,
+                gamma_range,
+                tolerance,
+            } => {
+                check_range(&field("beta_range"), beta_range, check_beta)?;
+                check_range(&field("g_range"), g_range, check_g)?;
+                check_range(&field("gamma_range"), gamma_range, check_gamma)?;
+                check_fraction(&field("tolerance"), *tolerance)
+            }
+            Experiment::Refine {
+                beta_range,
+                g_range,
+                gamma_range,
+                refinement_points,
+                threshold,
+            } => {
+                check_range(&field("beta_range"), beta_range, check_beta)?;
+                check_range(&field("g_range"), g_range, check_g)?;
+                check_range(&field("gamma_range"), gamma_range, check_gamma)?;
+                if *refinement_points < 2 {
+                    return Err(Error::InvalidParameter {
+                        field: field("refinement_points"),
+                        message: format!("{} must be at least 2", refinement_points),
+                    });
+                }
+                check_gamma(&field("threshold"), *threshold)
+            }
+            Experiment::Pareto {
+                beta_range,
+                g_range,
+                gamma_range,
+                error_range,
+            } => {
+                check_range(&field("beta_range"), beta_range, check_beta)?;
+                check_range(&field("g_range"), g_range, check_g)?;
+                check_range(&field("gamma_range"), gamma_range, check_gamma)?;
+                check_range(&field("error_range"), error_range, check_error)
+            }
+            Experiment::Variance {
+                beta,
+                g,
+                gamma,
+                replications,
+            } => {
+                check_beta(&field("beta"), *beta)?;
+                check_g(&field("g"), *g)?;
+                check_gamma(&field("gamma"), *gamma)?;
+                check_replications(&field("replications"), *replications)
+            }
+            Experiment::QmcConvergence {
+                beta,
+                g,
+                gamma,
+                monte_carlo_range,
+                replications,
+            } => {
+                check_beta(&field("beta"), *beta)?;
+                check_g(&field("g"), *g)?;
+                check_gamma(&field("gamma"), *gamma)?;
+                check_range(
+                    &field("monte_carlo_range"),
+                    monte_carlo_range,
+                    |field, n| check_parameter(field, n, |n| n >= 1., "at least 1"),
+                )?;
+                check_replications(&field("replications"), *replications)
+            }
+            Experiment::Adversary {
+                beta_range,
+                g,
+                gamma,
+                protocol,
+                strategies,
+                policy,
+            } => {
+                check_range(&field("beta_range"), beta_range, check_beta)?;
+                check_g(&field("g"), *g)?;
+                check_gamma(&field("gamma"), *gamma)?;
+                // The event engine follows the heaviest chain
+                if *protocol == ProtocolName::PoemDag {
+                    return Err(Error::InvalidParameter {
+                        field: field("protocol"),
+                        message: "adversary strategies only run against chain protocols"
+                            .to_string(),
+                    });
+                }
+                if strategies.is_empty() && policy.is_none() {
+                    return Err(Error::InvalidParameter {
+                        field: field("strategies"),
+                        message: "at least one strategy or a policy is needed".to_string(),
+                    });
+                }
+                // The MDP is solved for the work of PoEM blocks
+                if policy.is_some() && *protocol != ProtocolName::Poem {
+                    return Err(Error::InvalidParameter {
+                        field: field("policy"),
+                        message: "MDP policies only replay against poem".to_string(),
+                    });
+                }
+                Ok(())
+            }
+            Experiment::Backbone {
+                beta_range,
+                g_range,
+                gamma,
+                protocols,
+                strategy: _,
+                window,
+            } => {
+                check_range(&field("beta_range"), beta_range, check_beta)?;
+                check_range(&field("g_range"), g_range, check_g)?;
+                check_gamma(&field("gamma"), *gamma)?;
+                if protocols.is_empty() || protocols.contains(&ProtocolName::PoemDag) {
+                    return Err(Error::InvalidParameter {
+                        field: field("protocols"),
+                        message: "at least one chain protocol is needed".to_string(),
+                    });
+                }
+                if *window == 0 {
+                    return Err(Error::InvalidParameter {
+                        field: field("window"),
+                        message: "must be positive".to_string(),
+                    });
+                }
+                Ok(())
+            }
+            Experiment::Rounds {
+                beta_range,
+                g_range,
+                gamma,
+                protocols,
+                parties,
+                queries,
+            } => {
+                check_range(&field("beta_range"), beta_range, check_beta)?;
+                check_range(&field("g_range"), g_range, check_g)?;
+                check_gamma(&field("gamma"), *gamma)?;
+                if protocols.is_empty() || protocols.contains(&ProtocolName::PoemDag) {
+                    return Err(Error::InvalidParameter {
+                        field: field("protocols"),
+                        message: "at least one chain protocol is needed".to_string(),
+                    });
+                }
+                if *queries == 0 {
+                    return Err(Error::InvalidParameter {
+                        field: field("queries"),
+                        message: "must be positive".to_string(),
+                    });
+                }
+                let g_max = parse_field_range(&field("g_range"), g_range, None)?
+                    .into_iter()
+                    .fold(0., f64::max);
+                for beta in parse_field_range(&field("beta_range"), beta_range, None)? {
+                    let model = RoundModel::new(*parties, *queries, beta, g_max);
+                    if model.corrupted == 0 || 2 * model.corrupted >= *parties {
+                        return Err(Error::InvalidParameter {
+                            field: field("parties"),
+                            message: format!(
+                                "{} parties leave no adversary or no honest majority at beta {}",
+                                parties, beta
+                            ),
+                        });
+                    }
+                    if model.success > 1. {
+                        return Err(Error::InvalidParameter {
+                            field: field("queries"),
+                            message: format!(
+                                "{} queries per party cannot find {} blocks per round",
+                                queries, g_max
+                            ),
+                        });
+                    }
+                }
+                Ok(())
+            }
+            Experiment::Resilience {
+                g_range,
+                gamma_range,
+                protocols,
+                tolerance,
+            } => {
+                check_range(&field("g_range"), g_range, check_g)?;
+                check_range(&field("gamma_range"), gamma_range, check_gamma)?;
+                if protocols.is_empty() {
+                    return Err(Error::InvalidParameter {
+                        field: field("protocols"),
+                        message: "at least one protocol is needed".to_string(),
+                    });
+                }
+                check_parameter(
+                    &field("tolerance"),
+                    *tolerance,
+                    |tolerance| tolerance > 0. && tolerance < 0.5,
+                    "in (0, 0.5)",
+                )
+            }
+            Experiment::Growth {
+                beta,
+                g_range,
+                gamma_range,
+                protocols,
+            } => {
+                check_beta(&field("beta"), *beta)?;
+                check_range(&field("g_range"), g_range, check_g)?;
+                check_range(&field("gamma_range"), gamma_range, check_gamma)?;
+                if protocols.is_empty() {
+                    return Err(Error::InvalidParameter {
+                        field: field("protocols"),
+                        message: "at least one protocol is needed".to_string(),
+                    });
+                }
+                Ok(())
+            }
+            Experiment::Stationary {
+                beta_range,
+                g,
+                gamma,
+                protocols,
+                burn_in,
+                target_height,
+            } => {
+                check_range(&field("beta_range"), beta_range, check_beta)?;
+                check_g(&field("g"), *g)?;
+                check_gamma(&field("gamma"), *gamma)?;
+                if protocols.is_empty() {
+                    return Err(Error::InvalidParameter {
+                        field: field("protocols"),
+                        message: "at least one protocol is needed".to_string(),
+                    });
+                }
+                // The race needs at least half of the execution after the transaction
+                let (name, height) = match target_height {
+                    Some(height) => ("target_height", *height),
+                    None => ("burn_in", 2 * *burn_in),
+                };
+                if height == 0 || height > HONEST_COUNT / 2 {
+                    return Err(Error::InvalidParameter {
+                        field: field(name),
+                        message: format!(
+                            "the transaction must be placed between heights 1 and {}",
+                            HONEST_COUNT / 2
+                        ),
+                    });
+                }
+                Ok(())
+            }
+            Experiment::Confirmation {
+                beta_range,
+                g,
+                gamma,
+                protocols,
+                rules,
+                hybrid_time,
+            } => {
+                check_range(&field("beta_range"), beta_range, check_beta)?;
+                check_g(&field("g"), *g)?;
+                check_gamma(&field("gamma"), *gamma)?;
+                if protocols.is_empty() {
+                    return Err(Error::InvalidParameter {
+                        field: field("protocols"),
+                        message: "at least one protocol is needed".to_string(),
+                    });
+                }
+                if rules.is_empty() {
+                    return Err(Error::InvalidParameter {
+                        field: field("rules"),
+                        message: "at least one rule is needed".to_string(),
+                    });
+                }
+                check_parameter(
+                    &field("hybrid_time"),
+                    *hybrid_time,
+                    |time| time >= 0. && time.is_finite(),
+                    "non-negative",
+                )
+            }
+            Experiment::Mdp {
+                beta_range,
+                gamma,
+                k_range,
+                max_lead,
+                resolution,
+            } => {
+                check_range(&field("beta_range"), beta_range, check_beta)?;
+                check_gamma(&field("gamma"), *gamma)?;
+                check_range(&field("k_range"), k_range, |field, k| {
+                    check_parameter(field, k, |k| k >= 0. && k.is_finite(), "non-negative")
+                })?;
+                for (name, value) in [("max_lead", *max_lead), ("resolution", *resolution)] {
+                    if value == 0 {
+                        return Err(Error::InvalidParameter {
+                            field: field(name),
+                            message: "must be positive".to_string(),
+                        });
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Scenario {
+    fn validate(&self) -> Result<(), Error> {
+        check_settings(self.monte_carlo, "risk.error", self.risk.error)?;
+        if let Some(horizon) = self.horizon {
+            if horizon != HONEST_COUNT {
+                return Err(Error::InvalidParameter {
+                    field: "horizon".to_string(),
+                    message: format!(
+                        "{} is not supported, executions are {} blocks long",
+                        horizon, HONEST_COUNT
+                    ),
+                });
+            }
+        }
+        if let Some(sequential) = self.sequential {
+            sequential.validate(self.monte_carlo)?;
+        }
+        if self.experiments.is_empty() {
+            return Err(Error::InvalidParameter {
+                field: "experiment".to_string(),
+                message: "at least one experiment is required".to_string(),
+            });
+        }
+        for (index, experiment) in self.experiments.iter().enumerate() {
+            experiment.validate(&format!("experiment {} ({})", index + 1, experiment.name()))?;
+        }
+        Ok(())
+    }
+}
+
+pub fn parse_toml_scenario(contents: &str) -> Result<Scenario, Error> {
+    let scenario: Scenario =
+        toml::from_str(contents).map_err(|error| Error::Scenario(error.to_string()))?;
+    scenario.validate()?;
+    Ok(scenario)
+}
+
+pub fn parse_json_scenario(contents: &str) -> Result<Scenario, Error> {
+    let scenario: Scenario =
+        serde_json::from_str(contents).map_err(|error| Error::Scenario(error.to_string()))?;
+    scenario.validate()?;
+    Ok(scenario)
+}
+
+pub fn load_scenario(path: &Path) -> Result<Scenario, Error> {
+    let contents = std::fs::read_to_string(path)?;
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("toml") => parse_toml_scenario(&contents),
+        Some("json") => parse_json_scenario(&contents),
+        extension => Err(Error::UnsupportedFormat(
+            extension.unwrap_or_default().to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_parse_scenario() {
+        let scenario = parse_toml_scenario(
+            r#"
+            monte_carlo = 100
+            horizon = 1300
+
+            [risk]
+            error = 0.05
+
+            [[experiment]]
+            kind = "gamma-latency"
+            beta = 0.1
+            g = 1.7
+            gamma_range = "0:70:20"
+
+            [[experiment]]
+            kind = "refine"
+            beta_range = "0.01:0.45:33"
+            g_range = "0.05:85.0:10"
+            gamma_range = "0:20.0:8"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(scenario.monte_carlo, 100);
+        assert_eq!(scenario.risk.error, 0.05);
+        assert_eq!(scenario.output.directory, "simulation_data");
+        assert_eq!(scenario.experiments.len(), 2);
+        assert!(matches!(
+            scenario.experiments[1],
+            Experiment::Refine {
+                refinement_points: 5,
+                ..
+            }
+        ));
+
+        // Unknown fields are rejected
+        let error = parse_toml_scenario(
+            r#"
+            [[experiment]]
+            kind = "poem"
+            beta_range = "0.01:0.45:33"
+            g_range = "0.05:85.0:50"
+            gama_range = "0:20.0:40"
+            "#,
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("gama_range"));
+
+        // Values outside their domain are reported with their location
+        let error = parse_toml_scenario(
+            r#"
+            [[experiment]]
+            kind = "g-latency"
+            beta = 0.6
+            gamma = 0
+            g_range = "0.1:6.3:50"
+            "#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "experiment 1 (g-latency), beta: beta must be strictly between 0 and 0.5, got 0.6"
+        );
+
+        let error = parse_json_scenario(
+            r#"{"experiment": [{"kind": "dag", "beta_range": "0.1:0.3", "g_range": "1:2:2", "gamma_range": "0:1:2"}]}"#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "experiment 1 (dag), beta_range: Invalid range format. Use the format: start:end:num_points"
+        );
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let scenario = parse_toml_scenario(
+            r#"
+            [[experiment]]
+            kind = "poem"
+            beta_range = "0.01:0.45:3"
+            g_range = "0.05:85.0:3"
+            gamma_range = "0:20.0:3"
+
+            [[experiment]]
+            kind = "refine"
+            beta_range = "0.01:0.45:3"
+            g_range = "0.05:85.0:3"
+            gamma_range = "0:20.0:3"
+
+            [[experiment]]
+            kind = "mdp"
+            beta_range = "0.1:0.4:3"
+            gamma = 0
+            k_range = "1:4:4"
+            "#,
+        )
+        .unwrap();
+        let [poem, refine, mdp] = &scenario.experiments[..] else {
+            panic!("expected three experiments");
+        };
+        // Only the sweeps keep a checkpoint, so only they shard, sequence and resume
+        assert!(poem.uses_checkpoint() && poem.can_shard() && poem.can_sequence());
+        assert!(!refine.uses_checkpoint() && !refine.can_shard() && !refine.can_sequence());
+        assert!(poem.uses_seed() && refine.uses_seed() && !mdp.uses_seed());
+        assert!(poem
+            .get_variance_reductions()
+            .contains(&VarianceReductionMode::ControlVariates));
+        assert!(!refine
+            .get_variance_reductions()
+            .contains(&VarianceReductionMode::None));
+        assert_eq!(
+            mdp.get_variance_reductions(),
+            [VarianceReductionMode::CommonRandomNumbers]
+        );
+    }
+
+    #[test]
+    fn test_check_settings() {
+        assert!(check_settings(1000, "error", 0.1).is_ok());
+        assert_eq!(
+            check_settings(0, "error", 0.1).unwrap_err().to_string(),
+            "monte_carlo: must be positive"
+        );
+        for error in [0., 0.7] {
+            assert_eq!(
+                check_settings(1000, "error", error)
+                    .unwrap_err()
+                    .to_string(),
+                format!("error: {} must be strictly between 0 and 0.5", error)
+            );
+        }
+    }
+}
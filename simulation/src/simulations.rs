@@ -1,28 +1,23 @@
 use crate::{
-    analysis::get_monte_carlo_performance,
-    formatting::{
-        get_monte_carlo_progresses, scale_monte_carlo_blocks, scale_monte_carlo_progresses,
+    analysis::{
+        get_monte_carlo_partial_performance, PartialPerformance, Performance, SUMMATION_BLOCK,
     },
-    sampling::{get_monte_carlo_bitcoin_executions, sample_monte_carlo_poem_executions},
-    types::INF,
+    checkpoint::{get_description_hash, Checkpoint},
+    formatting::{scale_monte_carlo_blocks, scale_monte_carlo_progresses},
+    protocol::{Bitcoin, Poem, PoemDag, Protocol},
+    sampling::Samples,
+    types::{Block, Chain, INF},
 };
 
 pub const HONEST_COUNT: usize = 1300;
 pub const ADVERSARY_COUNT: usize = 1300;
 
-pub struct Data {
-    pub bitcoin_latencies: Vec<f64>,
-    pub bitcoin_optimal_k: Vec<f64>,
-    pub bitcoin_optimal_g: Vec<f64>,
-    pub bitcoin_throughputs: Vec<f64>,
-    pub poem_latencies: Vec<f64>,
-    pub poem_optimal_k: Vec<f64>,
-    pub poem_optimal_g: Vec<f64>,
-    pub poem_optimal_gamma: Vec<f64>,
-    pub poem_throughputs: Vec<f64>,
-}
+// The sweeps generate and evaluate the samples this many at a time, so that their memory
+// does not grow with the number of samples. Chunks are whole summation blocks.
+const CHUNK_SIZE: usize = SUMMATION_BLOCK;
 
-pub struct PoemData {
+// The best performance of a protocol in each cell of a sweep
+pub struct SweepData {
     pub latency: Vec<f64>,
     pub optimal_k: Vec<f64>,
     pub optimal_g: Vec<f64>,
@@ -32,41 +27,403 @@ pub struct PoemData {
     pub max_height: Vec<f64>,
     pub adversary_max_work: Vec<f64>,
     pub adversary_max_height: Vec<f64>,
-}
-
-pub struct BitcoinData {
-    pub latency: Vec<f64>,
-    pub optimal_k: Vec<f64>,
-    pub optimal_g: Vec<f64>,
-    pub throughput: Vec<f64>,
-    pub max_work: Vec<f64>,
-    pub max_height: Vec<f64>,
-    pub adversary_max_work: Vec<f64>,
-    pub adversary_max_height: Vec<f64>,
+    // The number of samples the reported point was evaluated on
+    pub monte_carlo: Vec<usize>,
 }
 
 pub enum ReductionType {
     Beta,
     Gamma,
     G,
+    // Keeps every (beta, g, gamma) cell, in the order of get_full_grid
+    Full,
 }
 
-pub fn simulate_poem(
-    timestamps: (Vec<[f64; HONEST_COUNT]>, Vec<[f64; ADVERSARY_COUNT]>),
+// Index of a cell in a ReductionType::Full sweep, with beta varying fastest and gamma slowest
+pub fn get_full_grid_index(
+    beta_index: usize,
+    g_index: usize,
+    gamma_index: usize,
+    beta_length: usize,
+    g_length: usize,
+) -> usize {
+    (gamma_index * g_length + g_index) * beta_length + beta_index
+}
+
+// The (beta, g, gamma) of each cell of a ReductionType::Full sweep
+pub fn get_full_grid(
+    beta_range: &[f64],
+    g_range: &[f64],
+    gamma_range: &[f64],
+) -> Vec<(f64, f64, f64)> {
+    gamma_range
+        .iter()
+        .flat_map(|&gamma| {
+            g_range
+                .iter()
+                .flat_map(move |&g| beta_range.iter().map(move |&beta| (beta, g, gamma)))
+        })
+        .collect()
+}
+
+// Without common random numbers, each grid point of each protocol draws the samples of its
+// own point
+fn get_point(protocol: &str, beta: f64, g: f64, gamma: f64) -> u64 {
+    get_description_hash(&format!(
+        "{}:{:x}:{:x}:{:x}",
+        protocol,
+        beta.to_bits(),
+        g.to_bits(),
+        gamma.to_bits()
+    ))
+}
+
+// The control variate of each sample: the rate at which its scaled honest chain grows, less
+// the analytic mean of that rate. The last of n exponential block times of rate g has a gamma
+// distribution, whose inverse has mean g / (n - 1).
+fn get_controls(scaled_honest_blocks: &[[Block; HONEST_COUNT]], g: f64) -> Vec<f64> {
+    let n = HONEST_COUNT as f64;
+    scaled_honest_blocks
+        .iter()
+        .map(|blocks| n / blocks[HONEST_COUNT - 1].timestamp - g * n / (n - 1.))
+        .collect()
+}
+
+// The analytic Bitcoin result: the probability that a private adversary with the fraction
+// beta < 1/2 of the hash power, mining without delay from genesis, ever catches up with the
+// honest chain once it has z blocks. The adversary has m blocks by then with the negative
+// binomial probability C(m + z - 1, m) p^z q^m, and catches up from a deficit of z - m with
+// probability (q / p)^(z - m).
+fn get_catch_up_probability(beta: f64, z: usize) -> f64 {
+    let (p, q) = (1. - beta, beta);
+    let mut term = p.powi(z as i32);
+    let mut probability = 1.;
+    for m in 0..z {
+        probability -= term * (1. - (q / p).powi((z - m) as i32));
+        term *= q * (m + z) as f64 / (m + 1) as f64;
+    }
+    probability
+}
+
+// The tail control variate of each sample at beta, when 0 < beta < 1/2: the probability that
+// the adversary catches up given the blocks it has when the honest chain has z of them, less
+// its analytic mean. z is the depth at which Bitcoin without delay is safe at epsilon, so the
+// control is large on the samples in which the adversary is lucky early, which make up the
+// tail of k. Both parties mine their unscaled blocks at rate 1, so the adversary block at t
+// comes before the honest block at t' once scaled when t (1 - beta) / beta <= t'.
+fn get_tail_controls(
+    honest_blocks: &[[Block; HONEST_COUNT]],
+    adversary_blocks: &[[Block; ADVERSARY_COUNT]],
+    beta: f64,
+    epsilon: f64,
+) -> Option<Vec<f64>> {
+    if beta <= 0. || beta >= 0.5 {
+        return None;
+    }
+    let z = (1..HONEST_COUNT / 2)
+        .find(|&z| get_catch_up_probability(beta, z) <= epsilon)
+        .unwrap_or(HONEST_COUNT / 2);
+    let mean = get_catch_up_probability(beta, z);
+    let ratio = beta / (1. - beta);
+    let controls = honest_blocks
+        .iter()
+        .zip(adversary_blocks)
+        .map(|(honest_blocks, adversary_blocks)| {
+            let time = honest_blocks[z - 1].timestamp * ratio;
+            let m = adversary_blocks.partition_point(|block| block.timestamp <= time);
+            ratio.powi(z as i32 - m as i32).min(1.) - mean
+        })
+        .collect();
+    Some(controls)
+}
+
+// Evaluates every beta of `beta_range` at (g, gamma) and records the performances in the
+// checkpoint. Only the k values the expected shortfall over the `monte_carlo` samples of
+// the run can reach are kept between chunks.
+#[allow(clippy::too_many_arguments)]
+fn evaluate_betas(
+    protocol: &dyn Protocol,
+    samples: Samples,
+    monte_carlo: usize,
+    epsilon: f64,
+    beta_range: &[f64],
+    g: f64,
+    gamma: f64,
+    checkpoint: &mut Checkpoint,
+) {
+    if !samples.variance_reduction.common_random_numbers {
+        for &beta in beta_range {
+            let samples = Samples {
+                point: get_point(protocol.name(), beta, g, gamma),
+                ..samples
+            };
+            evaluate_common_betas(
+                protocol,
+                samples,
+                monte_carlo,
+                epsilon,
+                &[beta],
+                g,
+                gamma,
+                checkpoint,
+            );
+        }
+    } else {
+        evaluate_common_betas(
+            protocol,
+            samples,
+            monte_carlo,
+            epsilon,
+            beta_range,
+            g,
+            gamma,
+            checkpoint,
+        );
+    }
+}
+
+// Announces the protocol and returns the gamma it is simulated at, 0 without a per-block bias
+pub fn start_protocol(protocol: &dyn Protocol, gamma: f64) -> f64 {
+    println!("Working on {}...", protocol.title());
+    if protocol.has_bias() {
+        gamma
+    } else {
+        0.0
+    }
+}
+
+// Same as start_protocol, for a range of gammas
+pub fn start_protocol_sweep<'a>(protocol: &dyn Protocol, gamma_range: &'a [f64]) -> &'a [f64] {
+    println!("Working on {}...", protocol.title());
+    if protocol.has_bias() {
+        gamma_range
+    } else {
+        &[0.0]
+    }
+}
+
+// The executions of a chunk of samples: the sampled blocks, the honest ones scaled to
+// (g, gamma), and once built, the honest progresses and the unscaled adversary progresses,
+// with a copy that each beta scales
+pub struct ChunkExecutions {
+    pub honest_blocks: Vec<[Block; HONEST_COUNT]>,
+    pub adversary_blocks: Vec<[Block; ADVERSARY_COUNT]>,
+    pub scaled_honest_blocks: Vec<[Block; HONEST_COUNT]>,
+    pub honest_progresses: Vec<Vec<Chain>>,
+    pub adversary_progresses: Vec<Vec<Chain>>,
+    pub scaled_adversary_progresses: Vec<Vec<Chain>>,
+}
+
+impl ChunkExecutions {
+    // Samples the blocks of the chunk, without building any progress
+    pub fn sample(protocol: &dyn Protocol, chunk: Samples, g: f64, gamma: f64) -> Self {
+        let (honest_blocks, adversary_blocks) = protocol.sample_executions(chunk);
+        let mut scaled_honest_blocks = honest_blocks.clone();
+        scale_monte_carlo_blocks(&mut scaled_honest_blocks, &honest_blocks, g, gamma);
+        ChunkExecutions {
+            honest_blocks,
+            adversary_blocks,
+            scaled_honest_blocks,
+            honest_progresses: Vec::new(),
+            adversary_progresses: Vec::new(),
+            scaled_adversary_progresses: Vec::new(),
+        }
+    }
+
+    // Samples the blocks of the chunk and builds the progresses of both parties
+    pub fn sample_progresses(protocol: &dyn Protocol, chunk: Samples, g: f64, gamma: f64) -> Self {
+        let mut executions = Self::sample(protocol, chunk, g, gamma);
+        executions.honest_progresses = vec![Vec::with_capacity(HONEST_COUNT + 1); chunk.len()];
+        protocol.get_honest_progresses(
+            &mut executions.honest_progresses,
+            &executions.scaled_honest_blocks,
+        );
+        executions.adversary_progresses =
+            vec![Vec::with_capacity(ADVERSARY_COUNT + 1); chunk.len()];
+        protocol.get_adversary_progresses(
+            &mut executions.adversary_progresses,
+            &executions.adversary_blocks,
+        );
+        executions.scaled_adversary_progresses = executions.adversary_progresses.clone();
+        executions
+    }
+
+    // Rescales the honest blocks to (g, gamma), rebuilding the honest progresses if they
+    // were built
+    pub fn scale_honest(&mut self, protocol: &dyn Protocol, g: f64, gamma: f64) {
+        scale_monte_carlo_blocks(
+            &mut self.scaled_honest_blocks,
+            &self.honest_blocks,
+            g,
+            gamma,
+        );
+        if !self.honest_progresses.is_empty() {
+            protocol.get_honest_progresses(&mut self.honest_progresses, &self.scaled_honest_blocks);
+        }
+    }
+
+    // Scales the adversary progresses to beta at (g, gamma)
+    pub fn scale_adversary(&mut self, beta: f64, g: f64, gamma: f64) {
+        if beta == 0. {
+            // An adversary without hash power never gets past genesis
+            for progresses in self.scaled_adversary_progresses.iter_mut() {
+                progresses[1..]
+                    .iter_mut()
+                    .for_each(|progress| progress.timestamp = INF);
+            }
+        } else {
+            scale_monte_carlo_progresses(
+                &mut self.scaled_adversary_progresses,
+                &self.adversary_progresses,
+                g * beta / (1. - beta),
+                gamma,
+            );
+        }
+    }
+}
+
+// Evaluates the betas on the same samples
+#[allow(clippy::too_many_arguments)]
+fn evaluate_common_betas(
+    protocol: &dyn Protocol,
+    samples: Samples,
+    monte_carlo: usize,
+    epsilon: f64,
+    beta_range: &[f64],
+    g: f64,
+    gamma: f64,
+    checkpoint: &mut Checkpoint,
+) {
+    let chunk_size = CHUNK_SIZE.min(samples.len());
+    let mut partials = vec![PartialPerformance::default(); beta_range.len()];
+    let mut honest_progresses = vec![Vec::with_capacity(HONEST_COUNT + 1); chunk_size];
+    let mut adversary_progresses = vec![Vec::with_capacity(ADVERSARY_COUNT + 1); chunk_size];
+    let mut scaled_adversary_progresses = vec![Vec::with_capacity(ADVERSARY_COUNT + 1); chunk_size];
+    // A sequential run keeps the tail that its whole budget may need
+    let (tail_monte_carlo, max_end) = match samples.sequential {
+        Some(sequential) => (
+            sequential.max_monte_carlo,
+            samples.start + sequential.max_monte_carlo,
+        ),
+        None => (monte_carlo, samples.end),
+    };
+    // The betas that still draw samples, all of them but sequential ones that are precise
+    let mut active = vec![true; beta_range.len()];
+    let mut batch = samples;
+
+    loop {
+        for chunk in batch.chunks(CHUNK_SIZE) {
+            let (honest_blocks, adversary_blocks) = protocol.sample_executions(chunk);
+            let honest_progresses = &mut honest_progresses[..chunk.len()];
+            let adversary_progresses = &mut adversary_progresses[..chunk.len()];
+            let scaled_adversary_progresses = &mut scaled_adversary_progresses[..chunk.len()];
+
+            // Get the honest progress of the scaled honest blocks
+            let mut scaled_honest_blocks = honest_blocks.clone();
+            scale_monte_carlo_blocks(&mut scaled_honest_blocks, &honest_blocks, g, gamma);
+            protocol.get_honest_progresses(honest_progresses, &scaled_honest_blocks);
+            let controls = samples
+                .variance_reduction
+                .control_variates
+                .then(|| get_controls(&scaled_honest_blocks, g));
+
+            // The adversary progress is scaled per beta, from the same unscaled progress
+            protocol.get_adversary_progresses(adversary_progresses, &adversary_blocks);
+            scaled_adversary_progresses.clone_from_slice(adversary_progresses);
+
+            for ((partial, &beta), _) in partials
+                .iter_mut()
+                .zip(beta_range)
+                .zip(&active)
+                .filter(|(_, &active)| active)
+            {
+                if beta == 0. {
+                    // An adversary without hash power never gets past genesis
+                    for progresses in scaled_adversary_progresses.iter_mut() {
+                        progresses[1..]
+                            .iter_mut()
+                            .for_each(|progress| progress.timestamp = INF);
+                    }
+                } else {
+                    scale_monte_carlo_progresses(
+                        scaled_adversary_progresses,
+                        adversary_progresses,
+                        g * beta / (1. - beta),
+                        gamma,
+                    );
+                }
+                let tail_controls = samples
+                    .variance_reduction
+                    .control_variates
+                    .then(|| get_tail_controls(&honest_blocks, &adversary_blocks, beta, epsilon))
+                    .flatten();
+                partial.extend(get_monte_carlo_partial_performance(
+                    honest_progresses,
+                    scaled_adversary_progresses,
+                    controls.as_deref(),
+                    tail_controls.as_deref(),
+                ));
+                partial.prune(tail_monte_carlo, epsilon);
+            }
+        }
+
+        let Some(sequential) = samples.sequential else {
+            break;
+        };
+        for ((partial, &beta), active) in partials.iter().zip(beta_range).zip(active.iter_mut()) {
+            if !*active {
+                continue;
+            }
+            let ((k, ..), error) = partial.get_performance_per_epsilon(&[epsilon])[0];
+            if sequential.is_precise(k, error) || batch.end == max_end {
+                println!("   - beta: {}, {} samples", beta, partial.get_monte_carlo());
+                *active = false;
+            }
+        }
+        if !active.contains(&true) {
+            break;
+        }
+        batch = Samples {
+            start: batch.end,
+            end: (batch.end + samples.len()).min(max_end),
+            ..samples
+        };
+    }
+
+    for (partial, &beta) in partials.iter().zip(beta_range) {
+        checkpoint.insert(protocol.name(), beta, g, gamma, partial, epsilon);
+    }
+}
+
+// Sweeps the protocol over the grid, keeping the lowest latency of each cell of the
+// reduction. A protocol without a per-block bias is swept over the single gamma = 0.
+#[allow(clippy::too_many_arguments)]
+pub fn simulate_protocol(
+    protocol: &dyn Protocol,
+    samples: Samples,
     monte_carlo: usize,
     epsilon: f64,
     beta_range: Vec<f64>,
     g_range: Vec<f64>,
     gamma_range: Vec<f64>,
     reduction_type: ReductionType,
-) -> PoemData {
-    println!("Working on PoEM...");
+    checkpoint: &mut Checkpoint,
+) -> SweepData {
+    println!("Working on {}...", protocol.title());
+    let gamma_range = if protocol.has_bias() {
+        gamma_range
+    } else {
+        vec![0.0]
+    };
     let data_length = match reduction_type {
         ReductionType::Beta => beta_range.len(),
         ReductionType::Gamma => gamma_range.len(),
         ReductionType::G => g_range.len(),
+        ReductionType::Full => beta_range.len() * g_range.len() * gamma_range.len(),
     };
-    let mut poem_data = PoemData {
+    // A full grid records every cell, insecure ones included, instead of the fastest
+    let keep_all = matches!(reduction_type, ReductionType::Full);
+    let mut data = SweepData {
         latency: vec![INF; data_length],
         optimal_k: vec![INF; data_length],
         optimal_g: vec![0.0; data_length],
@@ -76,60 +433,41 @@ pub fn simulate_poem(
         max_height: vec![0.0; data_length],
         adversary_max_work: vec![0.0; data_length],
         adversary_max_height: vec![0.0; data_length],
+        monte_carlo: vec![0; data_length],
     };
 
-    // Get the block creations
-    let (poem_honest_monte_carlo, poem_adversary_monte_carlo) =
-        sample_monte_carlo_poem_executions::<HONEST_COUNT, ADVERSARY_COUNT>(&timestamps);
-
-    // Create the scaled honest blocks initial vector
-    let mut scaled_poem_honest_monte_carlo = poem_honest_monte_carlo.clone();
-    // Create the honest progress empty vector
-    let mut poem_honest_progress_monte_carlo =
-        vec![Vec::with_capacity(HONEST_COUNT + 1); monte_carlo];
-
-    // Create the adversary progress empty vector
-    let mut original_poem_adversary_progress_monte_carlo =
-        vec![Vec::with_capacity(ADVERSARY_COUNT + 1); monte_carlo];
-    // Initialize the adversary progress initial vector
-    get_monte_carlo_progresses(
-        &mut original_poem_adversary_progress_monte_carlo,
-        &poem_adversary_monte_carlo,
-        0.,
-    );
-    // Create the scaled adversary progress empty vector
-    let mut scaled_poem_adversary_progress_monte_carlo =
-        original_poem_adversary_progress_monte_carlo.clone();
-
     for (gamma_index, &gamma) in gamma_range.iter().enumerate() {
-        println!("gamma: {}", gamma);
+        if protocol.has_bias() {
+            println!("gamma: {}", gamma);
+        }
         for (g_index, &g) in g_range.iter().enumerate() {
-            println!(" - g: {}", g);
-            // Scale PoEM honest blocks
-            scale_monte_carlo_blocks(
-                &mut scaled_poem_honest_monte_carlo,
-                &poem_honest_monte_carlo,
-                g,
-                gamma,
-            );
-
-            // Get PoEM honest progress
-            get_monte_carlo_progresses(
-                &mut poem_honest_progress_monte_carlo,
-                &scaled_poem_honest_monte_carlo,
-                1.,
-            );
-
-            for (beta_index, &beta) in beta_range.iter().enumerate() {
-                // Scale PoEM adversary progress
-                scale_monte_carlo_progresses(
-                    &mut scaled_poem_adversary_progress_monte_carlo,
-                    &original_poem_adversary_progress_monte_carlo,
-                    g * beta / (1. - beta),
+            if protocol.has_bias() {
+                println!(" - g: {}", g);
+            } else {
+                println!("g: {}", g);
+            }
+            // Only the betas that have not been evaluated yet need the samples
+            let missing: Vec<f64> = beta_range
+                .iter()
+                .copied()
+                .filter(|&beta| checkpoint.get(protocol.name(), beta, g, gamma).is_none())
+                .collect();
+            if !missing.is_empty() {
+                evaluate_betas(
+                    protocol,
+                    samples,
+                    monte_carlo,
+                    epsilon,
+                    &missing,
+                    g,
                     gamma,
+                    checkpoint,
                 );
+            }
 
+            for (beta_index, &beta) in beta_range.iter().enumerate() {
                 // Get configuration performance
+                let performance = checkpoint.get(protocol.name(), beta, g, gamma).unwrap();
                 let (
                     k,
                     f_work,
@@ -138,127 +476,168 @@ pub fn simulate_poem(
                     max_height,
                     adversary_max_work,
                     adversary_max_height,
-                ) = get_monte_carlo_performance(
-                    &poem_honest_progress_monte_carlo,
-                    &scaled_poem_adversary_progress_monte_carlo,
-                    epsilon,
-                );
-                let poem_latency = k / f_work;
+                ) = performance;
+                let latency = k / f_work;
+                let monte_carlo = checkpoint
+                    .get_monte_carlo(protocol.name(), beta, g, gamma)
+                    .unwrap();
                 let reduction_index = match reduction_type {
                     ReductionType::Beta => beta_index,
                     ReductionType::Gamma => gamma_index,
                     ReductionType::G => g_index,
+                    ReductionType::Full => get_full_grid_index(
+                        beta_index,
+                        g_index,
+                        gamma_index,
+                        beta_range.len(),
+                        g_range.len(),
+                    ),
                 };
 
-                if poem_latency < poem_data.latency[reduction_index] {
-                    poem_data.latency[reduction_index] = poem_latency;
-                    poem_data.optimal_k[reduction_index] = k;
-                    poem_data.optimal_gamma[reduction_index] = gamma;
-                    poem_data.optimal_g[reduction_index] = g;
-                    poem_data.throughput[reduction_index] = f_height;
-                    poem_data.max_work[reduction_index] = max_work;
-                    poem_data.max_height[reduction_index] = max_height;
-                    poem_data.adversary_max_work[reduction_index] = adversary_max_work;
-                    poem_data.adversary_max_height[reduction_index] = adversary_max_height;
+                if keep_all || latency < data.latency[reduction_index] {
+                    data.latency[reduction_index] = latency;
+                    data.optimal_k[reduction_index] = k;
+                    data.optimal_gamma[reduction_index] = gamma;
+                    data.optimal_g[reduction_index] = g;
+                    data.throughput[reduction_index] = f_height;
+                    data.max_work[reduction_index] = max_work;
+                    data.max_height[reduction_index] = max_height;
+                    data.adversary_max_work[reduction_index] = adversary_max_work;
+                    data.adversary_max_height[reduction_index] = adversary_max_height;
+                    data.monte_carlo[reduction_index] = monte_carlo;
+                } else if latency == INF && data.latency[reduction_index] == INF {
+                    // A cell the adversary always wins reports the most samples spent on it
+                    data.monte_carlo[reduction_index] =
+                        data.monte_carlo[reduction_index].max(monte_carlo);
                 }
             }
         }
     }
-    poem_data
+    data
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn simulate_poem(
+    samples: Samples,
+    monte_carlo: usize,
+    epsilon: f64,
+    beta_range: Vec<f64>,
+    g_range: Vec<f64>,
+    gamma_range: Vec<f64>,
+    reduction_type: ReductionType,
+    checkpoint: &mut Checkpoint,
+) -> SweepData {
+    simulate_protocol(
+        &Poem,
+        samples,
+        monte_carlo,
+        epsilon,
+        beta_range,
+        g_range,
+        gamma_range,
+        reduction_type,
+        checkpoint,
+    )
+}
+
+// Same sweep as simulate_poem, but the honest blocks form a DAG in which each block
+// references every visible tip, and the adversary races against its total weight.
+#[allow(clippy::too_many_arguments)]
+pub fn simulate_poem_dag(
+    samples: Samples,
+    monte_carlo: usize,
+    epsilon: f64,
+    beta_range: Vec<f64>,
+    g_range: Vec<f64>,
+    gamma_range: Vec<f64>,
+    reduction_type: ReductionType,
+    checkpoint: &mut Checkpoint,
+) -> SweepData {
+    simulate_protocol(
+        &PoemDag,
+        samples,
+        monte_carlo,
+        epsilon,
+        beta_range,
+        g_range,
+        gamma_range,
+        reduction_type,
+        checkpoint,
+    )
 }
 
 pub fn simulate_bitcoin(
-    timestamps: (Vec<[f64; HONEST_COUNT]>, Vec<[f64; ADVERSARY_COUNT]>),
+    samples: Samples,
     monte_carlo: usize,
     epsilon: f64,
     beta_range: Vec<f64>,
     g_range: Vec<f64>,
-) -> BitcoinData {
-    println!("Working on Bitcoin...");
-    let mut bitcoin_data = BitcoinData {
-        latency: vec![INF; beta_range.len()],
-        optimal_k: vec![INF; beta_range.len()],
-        optimal_g: vec![0.0; beta_range.len()],
-        throughput: vec![0.0; beta_range.len()],
-        max_work: vec![0.0; beta_range.len()],
-        max_height: vec![0.0; beta_range.len()],
-        adversary_max_work: vec![0.0; beta_range.len()],
-        adversary_max_height: vec![0.0; beta_range.len()],
-    };
+    reduction_type: ReductionType,
+    checkpoint: &mut Checkpoint,
+) -> SweepData {
+    simulate_protocol(
+        &Bitcoin,
+        samples,
+        monte_carlo,
+        epsilon,
+        beta_range,
+        g_range,
+        vec![0.0],
+        reduction_type,
+        checkpoint,
+    )
+}
 
-    let (bitcoin_honest_monte_carlo, bitcoin_adversary_monte_carlo) =
-        get_monte_carlo_bitcoin_executions::<HONEST_COUNT, ADVERSARY_COUNT>(&timestamps);
-
-    // Create the scaled honest blocks initial vector
-    let mut scaled_bitcoin_honest_monte_carlo = bitcoin_honest_monte_carlo.clone();
-    // Create the honest progress empty vector
-    let mut bitcoin_honest_progress_monte_carlo =
-        vec![Vec::with_capacity(HONEST_COUNT + 1); monte_carlo];
-
-    // Create the adversary progress empty vector
-    let mut original_bitcoin_adversary_progress_monte_carlo =
-        vec![Vec::with_capacity(ADVERSARY_COUNT + 1); monte_carlo];
-    // Initialize the adversary progress initial vector
-    get_monte_carlo_progresses(
-        &mut original_bitcoin_adversary_progress_monte_carlo,
-        &bitcoin_adversary_monte_carlo,
-        0.,
+// The performance of the protocol at a single grid point, evaluated without a checkpoint
+pub fn evaluate_performance(
+    protocol: &dyn Protocol,
+    samples: Samples,
+    monte_carlo: usize,
+    epsilon: f64,
+    beta: f64,
+    g: f64,
+    gamma: f64,
+) -> Performance {
+    let mut checkpoint = Checkpoint::disabled(samples.seed);
+    evaluate_betas(
+        protocol,
+        samples,
+        monte_carlo,
+        epsilon,
+        &[beta],
+        g,
+        gamma,
+        &mut checkpoint,
     );
-    // Create the scaled adversary progress empty vector
-    let mut scaled_bitcoin_adversary_progress_monte_carlo =
-        original_bitcoin_adversary_progress_monte_carlo.clone();
+    checkpoint.get(protocol.name(), beta, g, gamma).unwrap()
+}
 
-    for &g in g_range.iter() {
-        println!("g: {}", g);
-        // Scale Bitcoin honest blocks
-        scale_monte_carlo_blocks(
-            &mut scaled_bitcoin_honest_monte_carlo,
-            &bitcoin_honest_monte_carlo,
-            g,
-            0.0,
-        );
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        // Get Bitcoin honest progress
-        get_monte_carlo_progresses(
-            &mut bitcoin_honest_progress_monte_carlo,
-            &scaled_bitcoin_honest_monte_carlo,
-            1.,
+    #[test]
+    fn test_full_grid() {
+        // Every cell keeps its own g and statistics, even when the adversary wins it
+        let samples = Samples::new(4, 256);
+        let mut checkpoint = Checkpoint::disabled(samples.seed);
+        let data = simulate_bitcoin(
+            samples,
+            256,
+            0.1,
+            vec![0.1, 0.45],
+            vec![1., 4.],
+            ReductionType::Full,
+            &mut checkpoint,
         );
-
-        for (beta_index, &beta) in beta_range.iter().enumerate() {
-            // Scale Bitcoin adversary progress
-            scale_monte_carlo_progresses(
-                &mut scaled_bitcoin_adversary_progress_monte_carlo,
-                &original_bitcoin_adversary_progress_monte_carlo,
-                g * beta / (1. - beta),
-                0.0,
-            );
-
-            let (
-                k,
-                f_work,
-                f_height,
-                max_work,
-                max_height,
-                adversary_max_work,
-                adversary_max_height,
-            ) = get_monte_carlo_performance(
-                &bitcoin_honest_progress_monte_carlo,
-                &scaled_bitcoin_adversary_progress_monte_carlo,
-                epsilon,
-            );
-            let bitcoin_latency = k / f_work;
-            if bitcoin_latency < bitcoin_data.latency[beta_index] {
-                bitcoin_data.latency[beta_index] = bitcoin_latency;
-                bitcoin_data.optimal_k[beta_index] = k;
-                bitcoin_data.optimal_g[beta_index] = g;
-                bitcoin_data.throughput[beta_index] = f_height;
-                bitcoin_data.max_work[beta_index] = max_work;
-                bitcoin_data.max_height[beta_index] = max_height;
-                bitcoin_data.adversary_max_work[beta_index] = adversary_max_work;
-                bitcoin_data.adversary_max_height[beta_index] = adversary_max_height;
-            }
+        for (index, (beta, g, _)) in get_full_grid(&[0.1, 0.45], &[1., 4.], &[0.])
+            .into_iter()
+            .enumerate()
+        {
+            assert_eq!(data.optimal_g[index], g);
+            assert!(data.throughput[index] > 0. && data.max_work[index] > 0.);
+            assert!(data.adversary_max_work[index] > 0.);
+            assert_eq!(data.latency[index] == INF, beta == 0.45);
         }
     }
-    bitcoin_data
-}
\ No newline at end of file
+}
@@ -0,0 +1,186 @@
+use std::sync::OnceLock;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::simulations::{ADVERSARY_COUNT, HONEST_COUNT};
+
+const BITS: usize = 32;
+
+// Every block of a sample draws its honest and adversary time and work from its own
+// dimensions
+pub const SOBOL_DIMENSIONS: usize = 4 * if HONEST_COUNT > ADVERSARY_COUNT {
+    HONEST_COUNT
+} else {
+    ADVERSARY_COUNT
+};
+
+// The initial direction numbers are drawn from a fixed seed, so every run uses the same
+// sequence. They are valid for any primitive polynomial, but unlike tabulated ones they are
+// not tuned for the uniformity of low-dimensional projections.
+const DIRECTION_SEED: u64 = 0x50b0_1000;
+
+// Multiplies two polynomials over GF(2) modulo `modulus`, of degree `degree`
+fn multiply_modulo(mut a: u32, mut b: u32, modulus: u32, degree: u32) -> u32 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 == 1 {
+            product ^= a;
+        }
+        b >>= 1;
+        a <<= 1;
+        if a >> degree & 1 == 1 {
+            a ^= modulus;
+        }
+    }
+    product
+}
+
+fn power_modulo(mut base: u32, mut exponent: u32, modulus: u32, degree: u32) -> u32 {
+    if base >> degree & 1 == 1 {
+        base ^= modulus;
+    }
+    let mut power = 1;
+    while exponent != 0 {
+        if exponent & 1 == 1 {
+            power = multiply_modulo(power, base, modulus, degree);
+        }
+        base = multiply_modulo(base, base, modulus, degree);
+        exponent >>= 1;
+    }
+    power
+}
+
+fn get_prime_factors(mut n: u32) -> Vec<u32> {
+    let mut factors = Vec::new();
+    let mut factor = 2;
+    while factor * factor <= n {
+        if n.is_multiple_of(factor) {
+            factors.push(factor);
+            while n.is_multiple_of(factor) {
+                n /= factor;
+            }
+        }
+        factor += 1;
+    }
+    if n > 1 {
+        factors.push(n);
+    }
+    factors
+}
+
+// A polynomial of degree d is primitive when x has order 2^d - 1 modulo it
+fn is_primitive(polynomial: u32, degree: u32) -> bool {
+    let order = (1 << degree) - 1;
+    power_modulo(2, order, polynomial, degree) == 1
+        && get_prime_factors(order)
+            .iter()
+            .all(|factor| power_modulo(2, order / factor, polynomial, degree) != 1)
+}
+
+// Primitive polynomials over GF(2) by increasing degree, with the bits of their coefficients
+// from x^degree down to 1
+fn get_primitive_polynomials(count: usize) -> Vec<(u32, u32)> {
+    let mut polynomials = Vec::with_capacity(count);
+    let mut degree = 1;
+    while polynomials.len() < count {
+        for polynomial in (1 << degree | 1..1 << (degree + 1)).step_by(2) {
+            if polynomials.len() < count && is_primitive(polynomial, degree) {
+                polynomials.push((polynomial, degree));
+            }
+        }
+        degree += 1;
+    }
+    polynomials
+}
+
+// The direction numbers of every dimension. The first dimension is the van der Corput
+// sequence, and each other one follows the recurrence of its primitive polynomial.
+fn get_directions() -> &'static [[u32; BITS]] {
+    static DIRECTIONS: OnceLock<Vec<[u32; BITS]>> = OnceLock::new();
+    DIRECTIONS.get_or_init(|| {
+        let mut rng = StdRng::seed_from_u64(DIRECTION_SEED);
+        let mut directions = vec![std::array::from_fn(|bit| 1 << (BITS - 1 - bit))];
+        for (polynomial, degree) in get_primitive_polynomials(SOBOL_DIMENSIONS - 1) {
+            let degree = degree as usize;
+            // The initial m_k are odd and below 2^k
+            let mut m = [0u64; BITS];
+            for (k, m_k) in m.iter_mut().enumerate().take(degree) {
+                *m_k = (rng.gen_range(0..1u64 << k) << 1) | 1;
+            }
+            for k in degree..BITS {
+                m[k] = m[k - degree] ^ (m[k - degree] << degree);
+                for j in 1..degree {
+                    if polynomial >> (degree - j) & 1 == 1 {
+                        m[k] ^= m[k - j] << j;
+                    }
+                }
+            }
+            directions.push(std::array::from_fn(|k| (m[k] << (BITS - 1 - k)) as u32));
+        }
+        directions
+    })
+}
+
+// The hash-based nested uniform (Owen) scrambling of Burley, Practical Hash-based Owen
+// Scrambling, 2020: a Laine-Karras permutation of the reversed bits, which flips each bit
+// depending only on the more significant ones
+fn scramble(x: u32, seed: u32) -> u32 {
+    let mut x = x.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x.reverse_bits()
+}
+
+// SplitMix64, to derive the scrambling of each dimension from the seed and the point
+pub(crate) fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+// The `dimension` coordinate of Sobol point `index`, scrambled by `seed`, as a uniform in
+// (0, 1). Each seed gives an independent randomization of the same low-discrepancy points.
+pub fn get_sobol_uniform(index: usize, dimension: usize, seed: u64) -> f64 {
+    let directions = &get_directions()[dimension];
+    let mut x = 0;
+    for (bit, direction) in directions.iter().enumerate() {
+        if index >> bit & 1 == 1 {
+            x ^= direction;
+        }
+    }
+    let x = scramble(x, mix(seed ^ mix(dimension as u64)) as u32);
+    (x as f64 + 0.5) / (1u64 << BITS) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_primitive_polynomials() {
+        // x + 1, x^2 + x + 1, x^3 + x + 1, x^3 + x^2 + 1, x^4 + x + 1, x^4 + x^3 + 1
+        assert_eq!(
+            get_primitive_polynomials(6),
+            vec![(3, 1), (7, 2), (11, 3), (13, 3), (19, 4), (25, 4)]
+        );
+        // x^4 + x^3 + x^2 + x + 1 is irreducible, but x has order 5 modulo it
+        assert!(!is_primitive(31, 4));
+    }
+
+    #[test]
+    fn test_stratification() {
+        // The first 2^m points of every dimension fall one in each interval of length 2^-m,
+        // which the scrambling preserves
+        let points = 64;
+        for dimension in [0, 1, 2, 100, SOBOL_DIMENSIONS - 1] {
+            let mut intervals: Vec<usize> = (0..points)
+                .map(|index| (get_sobol_uniform(index, dimension, 9) * points as f64) as usize)
+                .collect();
+            intervals.sort();
+            assert_eq!(intervals, (0..points).collect::<Vec<_>>());
+        }
+    }
+}
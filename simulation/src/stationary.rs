@@ -0,0 +1,298 @@
+use rayon::prelude::*;
+
+use crate::{
+    analysis::{get_monte_carlo_partial_performance, PartialPerformance, SUMMATION_BLOCK},
+    error::Error,
+    protocol::Protocol,
+    sampling::{sample_monte_carlo_offsets, Samples},
+    simulations::{start_protocol, ChunkExecutions},
+    types::Chain,
+};
+
+// Where the block holding the transaction is placed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    // The first honest progress at least this high
+    Height(usize),
+    // A height drawn per sample between burn_in and 2 burn_in
+    Random { burn_in: usize },
+}
+
+impl Target {
+    // The target height of every sample
+    fn get_heights(&self, samples: Samples) -> Vec<usize> {
+        match *self {
+            Target::Height(height) => vec![height; samples.len()],
+            Target::Random { burn_in } => sample_monte_carlo_offsets(samples, burn_in + 1)
+                .into_iter()
+                .map(|offset| burn_in + offset)
+                .collect(),
+        }
+    }
+}
+
+// The race for a transaction in the first honest progress at least `height` high, with both
+// progresses counted from the progress before it. By then, the adversary may have mined a
+// private chain from any earlier point of the honest chain, so it starts with the largest
+// lead any of them has: the adversary work less the honest work since that point. At height
+// 1 this is the race from genesis. None when the honest chain never reaches `height`.
+pub fn get_stationary_progresses(
+    honest_progresses: &[Chain],
+    adversary_progresses: &[Chain],
+    height: usize,
+) -> Option<(Vec<Chain>, Vec<Chain>)> {
+    let target = honest_progresses
+        .iter()
+        .position(|progress| progress.height >= height)?
+        .max(1);
+    let origin = honest_progresses[target - 1];
+
+    // The walk of the adversary work less the honest work, up to the origin
+    let mut honest_index = 0;
+    let mut adversary_index = 0;
+    let mut lowest: f64 = 0.;
+    loop {
+        let honest_next = honest_progresses.get(honest_index + 1);
+        let adversary_next = adversary_progresses
+            .get(adversary_index + 1)
+            .filter(|progress| progress.timestamp <= origin.timestamp);
+        match (honest_next, adversary_next) {
+            (Some(honest), Some(adversary)) if adversary.timestamp < honest.timestamp => {
+                adversary_index += 1
+            }
+            (Some(honest), _) if honest.timestamp <= origin.timestamp => honest_index += 1,
+            (_, Some(_)) => adversary_index += 1,
+            _ => break,
+        }
+        lowest = lowest
+            .min(adversary_progresses[adversary_index].work - honest_progresses[honest_index].work);
+    }
+    let adversary_origin = adversary_progresses[adversary_index];
+    let lead = adversary_origin.work - origin.work - lowest;
+
+    let shift = |progress: &Chain, base: &Chain, work: f64| Chain {
+        timestamp: progress.timestamp - origin.timestamp,
+        work: progress.work - base.work + work,
+        height: progress.height - base.height,
+        arrival_time: progress.arrival_time - origin.timestamp,
+    };
+    let honest = std::iter::once(Chain {
+        timestamp: 0.,
+        work: 0.,
+        height: 0,
+        arrival_time: 0.,
+    })
+    .chain(
+        honest_progresses[target..]
+            .iter()
+            .map(|progress| shift(progress, &origin, 0.)),
+    )
+    .collect();
+    let adversary = std::iter::once(Chain {
+        timestamp: 0.,
+        work: lead,
+        height: 0,
+        arrival_time: 0.,
+    })
+    .chain(
+        adversary_progresses[adversary_index + 1..]
+            .iter()
+            .map(|progress| shift(progress, &adversary_origin, lead)),
+    )
+    .collect();
+    Some((honest, adversary))
+}
+
+// Per beta, from genesis and from the target. The stationary values are over the samples
+// whose honest chain reaches the target, the others only being counted.
+pub struct StationaryData {
+    pub latency: Vec<f64>,
+    pub k: Vec<f64>,
+    pub growth_rate: Vec<f64>,
+    pub stationary_latency: Vec<f64>,
+    pub stationary_k: Vec<f64>,
+    pub stationary_growth_rate: Vec<f64>,
+    pub unreached: usize,
+}
+
+// Compares the latency of a transaction in the first honest block with that of one placed at
+// the target, on the same samples, at (g, gamma) for every beta. Fails when no honest chain
+// reaches the target.
+#[allow(clippy::too_many_arguments)]
+pub fn simulate_stationary(
+    protocol: &dyn Protocol,
+    samples: Samples,
+    monte_carlo: usize,
+    epsilon: f64,
+    beta_range: &[f64],
+    g: f64,
+    gamma: f64,
+    target: Target,
+) -> Result<StationaryData, Error> {
+    let gamma = start_protocol(protocol, gamma);
+    let mut partials = vec![PartialPerformance::default(); beta_range.len()];
+    let mut stationary_partials = partials.clone();
+    let mut unreached = 0;
+
+    for chunk in samples.chunks(SUMMATION_BLOCK) {
+        let mut executions = ChunkExecutions::sample_progresses(protocol, chunk, g, gamma);
+        let heights = target.get_heights(chunk);
+        // The honest progresses do not depend on beta
+        unreached += executions
+            .honest_progresses
+            .iter()
+            .zip(&heights)
+            .filter(|(honest_progresses, &height)| {
+                honest_progresses.last().unwrap().height < height
+            })
+            .count();
+
+        for ((partial, stationary_partial), &beta) in partials
+            .iter_mut()
+            .zip(stationary_partials.iter_mut())
+            .zip(beta_range)
+        {
+            executions.scale_adversary(beta, g, gamma);
+            let honest_progresses = &executions.honest_progresses;
+            let scaled_adversary_progresses = &executions.scaled_adversary_progresses;
+            partial.extend(get_monte_carlo_partial_performance(
+                honest_progresses,
+                scaled_adversary_progresses,
+                None,
+                None,
+            ));
+            partial.prune(monte_carlo, epsilon);
+
+            let (stationary_honest_progresses, stationary_adversary_progresses): (Vec<_>, Vec<_>) =
+                honest_progresses
+                    .par_iter()
+                    .zip(scaled_adversary_progresses.par_iter())
+                    .zip(heights.par_iter())
+                    .filter_map(|((honest_progresses, adversary_progresses), &height)| {
+                        get_stationary_progresses(honest_progresses, adversary_progresses, height)
+                    })
+                    .unzip();
+            if stationary_honest_progresses.is_empty() {
+                continue;
+            }
+            stationary_partial.extend(get_monte_carlo_partial_performance(
+                &stationary_honest_progresses,
+                &stationary_adversary_progresses,
+                None,
+                None,
+            ));
+            stationary_partial.prune(monte_carlo, epsilon);
+        }
+    }
+
+    if unreached == samples.len() {
+        return Err(Error::InvalidParameter {
+            field: "target".to_string(),
+            message: format!("{:?} is beyond the honest chain of every sample", target),
+        });
+    }
+    if unreached > 0 {
+        println!(
+            "{} of {} samples never reach the target and are left out",
+            unreached,
+            samples.len()
+        );
+    }
+
+    let mut stationary_data = StationaryData {
+        latency: Vec::new(),
+        k: Vec::new(),
+        growth_rate: Vec::new(),
+        stationary_latency: Vec::new(),
+        stationary_k: Vec::new(),
+        stationary_growth_rate: Vec::new(),
+        unreached,
+    };
+    for (partial, stationary_partial) in partials.iter().zip(&stationary_partials) {
+        let performance = partial.get_performance(epsilon);
+        stationary_data.k.push(performance.0);
+        stationary_data.growth_rate.push(performance.1);
+        stationary_data.latency.push(performance.0 / performance.1);
+        let performance = stationary_partial.get_performance(epsilon);
+        stationary_data.stationary_k.push(performance.0);
+        stationary_data.stationary_growth_rate.push(performance.1);
+        stationary_data
+            .stationary_latency
+            .push(performance.0 / performance.1);
+    }
+    Ok(stationary_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        protocol::{Poem, Protocol},
+        types::chain,
+    };
+
+    #[test]
+    fn test_genesis() {
+        // The first honest progress is the race from genesis
+        let (honest_blocks, adversary_blocks) = Poem.sample_executions(Samples::new(4, 2));
+        let mut honest_progresses = vec![Vec::new(); 2];
+        let mut adversary_progresses = vec![Vec::new(); 2];
+        Poem.get_honest_progresses(&mut honest_progresses, &honest_blocks);
+        Poem.get_adversary_progresses(&mut adversary_progresses, &adversary_blocks);
+        for (honest, adversary) in honest_progresses.iter().zip(&adversary_progresses) {
+            let (stationary_honest, stationary_adversary) =
+                get_stationary_progresses(honest, adversary, 1).unwrap();
+            assert_eq!(&stationary_honest, honest);
+            assert_eq!(&stationary_adversary, adversary);
+        }
+    }
+
+    #[test]
+    fn test_lead() {
+        // The best private chain forks after the honest block at 0.5: by the origin at 4, the
+        // adversary has mined 3 blocks since and the honest parties 2, so it leads by 1
+        let honest = [
+            chain(0., 0., 0),
+            chain(0.5, 1., 1),
+            chain(3., 2., 2),
+            chain(4., 3., 3),
+            chain(6., 4., 4),
+        ];
+        let adversary = [
+            chain(0., 0., 0),
+            chain(1., 1., 1),
+            chain(2.5, 2., 2),
+            chain(3.5, 3., 3),
+            chain(5., 4., 4),
+        ];
+        let (stationary_honest, stationary_adversary) =
+            get_stationary_progresses(&honest, &adversary, 4).unwrap();
+        assert_eq!(stationary_honest, vec![chain(0., 0., 0), chain(2., 1., 1)]);
+        assert_eq!(
+            stationary_adversary,
+            vec![chain(0., 1., 0), chain(1., 2., 1)]
+        );
+        // A target beyond the honest chain is not a race at all
+        assert_eq!(get_stationary_progresses(&honest, &adversary, 5), None);
+    }
+
+    #[test]
+    fn test_unreached() {
+        let samples = Samples::new(4, 8);
+        let result = simulate_stationary(
+            &Poem,
+            samples,
+            8,
+            0.1,
+            &[0.25],
+            1.,
+            0.,
+            Target::Height(usize::MAX),
+        );
+        assert!(matches!(result, Err(Error::InvalidParameter { .. })));
+        let stationary_data =
+            simulate_stationary(&Poem, samples, 8, 0.1, &[0.25], 1., 0., Target::Height(10))
+                .unwrap();
+        assert_eq!(stationary_data.unreached, 0);
+    }
+}
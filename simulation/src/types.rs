@@ -13,3 +13,23 @@ pub struct Block {
     pub timestamp: f64,
     pub work: f64,
 }
+
+// A progress that arrives as soon as it is mined, for tests
+#[cfg(test)]
+pub fn chain(timestamp: f64, work: f64, height: usize) -> Chain {
+    Chain {
+        timestamp,
+        work,
+        height,
+        arrival_time: timestamp,
+    }
+}
+
+// A block of unit work, for tests
+#[cfg(test)]
+pub fn block(timestamp: f64) -> Block {
+    Block {
+        timestamp,
+        work: 1.,
+    }
+}
@@ -0,0 +1,244 @@
+use crate::{
+    protocol::{Bitcoin, Poem},
+    sampling::{Samples, VarianceReduction},
+    simulations::evaluate_performance,
+};
+
+// The variance reductions compared by the variance experiment, each against the first
+pub const VARIANCE_REDUCTIONS: [(&str, VarianceReduction); 5] = [
+    (
+        "none",
+        VarianceReduction {
+            common_random_numbers: false,
+            antithetic: false,
+            control_variates: false,
+            quasi_monte_carlo: false,
+        },
+    ),
+    (
+        "common-random-numbers",
+        VarianceReduction {
+            common_random_numbers: true,
+            antithetic: false,
+            control_variates: false,
+            quasi_monte_carlo: false,
+        },
+    ),
+    (
+        "antithetic",
+        VarianceReduction {
+            common_random_numbers: true,
+            antithetic: true,
+            control_variates: false,
+            quasi_monte_carlo: false,
+        },
+    ),
+    (
+        "control-variates",
+        VarianceReduction {
+            common_random_numbers: true,
+            antithetic: false,
+            control_variates: true,
+            quasi_monte_carlo: false,
+        },
+    ),
+    (
+        "all",
+        VarianceReduction {
+            common_random_numbers: true,
+            antithetic: true,
+            control_variates: true,
+            quasi_monte_carlo: false,
+        },
+    ),
+];
+
+// The mean and variance of an estimate over the replications, and how many times smaller
+// its variance is than without variance reduction
+#[derive(Debug, Clone, Default)]
+pub struct Estimate {
+    pub mean: Vec<f64>,
+    pub variance: Vec<f64>,
+    pub variance_reduction: Vec<f64>,
+}
+
+// One entry per variance reduction of VARIANCE_REDUCTIONS
+#[derive(Debug, Clone, Default)]
+pub struct VarianceData {
+    pub poem_latency: Estimate,
+    pub bitcoin_latency: Estimate,
+    pub latency_difference: Estimate,
+}
+
+// Mean and unbiased variance
+fn get_mean_and_variance(values: &[f64]) -> (f64, f64) {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let variance = values.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.);
+    (mean, variance)
+}
+
+impl Estimate {
+    fn add(&mut self, values: &[f64]) {
+        let (mean, variance) = get_mean_and_variance(values);
+        self.mean.push(mean);
+        self.variance.push(variance);
+        self.variance_reduction.push(self.variance[0] / variance);
+    }
+}
+
+// Estimates the PoEM and Bitcoin latencies at (beta, g, gamma) `replications` times under
+// each variance reduction, replication r using the seed `seed + r`. The variance of the
+// estimates across replications measures the precision each reduction gives for the same
+// number of samples.
+pub fn simulate_variance(
+    seed: u64,
+    monte_carlo: usize,
+    epsilon: f64,
+    beta: f64,
+    g: f64,
+    gamma: f64,
+    replications: usize,
+) -> VarianceData {
+    let mut variance_data = VarianceData::default();
+    for (name, variance_reduction) in VARIANCE_REDUCTIONS {
+        println!("Variance reduction: {}", name);
+        let mut poem_latencies = Vec::with_capacity(replications);
+        let mut bitcoin_latencies = Vec::with_capacity(replications);
+        for replication in 0..replications {
+            let samples = Samples {
+                variance_reduction,
+                ..Samples::new(seed.wrapping_add(replication as u64), monte_carlo)
+            };
+            let (k, f_work, ..) =
+                evaluate_performance(&Poem, samples, monte_carlo, epsilon, beta, g, gamma);
+            poem_latencies.push(k / f_work);
+            let (k, f_work, ..) =
+                evaluate_performance(&Bitcoin, samples, monte_carlo, epsilon, beta, g, 0.0);
+            bitcoin_latencies.push(k / f_work);
+        }
+        let differences: Vec<f64> = poem_latencies
+            .iter()
+            .zip(&bitcoin_latencies)
+            .map(|(poem, bitcoin)| poem - bitcoin)
+            .collect();
+        variance_data.poem_latency.add(&poem_latencies);
+        variance_data.bitcoin_latency.add(&bitcoin_latencies);
+        variance_data.latency_difference.add(&differences);
+    }
+    variance_data
+}
+
+// The samplers compared by the convergence experiment, each against the first
+pub const SAMPLERS: [&str; 2] = ["pseudo-random", "sobol"];
+
+// One entry per number of samples, each holding an estimate per sampler of SAMPLERS
+#[derive(Debug, Clone, Default)]
+pub struct ConvergenceData {
+    pub monte_carlo: Vec<usize>,
+    pub poem_k: Vec<Estimate>,
+    pub bitcoin_k: Vec<Estimate>,
+    // Per sampler, the exponent r of the fitted standard deviation c * n^r of k*
+    pub poem_rate: Vec<f64>,
+    pub bitcoin_rate: Vec<f64>,
+}
+
+// The least-squares slope of the log standard deviation over the log number of samples,
+// -0.5 at the Monte Carlo rate
+fn get_convergence_rate(monte_carlo: &[usize], estimates: &[Estimate], sampler: usize) -> f64 {
+    let points: Vec<(f64, f64)> = monte_carlo
+        .iter()
+        .zip(estimates)
+        .map(|(&n, estimate)| ((n as f64).ln(), 0.5 * estimate.variance[sampler].ln()))
+        .collect();
+    let count = points.len() as f64;
+    let x_mean = points.iter().map(|point| point.0).sum::<f64>() / count;
+    let y_mean = points.iter().map(|point| point.1).sum::<f64>() / count;
+    let covariance: f64 = points
+        .iter()
+        .map(|(x, y)| (x - x_mean) * (y - y_mean))
+        .sum();
+    let variance: f64 = points.iter().map(|(x, _)| (x - x_mean).powi(2)).sum();
+    covariance / variance
+}
+
+// Estimates the PoEM and Bitcoin k* at (beta, g, gamma) `replications` times for each number
+// of samples, with pseudo-random samples and with scrambled Sobol points. Replication r uses
+// the seed `seed + r`, which also draws the scrambling, so the spread of the estimates across
+// replications measures the error of each sampler.
+pub fn simulate_convergence(
+    seed: u64,
+    monte_carlo_range: &[usize],
+    epsilon: f64,
+    beta: f64,
+    g: f64,
+    gamma: f64,
+    replications: usize,
+) -> ConvergenceData {
+    let mut convergence_data = ConvergenceData {
+        monte_carlo: monte_carlo_range.to_vec(),
+        ..Default::default()
+    };
+    for &monte_carlo in monte_carlo_range {
+        println!("Samples: {}", monte_carlo);
+        let mut poem_k = Estimate::default();
+        let mut bitcoin_k = Estimate::default();
+        for (sampler, name) in SAMPLERS.iter().enumerate() {
+            println!(" - sampler: {}", name);
+            let variance_reduction = VarianceReduction {
+                quasi_monte_carlo: sampler == 1,
+                ..VarianceReduction::default()
+            };
+            let mut poem_ks = Vec::with_capacity(replications);
+            let mut bitcoin_ks = Vec::with_capacity(replications);
+            for replication in 0..replications {
+                let samples = Samples {
+                    variance_reduction,
+                    ..Samples::new(seed.wrapping_add(replication as u64), monte_carlo)
+                };
+                poem_ks.push(
+                    evaluate_performance(&Poem, samples, monte_carlo, epsilon, beta, g, gamma).0,
+                );
+                bitcoin_ks.push(
+                    evaluate_performance(&Bitcoin, samples, monte_carlo, epsilon, beta, g, 0.0).0,
+                );
+            }
+            poem_k.add(&poem_ks);
+            bitcoin_k.add(&bitcoin_ks);
+        }
+        convergence_data.poem_k.push(poem_k);
+        convergence_data.bitcoin_k.push(bitcoin_k);
+    }
+    for sampler in 0..SAMPLERS.len() {
+        convergence_data.poem_rate.push(get_convergence_rate(
+            monte_carlo_range,
+            &convergence_data.poem_k,
+            sampler,
+        ));
+        convergence_data.bitcoin_rate.push(get_convergence_rate(
+            monte_carlo_range,
+            &convergence_data.bitcoin_k,
+            sampler,
+        ));
+    }
+    convergence_data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_convergence_rate() {
+        // Standard deviations of 1 / sqrt(n) converge at the Monte Carlo rate
+        let monte_carlo = [256, 1024, 4096];
+        let estimates: Vec<Estimate> = monte_carlo
+            .iter()
+            .map(|&n| Estimate {
+                variance: vec![1.0 / n as f64, 1.0 / (n * n) as f64],
+                ..Default::default()
+            })
+            .collect();
+        assert!((get_convergence_rate(&monte_carlo, &estimates, 0) + 0.5).abs() < 1e-12);
+        assert!((get_convergence_rate(&monte_carlo, &estimates, 1) + 1.0).abs() < 1e-12);
+    }
+}